@@ -0,0 +1,3 @@
+drop table download_queue_file;
+drop table download_queue_chunk;
+drop table download_queue;
\ No newline at end of file
@@ -0,0 +1,36 @@
+create table download_queue
+(
+    id          TEXT    not null,
+    item_type   TEXT    not null,
+    label       TEXT    not null,
+    target      TEXT    default '' not null,
+    asset_info  TEXT    default '' not null,
+    actions     TEXT    default '[]' not null,
+    total_size  BIGINT  default 0 not null,
+    total_files BIGINT  default 0 not null,
+    added_at    TEXT    not null,
+    constraint download_queue_pk
+        unique (id)
+);
+
+create table download_queue_chunk
+(
+    download TEXT,
+    guid     TEXT,
+    constraint download_queue_chunk_pk
+        unique (download, guid)
+);
+
+create index download_queue_chunk_download_index
+    on download_queue_chunk (download);
+
+create table download_queue_file
+(
+    download TEXT,
+    name     TEXT,
+    constraint download_queue_file_pk
+        unique (download, name)
+);
+
+create index download_queue_file_download_index
+    on download_queue_file (download);
@@ -0,0 +1 @@
+drop table download_history;
\ No newline at end of file
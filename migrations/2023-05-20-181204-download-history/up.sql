@@ -0,0 +1,24 @@
+create table download_history
+(
+    id            INTEGER not null
+        constraint download_history_pk
+            primary key autoincrement,
+    item_id       TEXT    not null,
+    item_type     TEXT    not null,
+    label         TEXT    default '' not null,
+    release       TEXT    default '' not null,
+    size          BIGINT  default 0 not null,
+    started_at    TEXT    not null,
+    finished_at   TEXT    not null,
+    duration      BIGINT  default 0 not null,
+    average_speed BIGINT  default 0 not null,
+    target        TEXT    default '' not null,
+    targets       TEXT    default '[]' not null,
+    result        TEXT    not null,
+    error         TEXT    default '' not null,
+    asset_info    TEXT    default '' not null,
+    actions       TEXT    default '[]' not null
+);
+
+create index download_history_finished_at_index
+    on download_history (finished_at);
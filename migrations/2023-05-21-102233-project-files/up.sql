@@ -0,0 +1,15 @@
+create table project_files
+(
+    project  TEXT not null,
+    path     TEXT not null,
+    release  TEXT not null,
+    label    TEXT default '' not null,
+    backup   TEXT default '' not null,
+    replaced BOOLEAN default 0 not null,
+    added_at TEXT not null,
+    constraint project_files_pk
+        primary key (project, path)
+);
+
+create index project_files_release_index
+    on project_files (project, release);
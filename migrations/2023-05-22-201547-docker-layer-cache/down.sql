@@ -0,0 +1 @@
+drop table docker_layer_cache;
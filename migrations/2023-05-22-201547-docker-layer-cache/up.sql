@@ -0,0 +1,8 @@
+create table docker_layer_cache
+(
+    digest    TEXT   not null
+        constraint docker_layer_cache_pk
+            primary key,
+    size      BIGINT default 0 not null,
+    last_used TEXT   not null
+);
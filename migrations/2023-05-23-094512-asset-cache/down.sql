@@ -0,0 +1,5 @@
+drop table asset_search;
+drop table asset_key_image;
+drop table asset_category;
+drop table asset_release;
+drop table asset_cache;
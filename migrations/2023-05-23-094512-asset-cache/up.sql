@@ -0,0 +1,56 @@
+create table asset_cache
+(
+    id         TEXT not null
+        constraint asset_cache_pk
+            primary key,
+    asset_id   TEXT not null,
+    title      TEXT default '' not null,
+    epic_asset TEXT not null,
+    asset_info TEXT default '' not null,
+    updated_at TEXT not null
+);
+
+create index asset_cache_asset_id_index
+    on asset_cache (asset_id);
+
+create table asset_release
+(
+    asset         TEXT not null,
+    id            TEXT not null,
+    app_id        TEXT default '' not null,
+    version_title TEXT default '' not null,
+    date_added    TEXT default '' not null,
+    constraint asset_release_pk
+        primary key (asset, id)
+);
+
+create table asset_category
+(
+    asset TEXT not null,
+    path  TEXT not null,
+    constraint asset_category_pk
+        primary key (asset, path)
+);
+
+create index asset_category_path_index
+    on asset_category (path);
+
+create table asset_key_image
+(
+    asset      TEXT not null,
+    image_type TEXT not null,
+    url        TEXT not null,
+    md5        TEXT default '' not null,
+    constraint asset_key_image_pk
+        primary key (asset, url)
+);
+
+create virtual table asset_search using fts5
+(
+    id unindexed,
+    title,
+    description,
+    seller,
+    technical_details,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
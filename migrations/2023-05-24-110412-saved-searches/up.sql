@@ -0,0 +1,8 @@
+create table saved_search
+(
+    name     TEXT not null
+        constraint saved_search_pk
+            primary key,
+    query    TEXT not null,
+    added_at TEXT not null
+);
@@ -0,0 +1,3 @@
+drop table collection_asset;
+drop table collection;
+drop table asset_tag;
@@ -0,0 +1,26 @@
+create table asset_tag
+(
+    asset TEXT not null,
+    tag   TEXT not null,
+    constraint asset_tag_pk
+        primary key (asset, tag)
+);
+
+create index asset_tag_tag_index
+    on asset_tag (tag);
+
+create table collection
+(
+    name     TEXT not null
+        constraint collection_pk
+            primary key,
+    added_at TEXT not null
+);
+
+create table collection_asset
+(
+    collection TEXT not null,
+    asset      TEXT not null,
+    constraint collection_asset_pk
+        primary key (collection, asset)
+);
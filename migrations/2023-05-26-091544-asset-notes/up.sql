@@ -0,0 +1,9 @@
+create table asset_note
+(
+    asset      TEXT not null
+        constraint asset_note_pk
+            primary key,
+    notes      TEXT    default '' not null,
+    rating     INTEGER default 0 not null,
+    updated_at TEXT not null
+);
@@ -1,4 +1,5 @@
 use crate::config;
+use crate::ui::widgets::download_manager::notifications::Notifications;
 use crate::window::EpicAssetManagerWindow;
 use gio::ApplicationFlags;
 use glib::clone;
@@ -210,6 +211,30 @@ impl EpicAssetManager {
             })
         );
 
+        // Download notification actions
+        let open_folder =
+            gio::SimpleAction::new("open-download-folder", Some(glib::VariantTy::STRING));
+        open_folder.connect_activate(|_, parameter| {
+            if let Some(path) = parameter.and_then(glib::Variant::get::<String>) {
+                glib::MainContext::default().spawn_local(async move {
+                    crate::tools::open_directory(&path).await;
+                });
+            }
+        });
+        self.add_action(&open_folder);
+
+        let retry = gio::SimpleAction::new("retry-download", Some(glib::VariantTy::STRING));
+        retry.connect_activate(clone!(@weak self as app => move |_, parameter| {
+            if let Some(id) = parameter.and_then(glib::Variant::get::<String>) {
+                app.main_window().present();
+                app.main_window()
+                    .imp()
+                    .download_manager
+                    .retry_from_notification(&id);
+            }
+        }));
+        self.add_action(&retry);
+
         let level = self_.settings.int("log-level");
         crate::ui::widgets::preferences::PreferencesWindow::set_log_level(level);
     }
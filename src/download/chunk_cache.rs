@@ -0,0 +1,157 @@
+use log::{debug, error};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Decoded chunk data shared by the threads assembling files, so a chunk used by several files is read and decompressed only once
+#[derive(Debug, Default)]
+pub struct ChunkCache {
+    state: Mutex<CacheState>,
+    loaded: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    /// Maximum size of the decoded data kept in memory in bytes
+    capacity: usize,
+    size: usize,
+    entries: HashMap<PathBuf, Arc<Vec<u8>>>,
+    /// Least recently used chunk first
+    order: VecDeque<PathBuf>,
+    /// Chunks being decoded by another thread right now
+    loading: HashSet<PathBuf>,
+}
+
+impl CacheState {
+    fn touch(&mut self, path: &Path) {
+        if let Some(pos) = self.order.iter().position(|p| p.eq(path)) {
+            if let Some(p) = self.order.remove(pos) {
+                self.order.push_back(p);
+            }
+        }
+    }
+
+    fn remove(&mut self, path: &Path) {
+        if let Some(data) = self.entries.remove(path) {
+            self.size -= data.len();
+            self.order.retain(|p| !p.eq(path));
+        }
+    }
+
+    fn insert(&mut self, path: PathBuf, data: Arc<Vec<u8>>) {
+        if data.len() > self.capacity {
+            return;
+        }
+        while self.size + data.len() > self.capacity {
+            let Some(oldest) = self.order.pop_front() else { break };
+            if let Some(d) = self.entries.remove(&oldest) {
+                self.size -= d.len();
+            }
+        }
+        self.size += data.len();
+        self.order.push_back(path.clone());
+        self.entries.insert(path, data);
+    }
+}
+
+impl ChunkCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(CacheState {
+                capacity,
+                ..CacheState::default()
+            }),
+            loaded: Condvar::new(),
+        }
+    }
+
+    /// Change the cache size in bytes, evicting chunks that no longer fit
+    pub fn set_capacity(&self, capacity: usize) {
+        if let Ok(mut state) = self.state.lock() {
+            state.capacity = capacity;
+            while state.size > state.capacity {
+                let Some(oldest) = state.order.pop_front() else { break };
+                if let Some(d) = state.entries.remove(&oldest) {
+                    state.size -= d.len();
+                }
+            }
+        }
+    }
+
+    /// Get the uncompressed data of the chunk stored at `path`, decoding it if it is not cached
+    pub fn get(&self, path: &Path) -> Option<Arc<Vec<u8>>> {
+        {
+            let Ok(mut state) = self.state.lock() else {
+                return decode_chunk(path).map(Arc::new);
+            };
+            loop {
+                if let Some(data) = state.entries.get(path).cloned() {
+                    state.touch(path);
+                    return Some(data);
+                }
+                if !state.loading.contains(path) {
+                    break;
+                }
+                // Another thread is decoding the same chunk, wait for it instead of doing the work twice
+                state = match self.loaded.wait(state) {
+                    Ok(s) => s,
+                    Err(_) => return decode_chunk(path).map(Arc::new),
+                };
+            }
+            state.loading.insert(path.to_path_buf());
+        }
+        let data = decode_chunk(path).map(Arc::new);
+        if let Ok(mut state) = self.state.lock() {
+            state.loading.remove(path);
+            if let Some(d) = &data {
+                state.insert(path.to_path_buf(), d.clone());
+            }
+        }
+        self.loaded.notify_all();
+        data
+    }
+
+    /// Drop a chunk whose file was removed or replaced
+    pub fn forget(&self, path: &Path) {
+        if let Ok(mut state) = self.state.lock() {
+            state.remove(path);
+        }
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            debug!("Clearing {} cached chunks", state.entries.len());
+            state.entries.clear();
+            state.order.clear();
+            state.size = 0;
+        }
+    }
+}
+
+fn decode_chunk(path: &Path) -> Option<Vec<u8>> {
+    let mut f = match File::open(path) {
+        Ok(f) => f,
+        Err(e) => {
+            error!("Error opening the chunk file {:?}: {:?}", path, e);
+            return None;
+        }
+    };
+    let mut buffer: Vec<u8> = Vec::new();
+    if let Err(e) = f.read_to_end(&mut buffer) {
+        error!("Unable to read chunk {:?}: {:?}", path, e);
+        return None;
+    }
+    let Some(ch) = egs_api::api::types::chunk::Chunk::from_vec(buffer) else {
+        error!("Failed to parse chunk from file: {:?}", path);
+        return None;
+    };
+    let size = ch
+        .uncompressed_size
+        .map_or(ch.data.len(), |s| s as usize)
+        .min(ch.data.len());
+    let mut data = ch.data;
+    data.truncate(size);
+    Some(data)
+}
@@ -0,0 +1,38 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Reason a download can not continue without user action
+#[derive(Debug, Clone)]
+pub enum DownloadError {
+    /// Reading or writing a file failed
+    Io { path: PathBuf, message: String },
+    /// The server could not be reached or refused the request
+    Http(String),
+    /// Downloaded data does not match the manifest
+    Corrupted(String),
+    /// Unpacking a downloaded archive failed
+    Extraction(String),
+    /// A directory the download is saved to is missing from the preferences
+    NotConfigured(String),
+}
+
+impl DownloadError {
+    pub fn io(path: &Path, err: &std::io::Error) -> Self {
+        Self::Io {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, message } => write!(f, "{} ({})", message, path.display()),
+            Self::Http(message) => write!(f, "Network error: {message}"),
+            Self::Corrupted(message) => write!(f, "{message} is corrupted"),
+            Self::Extraction(message) => write!(f, "Unable to extract: {message}"),
+            Self::NotConfigured(message) => write!(f, "{message} is not configured"),
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use log::debug;
+use reqwest::blocking::Response;
+use reqwest::{StatusCode, Url};
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+
+/// Response to a request continuing a partial download
+pub enum RangeResponse {
+    /// Server sends only the missing part, append it to the file
+    Resumed(Response),
+    /// Server ignored the range and sends everything, the file has to be written from the start
+    Restarted(Response),
+    /// Nothing is missing
+    Complete,
+}
+
+impl RangeResponse {
+    /// Open the target file for writing the response body
+    pub fn open_target(&self, path: &Path) -> std::io::Result<File> {
+        match self {
+            Self::Resumed(_) | Self::Complete => OpenOptions::new().append(true).open(path),
+            Self::Restarted(_) => File::create(path),
+        }
+    }
+}
+
+/// Request `link` continuing from `offset` bytes already on disk.
+/// `expected` is the full size of the file when the caller knows it.
+pub fn get_from_offset(
+    link: &Url,
+    offset: u64,
+    expected: Option<u64>,
+) -> reqwest::Result<RangeResponse> {
+    let client = reqwest::blocking::Client::new();
+    let mut request = client.get(link.clone());
+    if offset > 0 {
+        debug!("Resuming {} from byte {}", link, offset);
+        request = request.header(reqwest::header::RANGE, format!("bytes={offset}-"));
+    }
+    let response = request.send()?;
+    if offset > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        // The range starts at or past the end, which only means done if the sizes agree
+        match content_length(&response).or(expected) {
+            Some(total) if total == offset => return Ok(RangeResponse::Complete),
+            total => {
+                debug!(
+                    "Local file of {} has {} bytes, expected {:?}, starting from the beginning",
+                    link, offset, total
+                );
+                return Ok(RangeResponse::Restarted(
+                    client.get(link.clone()).send()?.error_for_status()?,
+                ));
+            }
+        }
+    }
+    let response = response.error_for_status()?;
+    if offset > 0 && response.status() == StatusCode::PARTIAL_CONTENT {
+        Ok(RangeResponse::Resumed(response))
+    } else {
+        if offset > 0 {
+            debug!(
+                "Range not supported by {}, starting from the beginning",
+                link
+            );
+        }
+        Ok(RangeResponse::Restarted(response))
+    }
+}
+
+/// Full size of the resource from the `Content-Range` header, as in `bytes */1234`
+fn content_length(response: &Response) -> Option<u64> {
+    response
+        .headers()
+        .get(reqwest::header::CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .rsplit_once('/')?
+        .1
+        .parse()
+        .ok()
+}
+
+/// Size of the partially downloaded file, 0 if there is none
+pub fn partial_size(path: &Path) -> u64 {
+    std::fs::metadata(path).map_or(0, |m| m.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::sync::mpsc::Receiver;
+
+    /// Serve one canned response per connection, reporting the `Range` header of every request
+    fn serve(responses: Vec<String>) -> (Url, Receiver<Option<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/file", listener.local_addr().unwrap())).unwrap();
+        let (send, recv) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut range = None;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("range") {
+                            range = Some(value.trim().to_string());
+                        }
+                    }
+                }
+                send.send(range).ok();
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (url, recv)
+    }
+
+    fn response(status: &str, headers: &[&str], body: &str) -> String {
+        let mut r = format!("HTTP/1.1 {status}\r\nContent-Length: {}\r\n", body.len());
+        for h in headers {
+            r.push_str(h);
+            r.push_str("\r\n");
+        }
+        r.push_str("Connection: close\r\n\r\n");
+        r.push_str(body);
+        r
+    }
+
+    fn partial_file(name: &str, content: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("egl-http-{}-{}", std::process::id(), name));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    /// Write the response body the way the downloaders do and return the resulting file
+    fn finish(response: RangeResponse, path: &Path) -> String {
+        let mut file = response.open_target(path).unwrap();
+        if let RangeResponse::Resumed(mut r) | RangeResponse::Restarted(mut r) = response {
+            let mut body = Vec::new();
+            r.read_to_end(&mut body).unwrap();
+            file.write_all(&body).unwrap();
+        }
+        let content = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        content
+    }
+
+    #[test]
+    fn partial_content_is_appended() {
+        let (url, ranges) = serve(vec![response(
+            "206 Partial Content",
+            &["Content-Range: bytes 5-10/11"],
+            " world",
+        )]);
+        let path = partial_file("resume", "hello");
+        let r = get_from_offset(&url, 5, Some(11)).unwrap();
+        assert!(matches!(r, RangeResponse::Resumed(_)));
+        assert_eq!(ranges.recv().unwrap().as_deref(), Some("bytes=5-"));
+        assert_eq!(finish(r, &path), "hello world");
+    }
+
+    #[test]
+    fn ignored_range_restarts_from_zero() {
+        let (url, _) = serve(vec![response("200 OK", &[], "hello world")]);
+        let path = partial_file("ignored", "hello");
+        let r = get_from_offset(&url, 5, Some(11)).unwrap();
+        assert!(matches!(r, RangeResponse::Restarted(_)));
+        assert_eq!(finish(r, &path), "hello world");
+    }
+
+    #[test]
+    fn no_range_is_sent_without_partial_file() {
+        let (url, ranges) = serve(vec![response("200 OK", &[], "hello world")]);
+        let r = get_from_offset(&url, 0, None).unwrap();
+        assert!(matches!(r, RangeResponse::Restarted(_)));
+        assert_eq!(ranges.recv().unwrap(), None);
+    }
+
+    #[test]
+    fn not_satisfiable_range_of_complete_file() {
+        let (url, _) = serve(vec![response(
+            "416 Range Not Satisfiable",
+            &["Content-Range: bytes */11"],
+            "",
+        )]);
+        let path = partial_file("complete", "hello world");
+        let r = get_from_offset(&url, 11, None).unwrap();
+        assert!(matches!(r, RangeResponse::Complete));
+        assert_eq!(finish(r, &path), "hello world");
+    }
+
+    #[test]
+    fn not_satisfiable_range_uses_expected_size() {
+        let (url, _) = serve(vec![response("416 Range Not Satisfiable", &[], "")]);
+        let r = get_from_offset(&url, 11, Some(11)).unwrap();
+        assert!(matches!(r, RangeResponse::Complete));
+    }
+
+    #[test]
+    fn not_satisfiable_range_of_larger_file_restarts() {
+        let (url, ranges) = serve(vec![
+            response(
+                "416 Range Not Satisfiable",
+                &["Content-Range: bytes */11"],
+                "",
+            ),
+            response("200 OK", &[], "hello world"),
+        ]);
+        let path = partial_file("larger", "hello world, and more");
+        let r = get_from_offset(&url, 21, Some(11)).unwrap();
+        assert!(matches!(r, RangeResponse::Restarted(_)));
+        assert_eq!(ranges.recv().unwrap().as_deref(), Some("bytes=21-"));
+        assert_eq!(ranges.recv().unwrap(), None);
+        assert_eq!(finish(r, &path), "hello world");
+    }
+
+    #[test]
+    fn not_satisfiable_range_of_unknown_size_restarts() {
+        let (url, _) = serve(vec![
+            response("416 Range Not Satisfiable", &[], ""),
+            response("200 OK", &[], "hello world"),
+        ]);
+        let path = partial_file("unknown", "hello world");
+        let r = get_from_offset(&url, 11, None).unwrap();
+        assert!(matches!(r, RangeResponse::Restarted(_)));
+        assert_eq!(finish(r, &path), "hello world");
+    }
+
+    #[test]
+    fn server_errors_are_reported() {
+        let (url, _) = serve(vec![response("404 Not Found", &[], "")]);
+        assert!(get_from_offset(&url, 5, None).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Token bucket shared by all download threads to cap the total download speed
+#[derive(Debug)]
+pub struct RateLimiter {
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Bytes per second, 0 means unlimited
+    rate: u64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl RateLimiter {
+    pub fn new(rate: u64) -> Self {
+        Self {
+            bucket: Mutex::new(Bucket {
+                rate,
+                tokens: 0.0,
+                last: Instant::now(),
+            }),
+        }
+    }
+
+    /// Change the speed limit in bytes per second, 0 disables the limit
+    pub fn set_rate(&self, rate: u64) {
+        if let Ok(mut bucket) = self.bucket.lock() {
+            bucket.rate = rate;
+            bucket.tokens = 0.0;
+            bucket.last = Instant::now();
+        }
+    }
+
+    pub fn rate(&self) -> u64 {
+        self.bucket.lock().map_or(0, |b| b.rate)
+    }
+
+    /// Block the calling thread until `amount` bytes can be transferred
+    pub fn acquire(&self, amount: usize) {
+        let wait = {
+            let Ok(mut bucket) = self.bucket.lock() else { return };
+            if bucket.rate == 0 {
+                return;
+            }
+            let rate = bucket.rate as f64;
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.last).as_secs_f64();
+            bucket.last = now;
+            // Allow bursts of at most one second worth of data
+            bucket.tokens = (bucket.tokens + elapsed * rate).min(rate);
+            bucket.tokens -= amount as f64;
+            if bucket.tokens >= 0.0 {
+                return;
+            }
+            Duration::from_secs_f64(-bucket.tokens / rate)
+        };
+        std::thread::sleep(wait);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pretend the limiter has been idle for `idle`
+    fn idle_for(limiter: &RateLimiter, idle: Duration) {
+        let mut bucket = limiter.bucket.lock().unwrap();
+        bucket.last = Instant::now().checked_sub(idle).unwrap();
+    }
+
+    #[test]
+    fn zero_rate_is_unlimited() {
+        let limiter = RateLimiter::default();
+        let start = Instant::now();
+        limiter.acquire(usize::MAX);
+        limiter.acquire(usize::MAX);
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+
+    #[test]
+    fn disabling_the_limit_stops_waiting() {
+        let limiter = RateLimiter::new(10);
+        limiter.set_rate(0);
+        let start = Instant::now();
+        limiter.acquire(1_000_000);
+        assert!(start.elapsed() < Duration::from_millis(50));
+        assert_eq!(limiter.rate(), 0);
+    }
+
+    #[test]
+    fn burst_is_capped_at_one_second() {
+        let limiter = RateLimiter::new(1000);
+        idle_for(&limiter, Duration::from_secs(10));
+        let start = Instant::now();
+        limiter.acquire(1000);
+        assert!(start.elapsed() < Duration::from_millis(50));
+        // Ten idle seconds did not save more than one second worth of data
+        limiter.acquire(100);
+        assert!(start.elapsed() >= Duration::from_millis(90));
+    }
+
+    #[test]
+    fn waits_for_the_bucket_to_refill() {
+        let limiter = RateLimiter::new(1000);
+        let start = Instant::now();
+        limiter.acquire(200);
+        assert!(start.elapsed() >= Duration::from_millis(190));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn refills_while_idle() {
+        let limiter = RateLimiter::new(1000);
+        idle_for(&limiter, Duration::from_millis(500));
+        let start = Instant::now();
+        limiter.acquire(400);
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,13 @@
+//! Downloading and assembling assets, shared by the download manager and the headless mode.
+
+pub mod chunk_cache;
+pub mod error;
+pub mod http;
+pub mod limiter;
+pub mod pipeline;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ThreadMessages {
+    Cancel,
+    Pause,
+}
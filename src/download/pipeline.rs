@@ -0,0 +1,868 @@
+//! Download steps shared by the download manager and the headless mode.
+//! Nothing in here uses widgets, progress and failures are reported to the caller.
+
+use crate::download::chunk_cache::ChunkCache;
+use crate::download::error::DownloadError;
+use crate::download::http::{self, RangeResponse};
+use crate::download::limiter::RateLimiter;
+use crate::download::ThreadMessages;
+use egs_api::api::types::asset_info::AssetInfo;
+use egs_api::api::types::download_manifest::{DownloadManifest, FileChunkPart, FileManifestList};
+use log::{debug, error, warn};
+use reqwest::Url;
+use sha1::digest::core_api::CoreWrapper;
+use sha1::{Digest, Sha1, Sha1Core};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Part of a chunk that can be read from a file of an already downloaded release
+#[derive(Debug, Clone)]
+pub struct LocalChunkPart {
+    pub offset: u128,
+    pub size: u128,
+    pub path: PathBuf,
+    pub position: u64,
+}
+
+/// File of a release and where the data of its chunk parts comes from
+#[derive(Default, Debug, Clone)]
+pub struct DownloadedFile {
+    pub asset: String,
+    pub release: String,
+    pub name: String,
+    pub chunks: Vec<FileChunkPart>,
+    pub finished_chunks: Vec<FileChunkPart>,
+    /// Sources in other releases for the chunk parts at the same index, these are not downloaded
+    pub local_parts: Vec<Option<LocalChunkPart>>,
+    pub hash: String,
+}
+
+impl DownloadedFile {
+    /// File built only from downloaded chunks
+    pub fn from_manifest(
+        asset: &str,
+        release: &str,
+        name: &str,
+        manifest: &FileManifestList,
+    ) -> Self {
+        Self {
+            asset: asset.to_string(),
+            release: release.to_string(),
+            name: name.to_string(),
+            chunks: manifest.file_chunk_parts.clone(),
+            finished_chunks: Vec::new(),
+            local_parts: Vec::new(),
+            hash: manifest.file_hash.clone(),
+        }
+    }
+
+    /// Take the chunk parts available in other releases from disk instead of downloading them
+    pub fn with_local_parts(mut self, local: &HashMap<String, Vec<LocalChunkPart>>) -> Self {
+        self.local_parts = file_local_parts(local, &self.chunks);
+        self.finished_chunks = self
+            .chunks
+            .iter()
+            .zip(self.local_parts.iter())
+            .filter(|(_, l)| l.is_some())
+            .map(|(chunk, _)| chunk.clone())
+            .collect();
+        self
+    }
+
+    /// All chunk parts can be read from other releases
+    pub fn is_local(&self) -> bool {
+        !self.chunks.is_empty() && self.finished_chunks.len() == self.chunks.len()
+    }
+
+    /// Chunks of the file that have to be downloaded
+    pub fn missing_chunks(&self) -> Vec<&FileChunkPart> {
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !matches!(self.local_parts.get(*index), Some(Some(_))))
+            .map(|(_, chunk)| chunk)
+            .collect()
+    }
+}
+
+/// How a chunk download attempt ended
+#[derive(Debug)]
+pub enum ChunkFetch {
+    /// The file was already complete, with its size
+    Complete(u128),
+    /// All data is on disk, with the bytes received and the time it took
+    Finished(u128, Duration),
+    /// The worker was asked to stop
+    Interrupted(ThreadMessages),
+    /// The connection failed, the download can continue from the partial file
+    Retry,
+}
+
+/// Download a chunk to `p` reporting the size of every received block to `progress`
+pub fn fetch_chunk_data(
+    link: &Url,
+    p: &Path,
+    g: &str,
+    recv: &Receiver<ThreadMessages>,
+    limiter: &RateLimiter,
+    progress: &mut dyn FnMut(u128),
+) -> Result<ChunkFetch, DownloadError> {
+    if let Ok(m) = recv.try_recv() {
+        return Ok(ChunkFetch::Interrupted(m));
+    }
+    debug!(
+        "Downloading chunk {} from {} to {:?}",
+        g,
+        link.to_string(),
+        p
+    );
+    if let Some(parent) = p.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| DownloadError::io(parent, &e))?;
+    }
+    // Continue from data left by a paused or interrupted download
+    let offset = http::partial_size(p);
+    let response = match http::get_from_offset(link, offset, None) {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Failed to start chunk download, trying again later: {}", e);
+            return Ok(ChunkFetch::Retry);
+        }
+    };
+    let mut file = response
+        .open_target(p)
+        .map_err(|e| DownloadError::io(p, &e))?;
+    let mut client = match response {
+        RangeResponse::Complete => {
+            return Ok(ChunkFetch::Complete(u128::from(offset)));
+        }
+        RangeResponse::Resumed(r) | RangeResponse::Restarted(r) => r,
+    };
+    let mut buffer: [u8; 1024] = [0; 1024];
+    let mut downloaded: u128 = 0;
+    let started = std::time::Instant::now();
+    loop {
+        if let Ok(m) = recv.try_recv() {
+            return Ok(ChunkFetch::Interrupted(m));
+        }
+        match client.read(&mut buffer) {
+            Ok(size) => {
+                if let Ok(m) = recv.try_recv() {
+                    return Ok(ChunkFetch::Interrupted(m));
+                }
+                if size > 0 {
+                    limiter.acquire(size);
+                    downloaded += size as u128;
+                    file.write_all(&buffer[0..size])
+                        .map_err(|e| DownloadError::io(p, &e))?;
+                    progress(size as u128);
+                } else {
+                    break;
+                }
+            }
+            Err(e) => {
+                // The data received so far stays on disk and the download continues from there
+                error!("Download error, trying again: {:?}", e);
+                return Ok(ChunkFetch::Retry);
+            }
+        }
+    }
+    Ok(ChunkFetch::Finished(downloaded, started.elapsed()))
+}
+
+/// Create the target directory and save the manifest next to the data
+pub fn save_asset_manifest(t: &Path, manifest: &DownloadManifest) -> Result<(), DownloadError> {
+    if let Ok(w) = crate::RUNNING.read() {
+        if !*w {
+            return Ok(());
+        }
+    }
+    let tar = match t.file_name() {
+        None => {
+            return Ok(());
+        }
+        Some(fname) => {
+            if fname.eq("data") {
+                match t.parent() {
+                    None => {
+                        return Ok(());
+                    }
+                    Some(p) => p,
+                }
+            } else {
+                t
+            }
+        }
+    };
+
+    std::fs::create_dir_all(t).map_err(|e| DownloadError::io(t, &e))?;
+    match File::create(tar.join("manifest.json")) {
+        Ok(mut json_manifest_file) => match serde_json::to_string(&manifest) {
+            Ok(json) => {
+                if let Err(e) = json_manifest_file.write_all(json.as_bytes().as_ref()) {
+                    error!("Unable to write json manifest: {}", e);
+                }
+            }
+            Err(e) => {
+                error!("Unable to save json manifest: {}", e);
+            }
+        },
+        Err(e) => {
+            error!("Unable to save Manifest: {:?}", e);
+        }
+    }
+    match File::create(tar.join("manifest")) {
+        Ok(mut manifest_file) => {
+            if let Err(e) = manifest_file.write_all(&manifest.to_vec()) {
+                error!("Unable to write binary Manifest: {}", e);
+            }
+        }
+        Err(e) => {
+            error!("Unable to save binary Manifest: {:?}", e);
+        }
+    }
+    Ok(())
+}
+
+/// Load the manifest saved next to the asset data by `save_asset_manifest`
+pub fn load_asset_manifest(t: &Path) -> Option<DownloadManifest> {
+    let tar = if t.file_name().map_or(false, |f| f.eq("data")) {
+        t.parent()?
+    } else {
+        t
+    };
+    if let Ok(data) = std::fs::read(tar.join("manifest")) {
+        if let Some(manifest) = DownloadManifest::parse(data) {
+            return Some(manifest);
+        }
+    }
+    match File::open(tar.join("manifest.json")) {
+        Ok(f) => match serde_json::from_reader(f) {
+            Ok(manifest) => Some(manifest),
+            Err(e) => {
+                error!("Unable to parse json manifest: {}", e);
+                None
+            }
+        },
+        Err(e) => {
+            error!("Unable to open saved manifest: {:?}", e);
+            None
+        }
+    }
+}
+
+pub fn file_hash(path: &Path) -> Option<String> {
+    let mut f = File::open(path).ok()?;
+    let mut buffer: [u8; 1024] = [0; 1024];
+    let mut hasher = sha1::Sha1::new();
+    loop {
+        match f.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(size) => hasher.update(&buffer[..size]),
+            Err(e) => {
+                error!("Unable to read {:?}: {}", path, e);
+                return None;
+            }
+        }
+    }
+    let hash = hasher.finalize();
+    Some(hash.iter().map(|b| format!("{b:02x}")).collect::<String>())
+}
+
+/// Write a file from its chunks to `path`, returns if the written data matches the hash in the manifest
+pub fn assemble_file(
+    file: &DownloadedFile,
+    temp: &Path,
+    path: &Path,
+    cache: &ChunkCache,
+) -> Result<bool, DownloadError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| DownloadError::io(parent, &e))?;
+    }
+    debug!("Created target directory: {:?}", path.to_str());
+    let mut target = File::create(path).map_err(|e| {
+        error!("Error opening the target file: {:?}", e);
+        DownloadError::io(path, &e)
+    })?;
+    let hash = extract_chunks(file, temp, &mut target, cache)?.finalize();
+    Ok(file
+        .hash
+        .eq(&hash.iter().map(|b| format!("{b:02x}")).collect::<String>()))
+}
+
+fn extract_chunks(
+    file: &DownloadedFile,
+    temp: &Path,
+    target: &mut File,
+    cache: &ChunkCache,
+) -> Result<CoreWrapper<Sha1Core>, DownloadError> {
+    let mut hasher = Sha1::new();
+    for (index, chunk) in file.chunks.iter().enumerate() {
+        if let Some(Some(local)) = file.local_parts.get(index) {
+            match read_local_part(local) {
+                Some(data) => {
+                    hasher.update(&data);
+                    target
+                        .write_all(&data)
+                        .map_err(|e| DownloadError::Extraction(e.to_string()))?;
+                }
+                None => {
+                    error!("Unable to read chunk data from {:?}", local.path);
+                    break;
+                }
+            }
+            continue;
+        }
+        let mut t = temp.to_path_buf();
+        t.push(format!("{}.chunk", chunk.guid));
+        let Some(data) = cache.get(&t) else {
+            error!("Unable to get data of chunk: {:?}", chunk.link);
+            break;
+        };
+        if (data.len() as u128) < chunk.offset + chunk.size {
+            error!("Chunk is not big enough");
+            break;
+        };
+        let part = &data[chunk.offset as usize..(chunk.offset + chunk.size) as usize];
+        hasher.update(part);
+        target
+            .write_all(part)
+            .map_err(|e| DownloadError::Extraction(e.to_string()))?;
+        debug!("chunk: {:?}", chunk);
+    }
+    Ok(hasher)
+}
+
+fn read_local_part(local: &LocalChunkPart) -> Option<Vec<u8>> {
+    let mut f = File::open(&local.path).ok()?;
+    f.seek(SeekFrom::Start(local.position)).ok()?;
+    let mut data = vec![0_u8; usize::try_from(local.size).ok()?];
+    f.read_exact(&mut data).ok()?;
+    Some(data)
+}
+
+/// Map chunk GUIDs to the places in already downloaded releases where their data is stored
+pub fn local_chunk_parts(sources: &[PathBuf]) -> HashMap<String, Vec<LocalChunkPart>> {
+    let mut result: HashMap<String, Vec<LocalChunkPart>> = HashMap::new();
+    for data in sources {
+        let Some(manifest) = load_asset_manifest(data) else { continue };
+        for (filename, file) in manifest.files() {
+            let path = data.join(&filename);
+            let Ok(metadata) = std::fs::metadata(&path) else { continue };
+            if u128::from(metadata.len()) != file.size() {
+                continue;
+            }
+            let mut position = 0_u64;
+            for part in file.file_chunk_parts {
+                let size = part.size;
+                result.entry(part.guid).or_default().push(LocalChunkPart {
+                    offset: part.offset,
+                    size,
+                    path: path.clone(),
+                    position,
+                });
+                position += u64::try_from(size).unwrap_or_default();
+            }
+        }
+    }
+    result
+}
+
+/// Find local sources for the parts of a file
+/// A chunk is only taken from disk when all of its parts are available, otherwise it is downloaded anyway
+pub fn file_local_parts(
+    local: &HashMap<String, Vec<LocalChunkPart>>,
+    chunks: &[FileChunkPart],
+) -> Vec<Option<LocalChunkPart>> {
+    let mut result: Vec<Option<LocalChunkPart>> = chunks
+        .iter()
+        .map(|chunk| {
+            local.get(&chunk.guid).and_then(|parts| {
+                parts
+                    .iter()
+                    .find(|p| {
+                        p.offset <= chunk.offset && chunk.offset + chunk.size <= p.offset + p.size
+                    })
+                    .map(|p| LocalChunkPart {
+                        offset: chunk.offset,
+                        size: chunk.size,
+                        path: p.path.clone(),
+                        position: p.position
+                            + u64::try_from(chunk.offset - p.offset).unwrap_or_default(),
+                    })
+            })
+        })
+        .collect();
+    let missing: HashSet<String> = chunks
+        .iter()
+        .zip(result.iter())
+        .filter(|(_, l)| l.is_none())
+        .map(|(c, _)| c.guid.clone())
+        .collect();
+    for (chunk, l) in chunks.iter().zip(result.iter_mut()) {
+        if missing.contains(&chunk.guid) {
+            *l = None;
+        }
+    }
+    result
+}
+
+/// Longest wait before downloading a failed chunk again
+const MAX_BACKOFF_SECONDS: u32 = 64;
+
+/// How many times a chunk is tried on every host it is available from before the download fails
+const ATTEMPTS_PER_HOST: u32 = 3;
+
+/// Chunk download results of a single CDN host during this session
+#[derive(Default, Debug, Clone)]
+pub struct HostStats {
+    pub chunks: u64,
+    pub failures: u64,
+    pub bytes: u128,
+    pub millis: u128,
+}
+
+impl HostStats {
+    /// Average speed in bytes per second
+    pub fn speed(&self) -> Option<u128> {
+        if self.millis == 0 {
+            None
+        } else {
+            Some(self.bytes * 1000 / self.millis)
+        }
+    }
+
+    pub fn failure_rate(&self) -> f64 {
+        let total = self.chunks + self.failures;
+        if total == 0 {
+            0.0
+        } else {
+            self.failures as f64 / total as f64
+        }
+    }
+
+    pub fn succeeded(&mut self, bytes: u128, duration: Duration) {
+        self.chunks += 1;
+        self.bytes += bytes;
+        self.millis += duration.as_millis();
+    }
+
+    /// Hosts that fail less come first, then faster ones. Hosts that were not used yet are tried before slower ones.
+    fn compare(&self, other: &Self) -> Ordering {
+        self.failure_rate()
+            .partial_cmp(&other.failure_rate())
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| {
+                other
+                    .speed()
+                    .unwrap_or(u128::MAX)
+                    .cmp(&self.speed().unwrap_or(u128::MAX))
+            })
+    }
+}
+
+/// URLs of every chunk, each manifest links the same chunks on a different host
+pub fn chunk_urls(manifests: &[DownloadManifest]) -> HashMap<String, Vec<Url>> {
+    let mut urls: HashMap<String, Vec<Url>> = HashMap::new();
+    for manifest in manifests {
+        for m in manifest.files().values() {
+            for chunk in &m.file_chunk_parts {
+                if let Some(url) = &chunk.link {
+                    urls.entry(chunk.guid.clone())
+                        .or_default()
+                        .push(url.clone());
+                }
+            }
+        }
+    }
+    urls
+}
+
+/// Pick the URL on the best host, avoiding the host of `exclude` when there are other options
+pub fn best_url(
+    candidates: &[Url],
+    stats: &HashMap<String, HostStats>,
+    exclude: Option<&Url>,
+) -> Option<Url> {
+    let empty = HostStats::default();
+    let host = |u: &Url| u.host_str().and_then(|h| stats.get(h)).unwrap_or(&empty);
+    let mut sorted: Vec<&Url> = candidates.iter().collect();
+    sorted.sort_by(|a, b| host(a).compare(host(b)));
+    sorted
+        .iter()
+        .find(|u| exclude.map_or(true, |e| e.host_str() != u.host_str()))
+        .or_else(|| sorted.first())
+        .map(|u| (*u).clone())
+}
+
+/// How many attempts a chunk available from `hosts` hosts gets before the download fails
+pub fn attempt_limit(hosts: usize) -> u32 {
+    ATTEMPTS_PER_HOST.saturating_mul(u32::try_from(hosts.max(1)).unwrap_or(u32::MAX))
+}
+
+/// Seconds to wait before the attempt, doubling up to a limit
+pub fn backoff_seconds(attempt: u32) -> u32 {
+    2_u32.saturating_pow(attempt).min(MAX_BACKOFF_SECONDS)
+}
+
+/// Download a chunk blocking the thread, moving to the best other host after every failure.
+/// The download manager drives the same policy from its main loop with `best_url`.
+pub fn download_chunk(
+    guid: &str,
+    urls: &[Url],
+    p: &Path,
+    recv: &Receiver<ThreadMessages>,
+    limiter: &RateLimiter,
+    hosts: &Mutex<HashMap<String, HostStats>>,
+    progress: &mut dyn FnMut(u128),
+) -> Result<ChunkFetch, DownloadError> {
+    let limit = attempt_limit(urls.len());
+    let mut failed: Option<Url> = None;
+    for attempt in 1..=limit {
+        let link = {
+            let stats = hosts.lock().map(|s| s.clone()).unwrap_or_default();
+            best_url(urls, &stats, failed.as_ref())
+        }
+        .ok_or_else(|| DownloadError::Http(format!("No URL to download chunk {guid}")))?;
+        let result = fetch_chunk_data(&link, p, guid, recv, limiter, progress)?;
+        let host = link.host_str().unwrap_or_default().to_string();
+        match result {
+            ChunkFetch::Finished(bytes, elapsed) => {
+                if let Ok(mut stats) = hosts.lock() {
+                    stats.entry(host).or_default().succeeded(bytes, elapsed);
+                }
+                return Ok(result);
+            }
+            ChunkFetch::Retry => {
+                if let Ok(mut stats) = hosts.lock() {
+                    stats.entry(host).or_default().failures += 1;
+                }
+                warn!(
+                    "Chunk {} failed on {:?}, attempt {} of {}",
+                    guid,
+                    link.host_str(),
+                    attempt,
+                    limit
+                );
+                failed = Some(link);
+                std::thread::sleep(Duration::from_secs(u64::from(backoff_seconds(attempt))));
+            }
+            ChunkFetch::Complete(_) | ChunkFetch::Interrupted(_) => return Ok(result),
+        }
+    }
+    Err(DownloadError::Http(format!(
+        "Chunk {guid} could not be downloaded from any of {} hosts",
+        urls.len()
+    )))
+}
+
+/// Chunks the files need that are not available from other releases, each once and in manifest order
+pub fn needed_chunks(files: &[DownloadedFile]) -> Vec<String> {
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut needed = Vec::new();
+    for file in files {
+        for chunk in file.missing_chunks() {
+            if seen.insert(chunk.guid.clone()) {
+                needed.push(chunk.guid.clone());
+            }
+        }
+    }
+    needed
+}
+
+/// Other releases of the asset already in the vaults, they can provide the chunks shared with `release_id`
+pub fn delta_sources(asset: &AssetInfo, release_id: &str, vaults: &[PathBuf]) -> Vec<PathBuf> {
+    let current = asset
+        .release_info(&release_id.to_string())
+        .and_then(|r| r.app_id)
+        .unwrap_or_default();
+    let mut sources: Vec<PathBuf> = Vec::new();
+    if let Some(releases) = &asset.release_info {
+        for rel in releases {
+            if let Some(app) = &rel.app_id {
+                if app.eq(&current) {
+                    continue;
+                }
+                sources.extend(
+                    vaults
+                        .iter()
+                        .map(|vault| vault.join(app).join("data"))
+                        .filter(|path| path.exists()),
+                );
+            }
+        }
+    }
+    sources
+}
+
+/// Space a download needs on the filesystem containing `path`
+#[derive(Debug, Clone)]
+pub struct SpaceNeed {
+    pub path: PathBuf,
+    pub bytes: u64,
+}
+
+impl SpaceNeed {
+    pub fn new<P: Into<PathBuf>>(path: P, bytes: u64) -> Self {
+        Self {
+            path: path.into(),
+            bytes,
+        }
+    }
+}
+
+/// Result of checking one filesystem
+#[derive(Debug, Clone)]
+pub struct FilesystemCheck {
+    pub path: PathBuf,
+    pub needed: u64,
+    pub available: u64,
+    pub reserved: u64,
+}
+
+impl FilesystemCheck {
+    pub fn fits_alone(&self) -> bool {
+        self.needed <= self.available
+    }
+
+    pub fn fits(&self) -> bool {
+        self.needed + self.reserved <= self.available
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "{} needs {}, {} available, {} reserved by other downloads",
+            self.path.display(),
+            format_bytes(self.needed),
+            format_bytes(self.available),
+            format_bytes(self.reserved)
+        )
+    }
+}
+
+/// Closest directory that exists, the download directories are often created later
+fn existing_ancestor(path: &Path) -> Option<&Path> {
+    let mut current = path;
+    while !current.exists() {
+        current = current.parent()?;
+    }
+    Some(current)
+}
+
+/// Compare the needs of a download with the free space of their filesystems and the space reserved by other downloads
+pub fn check_space<'a>(
+    needs: &[SpaceNeed],
+    reserved: impl IntoIterator<Item = &'a SpaceNeed>,
+) -> Vec<FilesystemCheck> {
+    let mut per_fs: HashMap<u64, FilesystemCheck> = HashMap::new();
+    for need in needs {
+        let Some(existing) = existing_ancestor(&need.path) else { continue };
+        let key = filesystem_id(existing);
+        let check = per_fs.entry(key).or_insert_with(|| FilesystemCheck {
+            path: existing.to_path_buf(),
+            needed: 0,
+            available: fs2::available_space(existing).unwrap_or_default(),
+            reserved: 0,
+        });
+        check.needed += need.bytes;
+    }
+    for need in reserved {
+        let Some(existing) = existing_ancestor(&need.path) else { continue };
+        if let Some(check) = per_fs.get_mut(&filesystem_id(existing)) {
+            check.reserved += need.bytes;
+        }
+    }
+    per_fs.into_values().collect()
+}
+
+/// Space an asset download needs for its chunks in `temp` and its files in every target
+pub fn asset_space_needs(
+    manifest: &DownloadManifest,
+    temp: &Path,
+    targets: &[PathBuf],
+    reused: u128,
+) -> Vec<SpaceNeed> {
+    let final_size: u128 = manifest.files().values().map(|f| f.size()).sum();
+    // Chunks read from other releases are never downloaded to the temporary directory
+    let download = manifest.total_download_size();
+    let download = if final_size == 0 {
+        download
+    } else {
+        download - download * reused.min(final_size) / final_size
+    };
+    let mut needs = vec![SpaceNeed::new(
+        temp,
+        u64::try_from(download).unwrap_or(u64::MAX),
+    )];
+    for target in targets {
+        needs.push(SpaceNeed::new(
+            target,
+            u64::try_from(final_size).unwrap_or(u64::MAX),
+        ));
+    }
+    needs
+}
+
+/// Size of the file data of `manifest` that can be read from `local` parts instead of downloaded
+pub fn reused_bytes(
+    manifest: &DownloadManifest,
+    local: &HashMap<String, Vec<LocalChunkPart>>,
+) -> u128 {
+    if local.is_empty() {
+        return 0;
+    }
+    manifest
+        .files()
+        .values()
+        .map(|m| {
+            file_local_parts(local, &m.file_chunk_parts)
+                .iter()
+                .flatten()
+                .map(|l| l.size)
+                .sum::<u128>()
+        })
+        .sum()
+}
+
+/// Chunks none of `files` has to download because all their parts are read from `local`,
+/// with the download size of every chunk
+pub fn reused_chunks(
+    manifest: &DownloadManifest,
+    files: &[FileManifestList],
+    local: &HashMap<String, Vec<LocalChunkPart>>,
+) -> Vec<(String, u128)> {
+    let mut reused: HashSet<String> = HashSet::new();
+    let mut missing: HashSet<String> = HashSet::new();
+    for file in files {
+        let parts = file_local_parts(local, &file.file_chunk_parts);
+        for (chunk, part) in file.file_chunk_parts.iter().zip(parts.iter()) {
+            if part.is_some() {
+                reused.insert(chunk.guid.clone());
+            } else {
+                missing.insert(chunk.guid.clone());
+            }
+        }
+    }
+    reused
+        .difference(&missing)
+        .map(|guid| {
+            (
+                guid.clone(),
+                manifest
+                    .chunk_filesize_list
+                    .get(guid)
+                    .copied()
+                    .unwrap_or_default(),
+            )
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn filesystem_id(path: &Path) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(path).map_or(0, |m| m.dev())
+}
+
+#[cfg(not(unix))]
+fn filesystem_id(path: &Path) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    path.components().next().hash(&mut hasher);
+    hasher.finish()
+}
+
+fn format_bytes(bytes: u64) -> String {
+    byte_unit::Byte::from_bytes(u128::from(bytes))
+        .get_appropriate_unit(false)
+        .format(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(host: &str) -> Url {
+        Url::parse(&format!("https://{host}/chunk")).unwrap()
+    }
+
+    fn stats(chunks: u64, failures: u64, bytes: u128, millis: u128) -> HostStats {
+        HostStats {
+            chunks,
+            failures,
+            bytes,
+            millis,
+        }
+    }
+
+    #[test]
+    fn best_url_prefers_reliable_and_fast_hosts() {
+        let candidates = vec![url("slow"), url("failing"), url("fast")];
+        let mut hosts = HashMap::new();
+        hosts.insert("slow".to_string(), stats(10, 0, 1000, 1000));
+        hosts.insert("failing".to_string(), stats(10, 10, 100_000, 1000));
+        hosts.insert("fast".to_string(), stats(10, 0, 100_000, 1000));
+        assert_eq!(best_url(&candidates, &hosts, None), Some(url("fast")));
+        assert_eq!(
+            best_url(&candidates, &hosts, Some(&url("fast"))),
+            Some(url("slow"))
+        );
+    }
+
+    #[test]
+    fn best_url_tries_unused_hosts_before_slower_ones() {
+        let candidates = vec![url("used"), url("new")];
+        let mut hosts = HashMap::new();
+        hosts.insert("used".to_string(), stats(10, 0, 1000, 1000));
+        assert_eq!(best_url(&candidates, &hosts, None), Some(url("new")));
+    }
+
+    #[test]
+    fn best_url_falls_back_to_the_excluded_host() {
+        let candidates = vec![url("only")];
+        assert_eq!(
+            best_url(&candidates, &HashMap::new(), Some(&url("only"))),
+            Some(url("only"))
+        );
+        assert_eq!(best_url(&[], &HashMap::new(), None), None);
+    }
+
+    #[test]
+    fn host_stats_record_successes() {
+        let mut s = HostStats::default();
+        assert_eq!(s.speed(), None);
+        s.succeeded(2000, Duration::from_secs(2));
+        s.failures += 1;
+        assert_eq!(s.speed(), Some(1000));
+        assert!((s.failure_rate() - 0.5).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn attempts_grow_with_hosts_and_backoff_is_capped() {
+        assert_eq!(attempt_limit(0), ATTEMPTS_PER_HOST);
+        assert_eq!(attempt_limit(2), ATTEMPTS_PER_HOST * 2);
+        assert_eq!(backoff_seconds(1), 2);
+        assert_eq!(backoff_seconds(3), 8);
+        assert_eq!(backoff_seconds(40), MAX_BACKOFF_SECONDS);
+    }
+
+    #[test]
+    fn space_is_summed_per_filesystem() {
+        let dir = std::env::temp_dir();
+        let needs = vec![
+            SpaceNeed::new(dir.join("egl-space-missing/temp"), 10),
+            SpaceNeed::new(dir.join("egl-space-missing/data"), 5),
+        ];
+        let reserved = vec![SpaceNeed::new(dir.clone(), 7)];
+        let checks = check_space(&needs, &reserved);
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].path, dir);
+        assert_eq!(checks[0].needed, 15);
+        assert_eq!(checks[0].reserved, 7);
+    }
+}
@@ -0,0 +1,491 @@
+use crate::download::chunk_cache::ChunkCache;
+use crate::download::error::DownloadError;
+use crate::download::limiter::RateLimiter;
+use crate::download::pipeline::{self, DownloadedFile, HostStats};
+use crate::models::asset_cache;
+use crate::models::collections;
+use crate::models::Model;
+use crate::ui::widgets::download_manager::asset;
+use crate::ui::widgets::download_manager::conflict::ConflictPolicy;
+use crate::ui::widgets::download_manager::download_item::ItemType;
+use crate::ui::widgets::download_manager::history::{self, DownloadResult, HistoryEntry};
+use crate::ui::widgets::download_manager::hooks::{self, HookContext};
+use crate::ui::widgets::download_manager::layer_cache;
+use crate::ui::widgets::download_manager::queue::QueuedDownload;
+use crate::ui::widgets::download_manager::PostDownloadAction;
+use egs_api::api::types::asset_info::AssetInfo;
+use egs_api::api::types::download_manifest::DownloadManifest;
+use gtk4::prelude::*;
+use log::{debug, warn};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use threadpool::ThreadPool;
+
+const EXIT_FAILED: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+/// A downloaded file does not match the hash in the manifest
+const EXIT_CORRUPTED: i32 = 3;
+
+const USAGE: &str = "Usage: epic_asset_manager download <asset-id|catalog-item-id> [--release <app_id>] --target <dir> [--run <command>]...";
+const DOCKER_CACHE_USAGE: &str = "Usage: epic_asset_manager docker-cache [--evict <GiB>|--clear]";
+const EXPORT_USAGE: &str = "Usage: epic_asset_manager export-collections [<file>]";
+
+#[derive(Debug)]
+struct DownloadArgs {
+    asset: String,
+    release: Option<String>,
+    target: PathBuf,
+    /// Commands to run after the download finished
+    commands: Vec<String>,
+}
+
+/// Download an asset without the user interface, returns the exit code of the process
+pub fn run(args: &[String]) -> i32 {
+    let args = match parse_args(args) {
+        Ok(a) => a,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            return EXIT_USAGE;
+        }
+    };
+    debug!("Headless download: {:?}", args);
+    let model = Model::new();
+    let mut eg = model.epic_games.borrow().clone();
+    let ud = eg.user_details();
+    if ud.access_token().is_none() && ud.refresh_token().is_none() {
+        eprintln!("Not logged in, log in with the application first");
+        return EXIT_FAILED;
+    }
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("Unable to start the async runtime: {e}");
+            return EXIT_FAILED;
+        }
+    };
+    if !runtime.block_on(eg.login()) {
+        eprintln!("Unable to log in with the stored token, log in with the application again");
+        return EXIT_FAILED;
+    }
+    store_tokens(&model, &eg.user_details());
+
+    let Some(asset) = find_asset(&model, &mut eg, &runtime, &args.asset) else {
+        eprintln!("Asset {} is not in the library", args.asset);
+        return EXIT_FAILED;
+    };
+    let Some(release_id) = find_release(&asset, args.release.as_deref()) else {
+        eprintln!(
+            "Release {} of {} not found",
+            args.release.unwrap_or_default(),
+            asset.title
+        );
+        return EXIT_FAILED;
+    };
+    println!("Downloading {} ({})", asset.title, release_id);
+
+    let manifests = asset::request_download_manifests(&mut eg, &release_id, &asset);
+    let Some(manifest) = manifests.first() else {
+        eprintln!("Unable to get download manifests");
+        return EXIT_FAILED;
+    };
+    let mut temp = PathBuf::from(
+        model
+            .settings
+            .string("temporary-download-directory")
+            .to_string(),
+    );
+    temp.push(&manifest.app_name_string);
+    temp.push("temp");
+    // Other releases already on disk can provide the chunks shared with this one
+    let vaults: Vec<PathBuf> = model
+        .settings
+        .strv("unreal-vault-directories")
+        .iter()
+        .map(|v| PathBuf::from(v.to_str()))
+        .collect();
+    let mut sources = pipeline::delta_sources(&asset, &release_id, &vaults);
+    sources.retain(|s| s.ne(&args.target));
+    let local = pipeline::local_chunk_parts(&sources);
+    if !local.is_empty() {
+        println!(
+            "Reusing {} chunks from already downloaded releases",
+            local.len()
+        );
+    }
+    let needs = pipeline::asset_space_needs(
+        manifest,
+        &temp,
+        &[args.target.clone()],
+        pipeline::reused_bytes(manifest, &local),
+    );
+    if let Some(full) = pipeline::check_space(&needs, std::iter::empty())
+        .iter()
+        .find(|c| !c.fits_alone())
+    {
+        eprintln!("Not enough disk space: {}", full.describe());
+        return EXIT_FAILED;
+    }
+    let files: Vec<DownloadedFile> = manifest
+        .files()
+        .into_iter()
+        .map(|(filename, m)| {
+            DownloadedFile::from_manifest(&release_id, &manifest.app_name_string, &filename, &m)
+                .with_local_parts(&local)
+        })
+        .collect();
+
+    let started_at = chrono::Utc::now();
+    let outcome = pipeline::save_asset_manifest(&args.target, manifest)
+        .and_then(|_| download_chunks(&model, &manifests, &files, &temp))
+        .and_then(|received| {
+            assemble_files(&model, files, &temp, &args.target)
+                .map(|corrupted| (received, corrupted))
+        });
+    let (mut code, received, result, error) = match outcome {
+        Ok((received, corrupted)) if corrupted.is_empty() => {
+            if let Err(e) = std::fs::remove_dir_all(&temp) {
+                warn!("Unable to remove temporary directory {:?}: {}", temp, e);
+            }
+            println!("Downloaded {} to {}", asset.title, args.target.display());
+            (0, received, DownloadResult::Finished, None)
+        }
+        Ok((received, corrupted)) => {
+            let message = format!("{} files failed hash validation", corrupted.len());
+            eprintln!("{}, chunks are kept in {}", message, temp.display());
+            (
+                EXIT_CORRUPTED,
+                received,
+                DownloadResult::Failed,
+                Some(message),
+            )
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            (EXIT_FAILED, 0, DownloadResult::Failed, Some(e.to_string()))
+        }
+    };
+    let target = args.target.display().to_string();
+    if result == DownloadResult::Finished {
+        let mut commands = args.commands.clone();
+        let global = model.settings.string("post-download-hook").to_string();
+        if !global.trim().is_empty() {
+            commands.insert(0, global);
+        }
+        let context = HookContext {
+            item_type: "asset".to_string(),
+            asset_id: asset.id.clone(),
+            release: release_id.clone(),
+            vault_path: None,
+            targets: vec![target.clone()],
+        };
+        for (command, reason) in hooks::run_commands(&commands, &context) {
+            eprintln!("Command {command} failed: {reason}");
+            code = EXIT_FAILED;
+        }
+    }
+    let release = asset
+        .release_info(&release_id)
+        .and_then(|r| r.version_title.filter(|v| !v.is_empty()).or(r.app_id))
+        .unwrap_or_else(|| release_id.clone());
+    let mut actions = vec![
+        PostDownloadAction::Copy(target.clone(), ConflictPolicy::Overwrite),
+        PostDownloadAction::NoVault,
+    ];
+    actions.extend(
+        args.commands
+            .into_iter()
+            .map(PostDownloadAction::RunCommand),
+    );
+    history::insert(&HistoryEntry::ended(
+        QueuedDownload {
+            id: release_id,
+            item_type: ItemType::Asset,
+            label: asset.title.clone(),
+            target: None,
+            asset: Some(asset),
+            // Running it again from the application writes to the same directory
+            actions,
+            total_size: manifest.total_download_size(),
+            total_files: manifest.file_manifest_list.len() as u64,
+            finished_files: 0,
+        },
+        started_at,
+        release,
+        received,
+        vec![target],
+        result,
+        error,
+    ));
+    code
+}
+
+/// Show or shrink the docker layer cache, returns the exit code of the process
+pub fn docker_cache(args: &[String]) -> i32 {
+    let limit = match args.iter().map(String::as_str).collect::<Vec<&str>>()[..] {
+        [] => None,
+        ["--clear"] => Some(0),
+        ["--evict", size] => match size.parse::<u64>() {
+            Ok(s) => Some(s * 1024 * 1024 * 1024),
+            Err(_) => {
+                eprintln!("--evict needs a size in GiB\n{DOCKER_CACHE_USAGE}");
+                return EXIT_USAGE;
+            }
+        },
+        _ => {
+            eprintln!("{DOCKER_CACHE_USAGE}");
+            return EXIT_USAGE;
+        }
+    };
+    if let Some(limit) = limit {
+        let settings = gtk4::gio::Settings::new(crate::config::APP_ID);
+        let Some(dir) = layer_cache::cache_directory(&settings) else {
+            eprintln!("Unreal Engine directory is not configured");
+            return EXIT_FAILED;
+        };
+        let (removed, freed) = layer_cache::evict(&dir, limit, &HashSet::new());
+        println!(
+            "Removed {} layers, freed {}",
+            removed,
+            byte_unit::Byte::from_bytes(u128::from(freed))
+                .get_appropriate_unit(false)
+                .format(1)
+        );
+    }
+    let (layers, size) = layer_cache::cache_usage();
+    println!(
+        "{} cached layers using {}",
+        layers,
+        byte_unit::Byte::from_bytes(u128::from(size))
+            .get_appropriate_unit(false)
+            .format(1)
+    );
+    0
+}
+
+/// Write the tags and collections as JSON to the file or stdout, returns the exit code of the process
+pub fn export_collections(args: &[String]) -> i32 {
+    if args.len() > 1 {
+        eprintln!("{EXPORT_USAGE}");
+        return EXIT_USAGE;
+    }
+    let export = collections::export();
+    let json = match serde_json::to_string_pretty(&export) {
+        Ok(j) => j,
+        Err(e) => {
+            eprintln!("Unable to serialize the collections: {e}");
+            return EXIT_FAILED;
+        }
+    };
+    match args.first() {
+        None => println!("{json}"),
+        Some(file) => {
+            if let Err(e) = std::fs::write(file, json) {
+                eprintln!("Unable to write {file}: {e}");
+                return EXIT_FAILED;
+            }
+            println!(
+                "Exported {} tags and {} collections to {}",
+                export.tags.len(),
+                export.collections.len(),
+                file
+            );
+        }
+    }
+    0
+}
+
+fn parse_args(args: &[String]) -> Result<DownloadArgs, String> {
+    let mut asset: Option<String> = None;
+    let mut release: Option<String> = None;
+    let mut target: Option<String> = None;
+    let mut commands: Vec<String> = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--release" => {
+                release = Some(iter.next().ok_or("--release needs an app id")?.clone());
+            }
+            "--target" => {
+                target = Some(iter.next().ok_or("--target needs a directory")?.clone());
+            }
+            "--run" => {
+                commands.push(iter.next().ok_or("--run needs a command")?.clone());
+            }
+            a if a.starts_with("--") => return Err(format!("Unknown option {a}")),
+            a => {
+                if asset.is_some() {
+                    return Err(format!("Unexpected argument {a}"));
+                }
+                asset = Some(a.to_string());
+            }
+        }
+    }
+    Ok(DownloadArgs {
+        asset: asset.ok_or("Missing asset id")?,
+        release,
+        target: PathBuf::from(target.ok_or("Missing --target")?),
+        commands,
+    })
+}
+
+/// Logging in rotates the refresh token, save it so the application can still log in
+fn store_tokens(model: &Model, ud: &egs_api::api::types::account::UserData) {
+    let token = model.save_secret(
+        ud.token_type
+            .as_ref()
+            .unwrap_or(&"login".to_string())
+            .as_str(),
+        "eam_epic_games_token",
+        ud.access_token(),
+        "token-expiration",
+        ud.expires_at,
+    );
+    let refresh = model.save_secret(
+        "refresh",
+        "eam_epic_games_refresh_token",
+        ud.refresh_token(),
+        "refresh-token-expiration",
+        ud.refresh_expires_at,
+    );
+    if !token || !refresh {
+        warn!("org.freedesktop.Secret.Service not available for use, secrets stored insecurely!");
+    }
+}
+
+/// Look for the asset in the library cache first and list the library only if it is not there
+fn find_asset(
+    model: &Model,
+    eg: &mut egs_api::EpicGames,
+    runtime: &tokio::runtime::Runtime,
+    id: &str,
+) -> Option<AssetInfo> {
+    asset_cache::import_json_cache(&PathBuf::from(
+        model.settings.string("cache-directory").to_string(),
+    ));
+    if let Some(info) = asset_cache::find_asset(id) {
+        return Some(info);
+    }
+    debug!("{} is not cached, listing the library", id);
+    let epic_asset = runtime
+        .block_on(eg.list_assets(None, None))
+        .into_iter()
+        .find(|a| a.asset_id.eq(id) || a.catalog_item_id.eq(id))?;
+    runtime.block_on(eg.asset_info(epic_asset))
+}
+
+/// Id of the release with the app id, or of the latest release
+fn find_release(asset: &AssetInfo, app_id: Option<&str>) -> Option<String> {
+    let releases = asset.sorted_releases()?;
+    let release = match app_id {
+        None => releases.first(),
+        Some(app) => releases
+            .iter()
+            .find(|r| r.app_id.as_deref() == Some(app) || r.id.as_deref() == Some(app)),
+    }?;
+    release.id.clone()
+}
+
+/// Download the chunks the files can not take from other releases into `temp`, returns the bytes received
+fn download_chunks(
+    model: &Model,
+    manifests: &[DownloadManifest],
+    files: &[DownloadedFile],
+    temp: &Path,
+) -> Result<u128, DownloadError> {
+    let chunk_urls = pipeline::chunk_urls(manifests);
+    let needed = pipeline::needed_chunks(files);
+
+    let threads = usize::try_from(model.settings.int("download-threads")).unwrap_or(5);
+    let pool = ThreadPool::new(threads.max(1));
+    let limiter = Arc::new(RateLimiter::new(
+        u64::try_from(model.settings.int("download-speed-limit")).unwrap_or_default() * 1024,
+    ));
+    let hosts: Arc<Mutex<HashMap<String, HostStats>>> = Arc::new(Mutex::new(HashMap::new()));
+    let (sender, receiver) = mpsc::channel::<Result<u128, DownloadError>>();
+    for guid in &needed {
+        let Some(urls) = chunk_urls.get(guid).cloned() else {
+            return Err(DownloadError::Http(format!(
+                "No URL to download chunk {guid}"
+            )));
+        };
+        let p = temp.join(format!("{guid}.chunk"));
+        let g = guid.clone();
+        let limiter = limiter.clone();
+        let hosts = hosts.clone();
+        let sender = sender.clone();
+        pool.execute(move || {
+            // Nothing pauses or cancels a headless download
+            let (_control, recv) = mpsc::channel();
+            let mut received: u128 = 0;
+            let result =
+                pipeline::download_chunk(&g, &urls, &p, &recv, &limiter, &hosts, &mut |size| {
+                    received += size;
+                });
+            sender.send(result.map(|_| received)).ok();
+        });
+    }
+    drop(sender);
+
+    let total = needed.len();
+    let mut received: u128 = 0;
+    let mut percent = None;
+    for (done, result) in receiver.iter().enumerate() {
+        received += result?;
+        let current = (done + 1) * 100 / total;
+        if percent != Some(current) {
+            percent = Some(current);
+            println!(
+                "[{:>3}%] Downloaded {}/{} chunks, {}",
+                current,
+                done + 1,
+                total,
+                byte_unit::Byte::from_bytes(received)
+                    .get_appropriate_unit(false)
+                    .format(1)
+            );
+        }
+    }
+    Ok(received)
+}
+
+/// Write all files of the release to `target`, returns the files that failed hash validation
+fn assemble_files(
+    model: &Model,
+    files: Vec<DownloadedFile>,
+    temp: &Path,
+    target: &Path,
+) -> Result<Vec<String>, DownloadError> {
+    let threads = usize::try_from(model.settings.int("file-extraction-threads")).unwrap_or(1);
+    let pool = ThreadPool::new(threads.max(1));
+    let cache_size = usize::try_from(model.settings.int("chunk-cache-size")).unwrap_or(256);
+    let cache = Arc::new(ChunkCache::new(cache_size * 1024 * 1024));
+    let (sender, receiver) = mpsc::channel::<(String, Result<bool, DownloadError>)>();
+    let total = files.len();
+    for file in files {
+        let filename = file.name.clone();
+        let temp = temp.to_path_buf();
+        let path = target.join(&filename);
+        let cache = cache.clone();
+        let sender = sender.clone();
+        pool.execute(move || {
+            let result = pipeline::assemble_file(&file, &temp, &path, &cache);
+            sender.send((filename, result)).ok();
+        });
+    }
+    drop(sender);
+
+    let mut corrupted: Vec<String> = Vec::new();
+    for (done, (filename, result)) in receiver.iter().enumerate() {
+        if !result? {
+            eprintln!("{filename} failed hash validation");
+            corrupted.push(filename.clone());
+        }
+        println!(
+            "[{:>3}%] Extracted {}",
+            (done + 1) * 100 / total.max(1),
+            filename
+        );
+    }
+    Ok(corrupted)
+}
@@ -1,7 +1,8 @@
 mod application;
 #[rustfmt::skip]
 mod config;
-// mod download;
+mod download;
+mod headless;
 mod models;
 mod schema;
 mod tools;
@@ -12,7 +13,7 @@ use crate::config::{GETTEXT_PACKAGE, LOCALEDIR, PKGDATADIR, PROFILE, RESOURCES_F
 use application::EpicAssetManager;
 use env_logger::Env;
 use gettextrs::{bindtextdomain, setlocale, textdomain, LocaleCategory};
-#[cfg(any(target_os = "linux", target_os= "macos"))]
+#[cfg(any(target_os = "linux", target_os = "macos"))]
 use gtk4::gio;
 use log::debug;
 use std::io::Write;
@@ -46,6 +47,18 @@ fn main() {
     gtk4::glib::set_application_name("Epic Asset Manager");
     gtk4::glib::set_prgname(Some("epic_asset_manager"));
 
+    // Downloads from the command line do not need a display
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map_or(false, |a| a.eq("download")) {
+        std::process::exit(headless::run(&args[2..]));
+    }
+    if args.get(1).map_or(false, |a| a.eq("docker-cache")) {
+        std::process::exit(headless::docker_cache(&args[2..]));
+    }
+    if args.get(1).map_or(false, |a| a.eq("export-collections")) {
+        std::process::exit(headless::export_collections(&args[2..]));
+    }
+
     gtk4::init().expect("Unable to start GTK4");
     adw::init().expect("Unable to start Adwaita");
 
@@ -0,0 +1,265 @@
+use crate::schema::{asset_cache, asset_category, asset_key_image, asset_release};
+use diesel::sql_types::Text;
+use diesel::{
+    BoolExpressionMethods, Connection, ExpressionMethods, QueryDsl, RunQueryDsl, SqliteConnection,
+};
+use egs_api::api::types::asset_info::AssetInfo;
+use egs_api::api::types::epic_asset::EpicAsset;
+use log::{debug, error, warn};
+use regex::Regex;
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::Path;
+
+lazy_static::lazy_static! {
+    static ref TAGS: Regex = Regex::new(r"<[^>]*>").unwrap();
+}
+
+#[derive(diesel::QueryableByName)]
+struct SearchHit {
+    #[diesel(sql_type = Text)]
+    id: String,
+}
+
+/// Catalog item ids of the assets with cached info
+pub fn cached_ids() -> HashSet<String> {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else {
+        return HashSet::new();
+    };
+    match asset_cache::table
+        .filter(asset_cache::asset_info.ne(""))
+        .select(asset_cache::id)
+        .load::<String>(&mut conn)
+    {
+        Ok(ids) => ids.into_iter().collect(),
+        Err(e) => {
+            error!("Unable to load the asset cache: {}", e);
+            HashSet::new()
+        }
+    }
+}
+
+/// Info of every cached asset, loaded with one query on startup
+pub fn cached_assets() -> Vec<AssetInfo> {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else {
+        return Vec::new();
+    };
+    let infos: Vec<String> = match asset_cache::table
+        .filter(asset_cache::asset_info.ne(""))
+        .select(asset_cache::asset_info)
+        .load(&mut conn)
+    {
+        Ok(i) => i,
+        Err(e) => {
+            error!("Unable to load the asset cache: {}", e);
+            return Vec::new();
+        }
+    };
+    infos
+        .iter()
+        .filter_map(|i| serde_json::from_str(i).ok())
+        .collect()
+}
+
+/// Cached info of the asset by catalog item id or asset id
+pub fn find_asset(id: &str) -> Option<AssetInfo> {
+    let db = crate::models::database::connection();
+    let mut conn = db.get().ok()?;
+    let info: String = asset_cache::table
+        .filter(asset_cache::id.eq(id).or(asset_cache::asset_id.eq(id)))
+        .filter(asset_cache::asset_info.ne(""))
+        .select(asset_cache::asset_info)
+        .first(&mut conn)
+        .ok()?;
+    serde_json::from_str(&info).ok()
+}
+
+/// Remember the listing entry, the info stays until it is fetched again
+pub fn store_epic_asset(asset: &EpicAsset) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let db = crate::models::database::connection();
+    let mut conn = db.get()?;
+    let json = serde_json::to_string(asset)?;
+    diesel::insert_into(asset_cache::table)
+        .values((
+            asset_cache::id.eq(&asset.catalog_item_id),
+            asset_cache::asset_id.eq(&asset.asset_id),
+            asset_cache::epic_asset.eq(&json),
+            asset_cache::updated_at.eq(chrono::Utc::now().to_rfc3339()),
+        ))
+        .on_conflict(asset_cache::id)
+        .do_update()
+        .set((
+            asset_cache::asset_id.eq(&asset.asset_id),
+            asset_cache::epic_asset.eq(&json),
+        ))
+        .execute(&mut conn)?;
+    Ok(())
+}
+
+/// Store the asset info with its releases, categories and images and index it for search
+pub fn store_asset_info(
+    id: &str,
+    asset: &AssetInfo,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    let db = crate::models::database::connection();
+    let mut conn = db.get()?;
+    conn.transaction(|conn| write_asset_info(conn, id, asset))?;
+    Ok(())
+}
+
+fn write_asset_info(
+    conn: &mut SqliteConnection,
+    id: &str,
+    asset: &AssetInfo,
+) -> Result<(), diesel::result::Error> {
+    let json = serde_json::to_string(asset).unwrap_or_default();
+    let title = asset.title.clone().unwrap_or_default();
+    diesel::update(asset_cache::table.filter(asset_cache::id.eq(id)))
+        .set((
+            asset_cache::title.eq(&title),
+            asset_cache::asset_info.eq(json),
+            asset_cache::updated_at.eq(chrono::Utc::now().to_rfc3339()),
+        ))
+        .execute(conn)?;
+    remove_details(conn, id)?;
+    if let Some(releases) = &asset.release_info {
+        for release in releases {
+            let Some(release_id) = &release.id else { continue };
+            diesel::insert_or_ignore_into(asset_release::table)
+                .values((
+                    asset_release::asset.eq(id),
+                    asset_release::id.eq(release_id),
+                    asset_release::app_id.eq(release.app_id.clone().unwrap_or_default()),
+                    asset_release::version_title
+                        .eq(release.version_title.clone().unwrap_or_default()),
+                    asset_release::date_added.eq(release
+                        .date_added
+                        .map(|d| d.to_rfc3339())
+                        .unwrap_or_default()),
+                ))
+                .execute(conn)?;
+        }
+    }
+    if let Some(categories) = &asset.categories {
+        for category in categories {
+            diesel::insert_or_ignore_into(asset_category::table)
+                .values((
+                    asset_category::asset.eq(id),
+                    asset_category::path.eq(&category.path),
+                ))
+                .execute(conn)?;
+        }
+    }
+    if let Some(images) = &asset.key_images {
+        for image in images {
+            diesel::insert_or_ignore_into(asset_key_image::table)
+                .values((
+                    asset_key_image::asset.eq(id),
+                    asset_key_image::image_type.eq(&image.type_field),
+                    asset_key_image::url.eq(image.url.to_string()),
+                    asset_key_image::md5.eq(&image.md5),
+                ))
+                .execute(conn)?;
+        }
+    }
+    diesel::sql_query(
+        "insert into asset_search (id, title, description, seller, technical_details) values (?, ?, ?, ?, ?)",
+    )
+    .bind::<Text, _>(id)
+    .bind::<Text, _>(title)
+    .bind::<Text, _>(plain_text(asset.long_description.as_deref()))
+    .bind::<Text, _>(asset.developer.clone().unwrap_or_default())
+    .bind::<Text, _>(plain_text(asset.technical_details.as_deref()))
+    .execute(conn)?;
+    Ok(())
+}
+
+fn remove_details(conn: &mut SqliteConnection, id: &str) -> Result<(), diesel::result::Error> {
+    diesel::delete(asset_release::table.filter(asset_release::asset.eq(id))).execute(conn)?;
+    diesel::delete(asset_category::table.filter(asset_category::asset.eq(id))).execute(conn)?;
+    diesel::delete(asset_key_image::table.filter(asset_key_image::asset.eq(id))).execute(conn)?;
+    diesel::sql_query("delete from asset_search where id = ?")
+        .bind::<Text, _>(id)
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Ids of the assets matching the word or phrase, `None` if the index could not be used
+pub fn search(text: &str) -> Option<HashSet<String>> {
+    let query = match_query(text)?;
+    let db = crate::models::database::connection();
+    let mut conn = db.get().ok()?;
+    match diesel::sql_query("select id from asset_search where asset_search match ?")
+        .bind::<Text, _>(query)
+        .load::<SearchHit>(&mut conn)
+    {
+        Ok(hits) => Some(hits.into_iter().map(|h| h.id).collect()),
+        Err(e) => {
+            warn!("Unable to search the asset cache: {}", e);
+            None
+        }
+    }
+}
+
+/// The words have to follow each other in one of the indexed columns, the last one can be
+/// the start of a word
+fn match_query(text: &str) -> Option<String> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    if words.is_empty() {
+        None
+    } else {
+        Some(format!("\"{}\"*", words.join(" ").replace('"', "\"\"")))
+    }
+}
+
+fn plain_text(html: Option<&str>) -> String {
+    let Some(html) = html else {
+        return String::new();
+    };
+    TAGS.replace_all(html, " ").to_string()
+}
+
+/// Move the loose JSON files of older versions into the database, the files are only removed
+/// once all of them were stored
+pub fn import_json_cache(cache: &Path) {
+    let Ok(entries) = std::fs::read_dir(cache) else {
+        return;
+    };
+    let mut imported: Vec<(std::path::PathBuf, [std::path::PathBuf; 2])> = Vec::new();
+    for entry in entries.flatten() {
+        let dir = entry.path();
+        let epic_file = dir.join("epic_asset.json");
+        let Some(epic_asset) = std::fs::File::open(&epic_file)
+            .ok()
+            .and_then(|f| serde_json::from_reader::<_, EpicAsset>(f).ok())
+        else {
+            continue;
+        };
+        debug!("Importing {:?} into the asset cache", dir);
+        if let Err(e) = store_epic_asset(&epic_asset) {
+            error!("Unable to import {:?}, keeping the JSON cache: {}", dir, e);
+            return;
+        }
+        let info_file = dir.join("asset_info.json");
+        if let Some(info) = std::fs::File::open(&info_file)
+            .ok()
+            .and_then(|f| serde_json::from_reader::<_, AssetInfo>(f).ok())
+        {
+            if let Err(e) = store_asset_info(&epic_asset.catalog_item_id, &info) {
+                error!("Unable to import {:?}, keeping the JSON cache: {}", dir, e);
+                return;
+            }
+        }
+        imported.push((dir, [epic_file, info_file]));
+    }
+    for (dir, files) in imported {
+        for file in files {
+            if let Err(e) = std::fs::remove_file(&file) {
+                warn!("Unable to remove the imported {:?}: {}", file, e);
+            }
+        }
+        std::fs::remove_dir(&dir).ok();
+    }
+}
@@ -1,3 +1,5 @@
+use crate::models::collections::{self, UserData};
+use crate::tools::query::{Comparison, Field};
 use chrono::{DateTime, Utc};
 use diesel::dsl::exists;
 use diesel::{select, ExpressionMethods, QueryDsl, RunQueryDsl};
@@ -33,6 +35,9 @@ mod imp {
         name: RefCell<Option<String>>,
         favorite: RefCell<bool>,
         downloaded: RefCell<bool>,
+        pub tags: RefCell<Vec<String>>,
+        pub collections: RefCell<Vec<String>>,
+        pub rating: RefCell<i32>,
         pub kind: RefCell<Option<String>>,
         pub asset: RefCell<Option<AssetInfo>>,
         thumbnail: RefCell<Option<Texture>>,
@@ -52,6 +57,9 @@ mod imp {
                 name: RefCell::new(None),
                 favorite: RefCell::new(false),
                 downloaded: RefCell::new(false),
+                tags: RefCell::new(Vec::new()),
+                collections: RefCell::new(Vec::new()),
+                rating: RefCell::new(0),
                 kind: RefCell::new(None),
                 asset: RefCell::new(None),
                 thumbnail: RefCell::new(None),
@@ -150,12 +158,13 @@ glib::wrapper! {
 // Constructor for new instances. This simply calls glib::Object::new() with
 // initial values for our two properties and then returns the new instance
 impl AssetData {
-    pub fn new(asset: &AssetInfo, image: Option<Texture>) -> AssetData {
+    pub fn new(asset: &AssetInfo, image: Option<Texture>, user_data: UserData) -> AssetData {
         let data: Self = glib::Object::new::<Self>();
         let self_ = data.imp();
 
         data.set_property("id", &asset.id);
         data.check_favorite();
+        data.set_user_data(user_data);
         data.set_property("name", &asset.title);
         self_.asset.replace(Some(asset.clone()));
         data.check_downloaded();
@@ -236,6 +245,12 @@ impl AssetData {
         self.property("downloaded")
     }
 
+    /// Personal rating, 0 if not rated
+    pub fn rating(&self) -> i32 {
+        let self_ = self.imp();
+        *self_.rating.borrow()
+    }
+
     pub fn release(&self) -> Option<DateTime<Utc>> {
         let self_ = self.imp();
         (*self_.asset.borrow())
@@ -325,6 +340,53 @@ impl AssetData {
         })
     }
 
+    /// Check a field qualifier of the search query
+    pub fn matches_field(&self, field: &Field) -> bool {
+        let self_ = self.imp();
+        let asset = self_.asset.borrow();
+        let Some(asset) = asset.as_ref() else {
+            return false;
+        };
+        match field {
+            Field::Seller(seller) => asset
+                .developer
+                .as_ref()
+                .map_or(false, |d| d.to_lowercase().contains(seller)),
+            Field::Engine(version) => asset.compatible_apps().map_or(false, |apps| {
+                apps.iter()
+                    .any(|a| a.trim_start_matches("UE_").starts_with(version.as_str()))
+            }),
+            Field::Platform(platform) => asset.platforms().map_or(false, |platforms| {
+                platforms
+                    .iter()
+                    .any(|p| p.to_lowercase().contains(platform))
+            }),
+            Field::Kind(kind) => self_
+                .kind
+                .borrow()
+                .as_ref()
+                .map_or(false, |k| k.trim_end_matches('s').eq(kind)),
+            Field::Downloaded(downloaded) => self.downloaded() == *downloaded,
+            Field::Favorite(favorite) => self.favorite() == *favorite,
+            Field::Released(comparison, date) => self.release().map_or(false, |r| {
+                let released = r.date_naive();
+                match comparison {
+                    Comparison::Equal => released == *date,
+                    Comparison::Before => released < *date,
+                    Comparison::BeforeOrEqual => released <= *date,
+                    Comparison::After => released > *date,
+                    Comparison::AfterOrEqual => released >= *date,
+                }
+            }),
+            Field::Tag(tag) => self_.tags.borrow().iter().any(|t| t.to_lowercase().eq(tag)),
+            Field::Collection(name) => self_
+                .collections
+                .borrow()
+                .iter()
+                .any(|c| c.to_lowercase().eq(name)),
+        }
+    }
+
     pub fn check_downloaded(&self) {
         let self_ = self.imp();
         let asset = &*self_.asset.borrow();
@@ -373,8 +435,21 @@ impl AssetData {
         self.set_property("favorite", false);
     }
 
+    /// Load the user tags, collections and rating of the asset
+    pub fn check_user_data(&self) {
+        self.set_user_data(collections::asset_user_data(&self.id()));
+    }
+
+    pub fn set_user_data(&self, data: UserData) {
+        let self_ = self.imp();
+        self_.tags.replace(data.tags);
+        self_.collections.replace(data.collections);
+        self_.rating.replace(data.rating);
+    }
+
     pub fn refresh(&self) {
         self.check_favorite();
+        self.check_user_data();
         self.check_downloaded();
         self.emit_by_name::<()>("refreshed", &[]);
     }
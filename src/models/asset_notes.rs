@@ -0,0 +1,72 @@
+use crate::schema::asset_note;
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use log::error;
+
+pub const MAX_RATING: i32 = 5;
+
+/// Private notes and rating of an asset
+#[derive(Debug, Default, Clone)]
+pub struct AssetNote {
+    /// Markdown as the user wrote it
+    pub notes: String,
+    /// 1 to 5, 0 if the asset is not rated
+    pub rating: i32,
+}
+
+pub fn note(asset: &str) -> AssetNote {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else {
+        return AssetNote::default();
+    };
+    asset_note::table
+        .filter(asset_note::asset.eq(asset))
+        .select((asset_note::notes, asset_note::rating))
+        .first::<(String, i32)>(&mut conn)
+        .map_or_else(
+            |_| AssetNote::default(),
+            |(notes, rating)| AssetNote { notes, rating },
+        )
+}
+
+pub fn save_notes(asset: &str, notes: &str) {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else { return };
+    let now = chrono::Utc::now().to_rfc3339();
+    if let Err(e) = diesel::insert_into(asset_note::table)
+        .values((
+            asset_note::asset.eq(asset),
+            asset_note::notes.eq(notes),
+            asset_note::updated_at.eq(&now),
+        ))
+        .on_conflict(asset_note::asset)
+        .do_update()
+        .set((asset_note::notes.eq(notes), asset_note::updated_at.eq(&now)))
+        .execute(&mut conn)
+    {
+        error!("Unable to save notes of {}: {}", asset, e);
+    }
+}
+
+/// Rate the asset, 0 removes the rating
+pub fn save_rating(asset: &str, rating: i32) {
+    let rating = rating.clamp(0, MAX_RATING);
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else { return };
+    let now = chrono::Utc::now().to_rfc3339();
+    if let Err(e) = diesel::insert_into(asset_note::table)
+        .values((
+            asset_note::asset.eq(asset),
+            asset_note::rating.eq(rating),
+            asset_note::updated_at.eq(&now),
+        ))
+        .on_conflict(asset_note::asset)
+        .do_update()
+        .set((
+            asset_note::rating.eq(rating),
+            asset_note::updated_at.eq(&now),
+        ))
+        .execute(&mut conn)
+    {
+        error!("Unable to save rating of {}: {}", asset, e);
+    }
+}
@@ -0,0 +1,282 @@
+use crate::schema::{asset_cache, asset_tag, collection, collection_asset};
+use diesel::sql_types::Text;
+use diesel::{Connection, ExpressionMethods, QueryDsl, RunQueryDsl};
+use log::error;
+use std::collections::{BTreeMap, HashMap};
+
+/// Tags, collections and ratings of all assets as `asset, kind, value` rows
+const USER_DATA: &str = "select asset, 'tag' as kind, tag as value from asset_tag
+    union all select asset, 'collection', collection from collection_asset
+    union all select asset, 'rating', cast(rating as text) from asset_note where rating > 0";
+
+/// Asset in the export, with its title so the file is readable without the database
+#[derive(Debug, serde::Serialize)]
+pub struct ExportedAsset {
+    pub id: String,
+    pub title: String,
+}
+
+/// User tags and collections of the library
+#[derive(Debug, Default, serde::Serialize)]
+pub struct Export {
+    /// Tags with the assets that have them
+    pub tags: BTreeMap<String, Vec<ExportedAsset>>,
+    pub collections: BTreeMap<String, Vec<ExportedAsset>>,
+}
+
+/// Everything the user added to an asset
+#[derive(Debug, Default, Clone)]
+pub struct UserData {
+    pub tags: Vec<String>,
+    pub collections: Vec<String>,
+    /// 1 to 5, 0 if the asset is not rated
+    pub rating: i32,
+}
+
+#[derive(diesel::QueryableByName)]
+struct UserDataRow {
+    #[diesel(sql_type = Text)]
+    asset: String,
+    #[diesel(sql_type = Text)]
+    kind: String,
+    #[diesel(sql_type = Text)]
+    value: String,
+}
+
+/// User data of all assets, loaded with a single query when the library is filled
+pub fn user_data() -> HashMap<String, UserData> {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else {
+        return HashMap::new();
+    };
+    match diesel::sql_query(format!("{USER_DATA} order by kind, value")).load(&mut conn) {
+        Ok(rows) => group_user_data(rows),
+        Err(e) => {
+            error!("Unable to load tags, collections and ratings: {}", e);
+            HashMap::new()
+        }
+    }
+}
+
+/// User data of one asset
+pub fn asset_user_data(asset: &str) -> UserData {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else {
+        return UserData::default();
+    };
+    match diesel::sql_query(format!(
+        "select asset, kind, value from ({USER_DATA}) where asset = ? order by kind, value"
+    ))
+    .bind::<Text, _>(asset)
+    .load(&mut conn)
+    {
+        Ok(rows) => group_user_data(rows).remove(asset).unwrap_or_default(),
+        Err(e) => {
+            error!(
+                "Unable to load tags, collections and rating of {}: {}",
+                asset, e
+            );
+            UserData::default()
+        }
+    }
+}
+
+fn group_user_data(rows: Vec<UserDataRow>) -> HashMap<String, UserData> {
+    let mut result: HashMap<String, UserData> = HashMap::new();
+    for row in rows {
+        let data = result.entry(row.asset).or_default();
+        match row.kind.as_str() {
+            "tag" => data.tags.push(row.value),
+            "collection" => data.collections.push(row.value),
+            "rating" => data.rating = row.value.parse().unwrap_or_default(),
+            _ => {}
+        }
+    }
+    result
+}
+
+pub fn asset_tags(asset: &str) -> Vec<String> {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else {
+        return Vec::new();
+    };
+    asset_tag::table
+        .filter(asset_tag::asset.eq(asset))
+        .order(asset_tag::tag.asc())
+        .select(asset_tag::tag)
+        .load(&mut conn)
+        .unwrap_or_default()
+}
+
+pub fn add_tag(asset: &str, tag: &str) {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else { return };
+    if let Err(e) = diesel::insert_or_ignore_into(asset_tag::table)
+        .values((asset_tag::asset.eq(asset), asset_tag::tag.eq(tag)))
+        .execute(&mut conn)
+    {
+        error!("Unable to tag {} with {}: {}", asset, tag, e);
+    }
+}
+
+pub fn remove_tag(asset: &str, tag: &str) {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else { return };
+    if let Err(e) = diesel::delete(
+        asset_tag::table
+            .filter(asset_tag::asset.eq(asset))
+            .filter(asset_tag::tag.eq(tag)),
+    )
+    .execute(&mut conn)
+    {
+        error!("Unable to remove tag {} from {}: {}", tag, asset, e);
+    }
+}
+
+/// Names of all collections
+pub fn collections() -> Vec<String> {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else {
+        return Vec::new();
+    };
+    collection::table
+        .order(collection::name.asc())
+        .select(collection::name)
+        .load(&mut conn)
+        .unwrap_or_default()
+}
+
+pub fn asset_collections(asset: &str) -> Vec<String> {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else {
+        return Vec::new();
+    };
+    collection_asset::table
+        .filter(collection_asset::asset.eq(asset))
+        .order(collection_asset::collection.asc())
+        .select(collection_asset::collection)
+        .load(&mut conn)
+        .unwrap_or_default()
+}
+
+/// Add the asset to the collection, creating the collection if it does not exist yet
+pub fn add_to_collection(name: &str, asset: &str) {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else { return };
+    if let Err(e) = conn.transaction(|conn| {
+        diesel::insert_or_ignore_into(collection::table)
+            .values((
+                collection::name.eq(name),
+                collection::added_at.eq(chrono::Utc::now().to_rfc3339()),
+            ))
+            .execute(conn)?;
+        diesel::insert_or_ignore_into(collection_asset::table)
+            .values((
+                collection_asset::collection.eq(name),
+                collection_asset::asset.eq(asset),
+            ))
+            .execute(conn)
+    }) {
+        error!("Unable to add {} to collection {}: {}", asset, name, e);
+    }
+}
+
+/// Remove the asset from the collection, the collection goes away with its last asset
+pub fn remove_from_collection(name: &str, asset: &str) {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else { return };
+    if let Err(e) = conn.transaction(|conn| {
+        diesel::delete(
+            collection_asset::table
+                .filter(collection_asset::collection.eq(name))
+                .filter(collection_asset::asset.eq(asset)),
+        )
+        .execute(conn)?;
+        let left: i64 = collection_asset::table
+            .filter(collection_asset::collection.eq(name))
+            .count()
+            .get_result(conn)?;
+        if left == 0 {
+            diesel::delete(collection::table.filter(collection::name.eq(name))).execute(conn)?;
+        }
+        Ok::<(), diesel::result::Error>(())
+    }) {
+        error!("Unable to remove {} from collection {}: {}", asset, name, e);
+    }
+}
+
+/// All tags and collections with the titles of their assets
+pub fn export() -> Export {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else {
+        return Export::default();
+    };
+    let titles: HashMap<String, String> = asset_cache::table
+        .select((asset_cache::id, asset_cache::title))
+        .load::<(String, String)>(&mut conn)
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    let asset = |id: String| ExportedAsset {
+        title: titles.get(&id).cloned().unwrap_or_default(),
+        id,
+    };
+    let mut export = Export::default();
+    let tags: Vec<(String, String)> = asset_tag::table
+        .order((asset_tag::tag.asc(), asset_tag::asset.asc()))
+        .select((asset_tag::tag, asset_tag::asset))
+        .load(&mut conn)
+        .unwrap_or_default();
+    for (tag, id) in tags {
+        export.tags.entry(tag).or_default().push(asset(id));
+    }
+    let names: Vec<String> = collection::table
+        .select(collection::name)
+        .load(&mut conn)
+        .unwrap_or_default();
+    for name in names {
+        export.collections.entry(name).or_default();
+    }
+    let members: Vec<(String, String)> = collection_asset::table
+        .order((
+            collection_asset::collection.asc(),
+            collection_asset::asset.asc(),
+        ))
+        .select((collection_asset::collection, collection_asset::asset))
+        .load(&mut conn)
+        .unwrap_or_default();
+    for (name, id) in members {
+        export.collections.entry(name).or_default().push(asset(id));
+    }
+    export
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(asset: &str, kind: &str, value: &str) -> UserDataRow {
+        UserDataRow {
+            asset: asset.to_string(),
+            kind: kind.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn rows_are_grouped_by_asset() {
+        let data = group_user_data(vec![
+            row("a", "collection", "props"),
+            row("a", "rating", "4"),
+            row("a", "tag", "nature"),
+            row("b", "tag", "sci-fi"),
+            row("a", "tag", "trees"),
+        ]);
+        assert_eq!(data["a"].tags, vec!["nature", "trees"]);
+        assert_eq!(data["a"].collections, vec!["props"]);
+        assert_eq!(data["a"].rating, 4);
+        assert_eq!(data["b"].tags, vec!["sci-fi"]);
+        assert!(data["b"].collections.is_empty());
+        assert_eq!(data["b"].rating, 0);
+    }
+}
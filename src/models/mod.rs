@@ -1,12 +1,17 @@
+pub mod asset_cache;
 pub mod asset_data;
+pub mod asset_notes;
 pub mod category_data;
+pub mod collections;
 pub mod database;
 pub mod engine_data;
 pub mod log_data;
 mod plugin_data;
 pub mod project_data;
+pub mod saved_search;
 
 use crate::config::APP_ID;
+use chrono::TimeZone;
 use egs_api::EpicGames;
 use gtk4::gio;
 use gtk4::glib::{MainContext, Receiver, Sender, UserDirectory, PRIORITY_DEFAULT};
@@ -165,6 +170,107 @@ impl Model {
         }
     }
 
+    /// Store a token and its expiration.
+    /// Returns false if the secret service could not be used and the token was stored in the settings instead.
+    pub fn save_secret(
+        &self,
+        secret_type: &str,
+        secret_name: &str,
+        secret: Option<String>,
+        expiration_name: &str,
+        expiration: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> bool {
+        let mut attributes = HashMap::new();
+        attributes.insert("application", crate::config::APP_ID);
+        attributes.insert("type", secret_type);
+        let d = expiration.map_or_else(
+            || {
+                chrono::Utc
+                    .timestamp_opt(0, 0)
+                    .unwrap()
+                    .to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+            },
+            |e| e.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        );
+        self.settings
+            .set_string(expiration_name, d.as_str())
+            .unwrap();
+
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        {
+            debug!("Saving {} secret", secret_name);
+            match &self.secret_service {
+                None => {
+                    self.save_insecure(secret_name, secret);
+                    return false;
+                }
+                Some(ss) => {
+                    // Clear the insecure storage if any
+                    self.settings
+                        .set_string(
+                            match secret_name {
+                                "eam_epic_games_token" => "token",
+                                "eam_epic_games_refresh_token" => "refresh-token",
+                                _ => {
+                                    return true;
+                                }
+                            },
+                            "",
+                        )
+                        .unwrap();
+                    match secret {
+                        None => {
+                            if let Err(e) = ss.get_any_collection().unwrap().create_item(
+                                secret_name,
+                                attributes,
+                                b"",
+                                true,
+                                "text/plain",
+                            ) {
+                                error!("Failed to save secret {}", e);
+                                self.save_insecure(secret_name, secret);
+                                return false;
+                            }
+                        }
+                        Some(rt) => {
+                            if let Err(e) = ss.get_any_collection().unwrap().create_item(
+                                secret_name,
+                                attributes,
+                                rt.as_bytes(),
+                                true,
+                                "text/plain",
+                            ) {
+                                error!("Failed to save secret {}", e);
+                                self.save_insecure(secret_name, Some(rt));
+                                return false;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        #[cfg(target_os = "windows")]
+        {
+            self.save_insecure(secret_name, secret);
+        }
+        true
+    }
+
+    fn save_insecure(&self, secret_name: &str, secret: Option<String>) {
+        self.settings
+            .set_string(
+                match secret_name {
+                    "eam_epic_games_token" => "token",
+                    "eam_epic_games_refresh_token" => "refresh-token",
+                    _ => {
+                        return;
+                    }
+                },
+                &secret.unwrap_or_default(),
+            )
+            .unwrap();
+    }
+
     fn load_secrets(&mut self) {
         #[cfg(any(target_os = "linux", target_os = "macos"))]
         {
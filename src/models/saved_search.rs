@@ -0,0 +1,48 @@
+use crate::schema::saved_search;
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use log::error;
+
+/// Saved searches as name and query, sorted by name
+pub fn saved_searches() -> Vec<(String, String)> {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else {
+        return Vec::new();
+    };
+    match saved_search::table
+        .order(saved_search::name.asc())
+        .select((saved_search::name, saved_search::query))
+        .load(&mut conn)
+    {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Unable to load saved searches: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Save the query under the name, an existing search with the same name is replaced
+pub fn save_search(name: &str, query: &str) {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else { return };
+    if let Err(e) = diesel::replace_into(saved_search::table)
+        .values((
+            saved_search::name.eq(name),
+            saved_search::query.eq(query),
+            saved_search::added_at.eq(chrono::Utc::now().to_rfc3339()),
+        ))
+        .execute(&mut conn)
+    {
+        error!("Unable to save search {}: {}", name, e);
+    }
+}
+
+pub fn remove_search(name: &str) {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else { return };
+    if let Err(e) =
+        diesel::delete(saved_search::table.filter(saved_search::name.eq(name))).execute(&mut conn)
+    {
+        error!("Unable to remove saved search {}: {}", name, e);
+    }
+}
@@ -17,3 +17,147 @@ diesel::table! {
         value -> Text,
     }
 }
+
+diesel::table! {
+    download_queue (id) {
+        id -> Text,
+        item_type -> Text,
+        label -> Text,
+        target -> Text,
+        asset_info -> Text,
+        actions -> Text,
+        total_size -> BigInt,
+        total_files -> BigInt,
+        added_at -> Text,
+    }
+}
+
+diesel::table! {
+    download_queue_chunk (download, guid) {
+        download -> Text,
+        guid -> Text,
+    }
+}
+
+diesel::table! {
+    download_queue_file (download, name) {
+        download -> Text,
+        name -> Text,
+    }
+}
+
+diesel::table! {
+    download_history (id) {
+        id -> Integer,
+        item_id -> Text,
+        item_type -> Text,
+        label -> Text,
+        release -> Text,
+        size -> BigInt,
+        started_at -> Text,
+        finished_at -> Text,
+        duration -> BigInt,
+        average_speed -> BigInt,
+        target -> Text,
+        targets -> Text,
+        result -> Text,
+        error -> Text,
+        asset_info -> Text,
+        actions -> Text,
+    }
+}
+
+diesel::table! {
+    project_files (project, path) {
+        project -> Text,
+        path -> Text,
+        release -> Text,
+        label -> Text,
+        backup -> Text,
+        replaced -> Bool,
+        added_at -> Text,
+    }
+}
+
+diesel::table! {
+    docker_layer_cache (digest) {
+        digest -> Text,
+        size -> BigInt,
+        last_used -> Text,
+    }
+}
+
+diesel::table! {
+    asset_cache (id) {
+        id -> Text,
+        asset_id -> Text,
+        title -> Text,
+        epic_asset -> Text,
+        asset_info -> Text,
+        updated_at -> Text,
+    }
+}
+
+diesel::table! {
+    asset_release (asset, id) {
+        asset -> Text,
+        id -> Text,
+        app_id -> Text,
+        version_title -> Text,
+        date_added -> Text,
+    }
+}
+
+diesel::table! {
+    asset_category (asset, path) {
+        asset -> Text,
+        path -> Text,
+    }
+}
+
+diesel::table! {
+    asset_key_image (asset, url) {
+        asset -> Text,
+        image_type -> Text,
+        url -> Text,
+        md5 -> Text,
+    }
+}
+
+diesel::table! {
+    saved_search (name) {
+        name -> Text,
+        query -> Text,
+        added_at -> Text,
+    }
+}
+
+diesel::table! {
+    asset_tag (asset, tag) {
+        asset -> Text,
+        tag -> Text,
+    }
+}
+
+diesel::table! {
+    collection (name) {
+        name -> Text,
+        added_at -> Text,
+    }
+}
+
+diesel::table! {
+    collection_asset (collection, asset) {
+        collection -> Text,
+        asset -> Text,
+    }
+}
+
+diesel::table! {
+    asset_note (asset) {
+        asset -> Text,
+        notes -> Text,
+        rating -> Integer,
+        updated_at -> Text,
+    }
+}
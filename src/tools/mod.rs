@@ -4,6 +4,7 @@ use ashpd;
 pub mod asset_info;
 pub mod epic_web;
 pub mod or;
+pub mod query;
 
 pub async fn open_directory(path: &str) {
     #[cfg(any(target_os = "linux", target_os = "macos"))]
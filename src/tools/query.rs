@@ -0,0 +1,588 @@
+use chrono::NaiveDate;
+use std::fmt;
+
+/// Fields that can qualify a search term, like `kind:plugin`
+pub const FIELDS: [&str; 9] = [
+    "seller",
+    "engine",
+    "platform",
+    "kind",
+    "downloaded",
+    "favorite",
+    "released",
+    "tag",
+    "collection",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    Before,
+    BeforeOrEqual,
+    After,
+    AfterOrEqual,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Field {
+    Seller(String),
+    Engine(String),
+    Platform(String),
+    Kind(String),
+    Downloaded(bool),
+    Favorite(bool),
+    Released(Comparison, NaiveDate),
+    Tag(String),
+    Collection(String),
+}
+
+/// Parsed search query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    /// Word or quoted phrase searched in the asset texts
+    Text(String),
+    Field(Field),
+    Not(Box<Query>),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+}
+
+/// Something a query can be evaluated on
+pub trait QueryTarget {
+    fn text_matches(&self, text: &str) -> bool;
+    fn field_matches(&self, field: &Field) -> bool;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Character offset in the query where the problem is
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at character {}", self.message, self.position + 1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Word(String),
+    Phrase(String),
+    Field(String),
+}
+
+impl Query {
+    /// Parse the search box text, terms next to each other have to match all
+    pub fn parse(input: &str) -> Result<Option<Self>, ParseError> {
+        let tokens = tokenize(input)?;
+        if tokens.is_empty() {
+            return Ok(None);
+        }
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            length: input.chars().count(),
+        };
+        let query = parser.or()?;
+        if let Some((_, position)) = parser.tokens.get(parser.position) {
+            return Err(ParseError {
+                position: *position,
+                message: "Unmatched closing parenthesis".to_string(),
+            });
+        }
+        Ok(Some(query))
+    }
+
+    pub fn matches(&self, target: &impl QueryTarget) -> bool {
+        match self {
+            Self::Text(text) => target.text_matches(text),
+            Self::Field(field) => target.field_matches(field),
+            Self::Not(query) => !query.matches(target),
+            Self::And(queries) => queries.iter().all(|q| q.matches(target)),
+            Self::Or(queries) => queries.iter().any(|q| q.matches(target)),
+        }
+    }
+
+    /// Free text terms of the query, to look them up in the search index once
+    pub fn texts(&self) -> Vec<String> {
+        match self {
+            Self::Text(text) => vec![text.clone()],
+            Self::Field(_) => Vec::new(),
+            Self::Not(query) => query.texts(),
+            Self::And(queries) | Self::Or(queries) => {
+                queries.iter().flat_map(Self::texts).collect()
+            }
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        match chars[i] {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => tokens.push((Token::Open, start)),
+            ')' => tokens.push((Token::Close, start)),
+            '&' => tokens.push((Token::And, start)),
+            '|' => tokens.push((Token::Or, start)),
+            '!' | '-' => tokens.push((Token::Not, start)),
+            '"' => {
+                let (phrase, end) = phrase(&chars, i)?;
+                tokens.push((Token::Phrase(phrase), start));
+                i = end;
+                continue;
+            }
+            _ => {
+                while i < chars.len()
+                    && !chars[i].is_whitespace()
+                    && !matches!(chars[i], '(' | ')' | '"' | ':' | '&' | '|')
+                {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                if i < chars.len() && chars[i] == ':' {
+                    let name = word.to_lowercase();
+                    if !FIELDS.contains(&name.as_str()) {
+                        return Err(ParseError {
+                            position: start,
+                            message: format!("Unknown field {word}"),
+                        });
+                    }
+                    tokens.push((Token::Field(name), start));
+                    i += 1;
+                } else {
+                    tokens.push((
+                        match word.as_str() {
+                            "AND" => Token::And,
+                            "OR" => Token::Or,
+                            "NOT" => Token::Not,
+                            _ => Token::Word(word),
+                        },
+                        start,
+                    ));
+                }
+                continue;
+            }
+        }
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+/// Read a quoted phrase starting at the quote, returns it with the position after the closing quote
+fn phrase(chars: &[char], start: usize) -> Result<(String, usize), ParseError> {
+    let mut i = start + 1;
+    let mut text = String::new();
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                text.push(chars[i + 1]);
+                i += 2;
+            }
+            '"' => return Ok((text, i + 1)),
+            c => {
+                text.push(c);
+                i += 1;
+            }
+        }
+    }
+    Err(ParseError {
+        position: start,
+        message: "Missing closing quote".to_string(),
+    })
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    /// Length of the input, where errors at the end are reported
+    length: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(t, _)| t)
+    }
+
+    fn advance(&mut self) -> Option<(Token, usize)> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        ParseError {
+            position: self
+                .tokens
+                .get(self.position)
+                .map_or(self.length, |(_, p)| *p),
+            message: message.to_string(),
+        }
+    }
+
+    fn or(&mut self) -> Result<Query, ParseError> {
+        let mut queries = vec![self.and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            queries.push(self.and()?);
+        }
+        Ok(if queries.len() == 1 {
+            queries.remove(0)
+        } else {
+            Query::Or(queries)
+        })
+    }
+
+    fn and(&mut self) -> Result<Query, ParseError> {
+        let mut queries = vec![self.unary()?];
+        loop {
+            match self.peek() {
+                Some(Token::And) => {
+                    self.position += 1;
+                }
+                None | Some(Token::Or | Token::Close) => break,
+                Some(_) => {}
+            }
+            queries.push(self.unary()?);
+        }
+        Ok(if queries.len() == 1 {
+            queries.remove(0)
+        } else {
+            Query::And(queries)
+        })
+    }
+
+    fn unary(&mut self) -> Result<Query, ParseError> {
+        if self.peek() == Some(&Token::Not) {
+            self.position += 1;
+            return Ok(Query::Not(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Query, ParseError> {
+        let error = self.error("Expected a search term");
+        match self.advance() {
+            Some((Token::Open, position)) => {
+                let query = self.or()?;
+                match self.advance() {
+                    Some((Token::Close, _)) => Ok(query),
+                    _ => Err(ParseError {
+                        position,
+                        message: "Missing closing parenthesis".to_string(),
+                    }),
+                }
+            }
+            Some((Token::Word(word) | Token::Phrase(word), _)) => Ok(Query::Text(word)),
+            Some((Token::Field(name), position)) => {
+                let value_error = self.error(&format!("Missing value for {name}"));
+                match self.advance() {
+                    Some((Token::Word(value) | Token::Phrase(value), value_position)) => {
+                        field(&name, &value)
+                            .map(Query::Field)
+                            .map_err(|message| ParseError {
+                                position: value_position,
+                                message,
+                            })
+                    }
+                    _ => Err(ParseError {
+                        position,
+                        ..value_error
+                    }),
+                }
+            }
+            _ => Err(error),
+        }
+    }
+}
+
+fn field(name: &str, value: &str) -> Result<Field, String> {
+    match name {
+        "seller" => Ok(Field::Seller(value.to_lowercase())),
+        "engine" => Ok(Field::Engine(value.trim_start_matches("UE_").to_string())),
+        "platform" => Ok(Field::Platform(value.to_lowercase())),
+        "kind" => {
+            let kind = value.to_lowercase();
+            let kind = kind.trim_end_matches('s');
+            if ["asset", "plugin", "project", "game", "engine"].contains(&kind) {
+                Ok(Field::Kind(kind.to_string()))
+            } else {
+                Err(format!("Unknown kind {value}"))
+            }
+        }
+        "downloaded" => yes_no(value).map(Field::Downloaded),
+        "favorite" => yes_no(value).map(Field::Favorite),
+        "released" => {
+            let (comparison, date) = if let Some(d) = value.strip_prefix(">=") {
+                (Comparison::AfterOrEqual, d)
+            } else if let Some(d) = value.strip_prefix("<=") {
+                (Comparison::BeforeOrEqual, d)
+            } else if let Some(d) = value.strip_prefix('>') {
+                (Comparison::After, d)
+            } else if let Some(d) = value.strip_prefix('<') {
+                (Comparison::Before, d)
+            } else {
+                (Comparison::Equal, value)
+            };
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map(|d| Field::Released(comparison, d))
+                .map_err(|_| format!("Invalid date {date}, use YYYY-MM-DD"))
+        }
+        "tag" => Ok(Field::Tag(value.to_lowercase())),
+        "collection" => Ok(Field::Collection(value.to_lowercase())),
+        _ => Err(format!("Unknown field {name}")),
+    }
+}
+
+fn yes_no(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "yes" | "true" | "1" => Ok(true),
+        "no" | "false" | "0" => Ok(false),
+        _ => Err(format!("Expected yes or no, not {value}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Query {
+        Query::parse(input).unwrap().unwrap()
+    }
+
+    fn parse_field(input: &str) -> Field {
+        match parse(input) {
+            Query::Field(field) => field,
+            q => panic!("{input} is not a field: {q:?}"),
+        }
+    }
+
+    fn error(input: &str) -> (usize, String) {
+        let e = Query::parse(input).unwrap_err();
+        (e.position, e.message)
+    }
+
+    fn text(t: &str) -> Query {
+        Query::Text(t.to_string())
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn empty_query() {
+        assert_eq!(Query::parse(""), Ok(None));
+        assert_eq!(Query::parse("   "), Ok(None));
+    }
+
+    #[test]
+    fn text_fields() {
+        assert_eq!(
+            parse_field("seller:Epic"),
+            Field::Seller("epic".to_string())
+        );
+        assert_eq!(
+            parse_field("platform:Windows"),
+            Field::Platform("windows".to_string())
+        );
+        assert_eq!(parse_field("tag:Nature"), Field::Tag("nature".to_string()));
+        assert_eq!(
+            parse_field("collection:Props"),
+            Field::Collection("props".to_string())
+        );
+        assert_eq!(
+            parse_field("SELLER:epic"),
+            Field::Seller("epic".to_string())
+        );
+    }
+
+    #[test]
+    fn engine_field() {
+        assert_eq!(parse_field("engine:5.1"), Field::Engine("5.1".to_string()));
+        assert_eq!(
+            parse_field("engine:UE_5.1"),
+            Field::Engine("5.1".to_string())
+        );
+    }
+
+    #[test]
+    fn kind_field() {
+        assert_eq!(
+            parse_field("kind:plugin"),
+            Field::Kind("plugin".to_string())
+        );
+        assert_eq!(parse_field("kind:Assets"), Field::Kind("asset".to_string()));
+        assert_eq!(error("kind:robot"), (5, "Unknown kind robot".to_string()));
+    }
+
+    #[test]
+    fn yes_no_fields() {
+        assert_eq!(parse_field("downloaded:yes"), Field::Downloaded(true));
+        assert_eq!(parse_field("downloaded:0"), Field::Downloaded(false));
+        assert_eq!(parse_field("favorite:TRUE"), Field::Favorite(true));
+        assert_eq!(parse_field("favorite:no"), Field::Favorite(false));
+        assert_eq!(
+            error("favorite:maybe"),
+            (9, "Expected yes or no, not maybe".to_string())
+        );
+    }
+
+    #[test]
+    fn released_field() {
+        assert_eq!(
+            parse_field("released:2022-03-04"),
+            Field::Released(Comparison::Equal, date(2022, 3, 4))
+        );
+        assert_eq!(
+            parse_field("released:<2022-03-04"),
+            Field::Released(Comparison::Before, date(2022, 3, 4))
+        );
+        assert_eq!(
+            parse_field("released:<=2022-03-04"),
+            Field::Released(Comparison::BeforeOrEqual, date(2022, 3, 4))
+        );
+        assert_eq!(
+            parse_field("released:>2022-03-04"),
+            Field::Released(Comparison::After, date(2022, 3, 4))
+        );
+        assert_eq!(
+            parse_field("released:>=2022-03-04"),
+            Field::Released(Comparison::AfterOrEqual, date(2022, 3, 4))
+        );
+    }
+
+    #[test]
+    fn released_range() {
+        assert_eq!(
+            parse("released:>=2021-01-01 released:<2022-01-01"),
+            Query::And(vec![
+                Query::Field(Field::Released(Comparison::AfterOrEqual, date(2021, 1, 1))),
+                Query::Field(Field::Released(Comparison::Before, date(2022, 1, 1))),
+            ])
+        );
+    }
+
+    #[test]
+    fn invalid_dates() {
+        assert_eq!(
+            error("released:2022-13-01"),
+            (9, "Invalid date 2022-13-01, use YYYY-MM-DD".to_string())
+        );
+        assert_eq!(
+            error("released:>yesterday"),
+            (9, "Invalid date yesterday, use YYYY-MM-DD".to_string())
+        );
+    }
+
+    #[test]
+    fn quoted_values() {
+        assert_eq!(parse("\"open world\""), text("open world"));
+        assert_eq!(
+            parse_field("tag:\"Open World\""),
+            Field::Tag("open world".to_string())
+        );
+        assert_eq!(parse(r#""say \"hi\"""#), text("say \"hi\""));
+        assert_eq!(parse("\"a:b\" c"), Query::And(vec![text("a:b"), text("c")]));
+    }
+
+    #[test]
+    fn negation() {
+        let not_plugin = Query::Not(Box::new(Query::Field(Field::Kind("plugin".to_string()))));
+        assert_eq!(parse("-kind:plugin"), not_plugin);
+        assert_eq!(parse("!kind:plugin"), not_plugin);
+        assert_eq!(parse("NOT kind:plugin"), not_plugin);
+        assert_eq!(
+            parse("tree -\"dead tree\""),
+            Query::And(vec![text("tree"), Query::Not(Box::new(text("dead tree")))])
+        );
+        assert_eq!(parse("sci-fi"), text("sci-fi"));
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(
+            parse("a b | c"),
+            Query::Or(vec![Query::And(vec![text("a"), text("b")]), text("c")])
+        );
+        assert_eq!(
+            parse("a & (b OR c)"),
+            Query::And(vec![text("a"), Query::Or(vec![text("b"), text("c")])])
+        );
+        assert_eq!(parse("a AND b"), Query::And(vec![text("a"), text("b")]));
+        assert_eq!(parse("(a)"), text("a"));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(error("foo:bar"), (0, "Unknown field foo".to_string()));
+        assert_eq!(error("a \"open"), (2, "Missing closing quote".to_string()));
+        assert_eq!(
+            error("(a b"),
+            (0, "Missing closing parenthesis".to_string())
+        );
+        assert_eq!(
+            error("a )"),
+            (2, "Unmatched closing parenthesis".to_string())
+        );
+        assert_eq!(error("kind:"), (0, "Missing value for kind".to_string()));
+        assert_eq!(error("a |"), (3, "Expected a search term".to_string()));
+        assert_eq!(error("a & )"), (4, "Expected a search term".to_string()));
+    }
+
+    #[test]
+    fn error_display() {
+        assert_eq!(
+            Query::parse("foo:bar").unwrap_err().to_string(),
+            "Unknown field foo at character 1"
+        );
+    }
+
+    #[test]
+    fn texts_of_query() {
+        assert_eq!(
+            parse("a -kind:plugin (\"b c\" | d)").texts(),
+            vec!["a".to_string(), "b c".to_string(), "d".to_string()]
+        );
+    }
+
+    struct Asset {
+        title: &'static str,
+        kind: &'static str,
+    }
+
+    impl QueryTarget for Asset {
+        fn text_matches(&self, text: &str) -> bool {
+            self.title.contains(text)
+        }
+
+        fn field_matches(&self, field: &Field) -> bool {
+            matches!(field, Field::Kind(k) if k == self.kind)
+        }
+    }
+
+    #[test]
+    fn matching() {
+        let tree = Asset {
+            title: "oak tree",
+            kind: "asset",
+        };
+        assert!(parse("tree kind:asset").matches(&tree));
+        assert!(!parse("tree -kind:asset").matches(&tree));
+        assert!(parse("rock | oak").matches(&tree));
+        assert!(!parse("rock | kind:plugin").matches(&tree));
+    }
+}
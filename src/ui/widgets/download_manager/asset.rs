@@ -1,33 +1,45 @@
+use crate::download::error::DownloadError;
+use crate::download::pipeline::{self, ChunkFetch, DownloadedFile};
+use crate::download::ThreadMessages;
 use crate::tools::asset_info::Search;
+use crate::ui::widgets::download_manager::conflict::{copy_files, ConflictPolicy, Conflicts};
+use crate::ui::widgets::download_manager::download_item::ItemType;
+use crate::ui::widgets::download_manager::error::Failures;
+use crate::ui::widgets::download_manager::mirrors::Mirrors;
+use crate::ui::widgets::download_manager::priority::Priority;
+use crate::ui::widgets::download_manager::queue::{Queue, QueuedDownload};
+use crate::ui::widgets::download_manager::space::DiskSpace;
 use crate::ui::widgets::download_manager::Msg::CancelChunk;
-use crate::ui::widgets::download_manager::{Msg, PostDownloadAction, ThreadMessages};
-use egs_api::api::types::chunk::Chunk;
+use crate::ui::widgets::download_manager::{Msg, PostDownloadAction};
+use egs_api::api::types::download_manifest::FileChunkPart;
 use glib::clone;
 use gtk4::glib;
 use gtk4::glib::Sender;
 use gtk4::subclass::prelude::*;
 use gtk4::{self, prelude::*};
 use log::{debug, error, info, warn};
-use rand::Rng;
 use reqwest::Url;
-use sha1::digest::core_api::CoreWrapper;
-use sha1::{Digest, Sha1, Sha1Core};
-use std::ffi::OsString;
-use std::fs::File;
-use std::io::{Read, Write};
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
 
+/// Result of comparing a downloaded asset with its saved manifest
 #[derive(Default, Debug, Clone)]
-pub struct DownloadedFile {
-    pub asset: String,
-    pub release: String,
-    pub name: String,
-    pub chunks: Vec<egs_api::api::types::download_manifest::FileChunkPart>,
-    pub finished_chunks: Vec<egs_api::api::types::download_manifest::FileChunkPart>,
-    hash: String,
+pub struct VerificationReport {
+    pub missing: Vec<String>,
+    pub corrupt: Vec<String>,
+    pub extra: Vec<String>,
 }
 
+impl VerificationReport {
+    /// Repair is needed when a file from the manifest is missing or does not match its hash
+    pub fn needs_repair(&self) -> bool {
+        !self.missing.is_empty() || !self.corrupt.is_empty()
+    }
+}
+
+/// How many times a file failing hash validation is downloaded again before giving up
+const MAX_FILE_RETRIES: u32 = 3;
+
 pub trait Asset {
     /// Add an asset for download
     /// This is the first step in the process
@@ -41,6 +53,23 @@ pub trait Asset {
         unimplemented!()
     }
 
+    /// Download only `files` of an asset again, the manifest saved with the asset lists their chunks
+    fn repair_asset_download(
+        &self,
+        _release_id: String,
+        _asset: egs_api::api::types::asset_info::AssetInfo,
+        _target: &Option<String>,
+        _manifest: egs_api::api::types::download_manifest::DownloadManifest,
+        _files: Vec<String>,
+    ) {
+        unimplemented!()
+    }
+
+    /// Show an asset download saved by a previous run
+    fn restore_asset_download(&self, _queued: &QueuedDownload) {
+        unimplemented!()
+    }
+
     fn download_asset_manifest(
         &self,
         _release_id: String,
@@ -86,6 +115,11 @@ pub trait Asset {
         unimplemented!()
     }
 
+    /// Start downloading a chunk picked by the scheduler
+    fn start_chunk_download(&self, _link: reqwest::Url, _p: PathBuf, _g: String) {
+        unimplemented!()
+    }
+
     fn remove_chunk(&self, _p: PathBuf, _g: String) {
         unimplemented!()
     }
@@ -94,6 +128,16 @@ pub trait Asset {
         unimplemented!()
     }
 
+    /// Drop the chunks of a file that failed hash validation and download them again
+    fn file_hash_failed(&self, _file: &str, _details: DownloadedFile) {
+        unimplemented!()
+    }
+
+    /// Download a chunk of a corrupted file again once no other file still needs its current data
+    fn redownload_corrupted_chunk(&self, _guid: &str) {
+        unimplemented!()
+    }
+
     fn asset_finished(&self, _item: &super::download_item::EpicDownloadItem) {
         unimplemented!()
     }
@@ -124,12 +168,35 @@ pub trait Asset {
         unimplemented!()
     }
 
+    /// Process files that failed again and continue the download
+    fn retry_asset_download(&self, _asset: &str) {
+        unimplemented!()
+    }
+
     fn cancel_asset_download(&self, _asset: String) {
         unimplemented!()
     }
 }
 
 trait AssetPriv {
+    fn create_asset_item(
+        &self,
+        _release_id: &str,
+        _asset: &egs_api::api::types::asset_info::AssetInfo,
+        _target: &Option<String>,
+        _actions: Option<Vec<super::PostDownloadAction>>,
+    ) -> Option<super::download_item::EpicDownloadItem> {
+        unimplemented!()
+    }
+
+    fn fetch_asset_manifest(
+        &self,
+        _release_id: String,
+        _asset: egs_api::api::types::asset_info::AssetInfo,
+    ) {
+        unimplemented!()
+    }
+
     fn load_thumbnail(
         &self,
         _id: String,
@@ -160,66 +227,64 @@ impl Asset for super::EpicDownloadManager {
     ) {
         debug!("Adding download: {:?}", asset.title);
 
-        let self_ = self.imp();
-
-        let mut items = self_.download_items.borrow_mut();
-        let item = match items.get_mut(&release_id) {
-            None => {
-                let item = super::download_item::EpicDownloadItem::new();
-                debug!("Adding item to the list under: {}", release_id);
-                items.insert(release_id.clone(), item.clone());
-                item
-            }
-            Some(_) => {
-                // Item is already downloading do nothing
-                return;
-            }
-        };
-        if let Some(w) = self_.window.get() {
-            item.set_window(w);
-        }
-        item.set_download_manager(self);
-        if let Some(actions) = actions {
-            item.add_actions(&actions);
+        let Some(item) = self.create_asset_item(&release_id, &asset, target, actions) else {
+            // Item is already downloading do nothing
+            return;
         };
-        item.set_property(
-            "item-type",
-            crate::ui::widgets::download_manager::download_item::ItemType::Asset,
+        self.queue_add(
+            &release_id,
+            ItemType::Asset,
+            &asset.title,
+            target,
+            Some(&asset),
+            &item.actions(),
         );
-        item.set_property("asset", asset.id.clone());
-        item.set_property("release", release_id.clone());
-        item.set_property("label", asset.title.clone());
-        item.set_property("target", target.clone());
-        item.set_property("status", "initializing...".to_string());
-        self.load_thumbnail(release_id.clone(), asset.thumbnail());
-
-        self_.downloads.append(&item);
-
-        self.set_property("has-items", self_.downloads.first_child().is_some());
-
-        item.connect_local(
-            "finished",
-            false,
-            clone!(@weak self as edm, @weak item => @default-return None, move |_| {
-                edm.finish(&item);
-                None
-            }),
-        );
-
-        let (sender, receiver) = gtk4::glib::MainContext::channel(gtk4::glib::PRIORITY_DEFAULT);
+        self.fetch_asset_manifest(release_id, asset);
+    }
 
-        receiver.attach(
-            None,
-            clone!(@weak self as download_manager => @default-panic, move |(id, manifest)| {
-                let self_ = download_manager.imp();
-                let sender = self_.sender.clone();
-                sender.send(super::Msg::StartAssetDownload(id, manifest)).unwrap();
-                glib::Continue(true)
-            }),
+    fn repair_asset_download(
+        &self,
+        release_id: String,
+        asset: egs_api::api::types::asset_info::AssetInfo,
+        target: &Option<String>,
+        manifest: egs_api::api::types::download_manifest::DownloadManifest,
+        files: Vec<String>,
+    ) {
+        debug!("Repairing {} files of {:?}", files.len(), asset.title);
+        let self_ = self.imp();
+        let Some(item) = self.create_asset_item(&release_id, &asset, target, None) else {
+            return;
+        };
+        self.queue_add(
+            &release_id,
+            ItemType::Asset,
+            &asset.title,
+            target,
+            Some(&asset),
+            &item.actions(),
         );
+        self_
+            .repair_files
+            .borrow_mut()
+            .insert(release_id.clone(), files.into_iter().collect());
+        self_
+            .sender
+            .send(Msg::StartAssetDownload(release_id, vec![manifest]))
+            .ok();
+    }
 
-        self.download_asset_manifest(release_id, asset, sender);
+    fn restore_asset_download(&self, queued: &QueuedDownload) {
+        let Some(asset) = &queued.asset else { return };
+        let Some(item) =
+            self.create_asset_item(&queued.id, asset, &queued.target, Some(queued.actions.clone()))
+        else {
+            return;
+        };
+        item.set_total_size(queued.total_size);
+        item.set_total_files(queued.total_files);
+        item.set_restored(queued.finished_files);
     }
+
     fn download_asset_manifest(
         &self,
         release_id: String,
@@ -240,29 +305,9 @@ impl Asset for super::EpicDownloadManager {
                         return;
                     }
                 }
-                let start = std::time::Instant::now();
-                if let Some(release_info) = asset.release_info(&release_id) {
-                    if let Some(manifest) =
-                        tokio::runtime::Runtime::new()
-                            .unwrap()
-                            .block_on(eg.asset_manifest(
-                                None,
-                                None,
-                                Some(asset.namespace),
-                                Some(asset.id),
-                                Some(release_info.app_id.unwrap_or_default()),
-                            ))
-                    {
-                        debug!("Got asset manifest: {:?}", manifest);
-                        let d = tokio::runtime::Runtime::new()
-                            .unwrap()
-                            .block_on(eg.asset_download_manifests(manifest));
-                        debug!("Got asset download manifests for {}", id);
-                        sender.send((id, d)).unwrap();
-                        // TODO cache download manifest
-                    };
-                }
-                debug!("Download Manifest requests took {:?}", start.elapsed());
+                let d = request_download_manifests(&mut eg, &release_id, &asset);
+                // An empty list fails the download
+                sender.send((id, d)).ok();
             });
         }
     }
@@ -277,22 +322,26 @@ impl Asset for super::EpicDownloadManager {
         let self_ = self.imp();
         let Some(item) = self.get_item(id) else { return };
         if dm.is_empty() {
-            item.set_property("status", "Failed to get download manifests".to_string());
+            self.download_failed(
+                id,
+                &DownloadError::Http("Unable to get download manifests".to_string()),
+            );
             return;
         }
-        let mut targets: Vec<(String, bool)> = Vec::new();
+        let mut targets: Vec<(String, ConflictPolicy)> = Vec::new();
         let mut to_vault = true;
         {
             let actions = item.actions();
 
             for act in actions {
                 match act {
-                    PostDownloadAction::Copy(t, over) => {
-                        targets.push((t.clone(), over));
+                    PostDownloadAction::Copy(t, policy) => {
+                        targets.push((t.clone(), policy));
                     }
                     PostDownloadAction::NoVault => {
                         to_vault = false;
                     }
+                    PostDownloadAction::RunCommand(_) => {}
                 }
             }
         };
@@ -308,40 +357,82 @@ impl Asset for super::EpicDownloadManager {
             v.push("data");
             v
         } else {
-            PathBuf::from_str(&targets.pop().unwrap().0).unwrap()
+            let Some((t, _)) = targets.pop() else { return };
+            PathBuf::from(t)
         };
+        let vaults = self_.settings.strv("unreal-vault-directories");
+        let mut temp = PathBuf::from(vaults.first().map_or_else(
+            || {
+                self_
+                    .settings
+                    .string("temporary-download-directory")
+                    .to_string()
+            },
+            std::string::ToString::to_string,
+        ));
+        temp.push(&dm[0].app_name_string);
+        temp.push("temp");
+        let mut local = HashMap::new();
+        if let Some(mut sources) = self_.delta_sources.borrow_mut().remove(id) {
+            sources.retain(|s| s.ne(&target));
+            local = pipeline::local_chunk_parts(&sources);
+        }
+        let mut copies: Vec<PathBuf> = vec![target.clone()];
+        copies.extend(targets.iter().map(|(t, _)| PathBuf::from(t)));
+        let needs = pipeline::asset_space_needs(
+            &dm[0],
+            &temp,
+            &copies,
+            pipeline::reused_bytes(&dm[0], &local),
+        );
+        let written = dm[0].files().values().map(|f| f.size()).sum::<u128>();
+        if !self.reserve_space(id, needs, u64::try_from(written).unwrap_or(u64::MAX)) {
+            return;
+        }
+
         let t = target.clone();
         let manifest = dm[0].clone();
+        let sender = self_.sender.clone();
+        let r_id = id.to_string();
         // Create target directory in the vault and save manifests to it
-        self_
-            .download_pool
-            .execute(move || save_asset_manifest(&t, &manifest));
+        self_.download_pool.execute(move || {
+            if let Err(e) = pipeline::save_asset_manifest(&t, &manifest) {
+                sender.send(Msg::DownloadFailed(r_id, e)).ok();
+            }
+        });
 
         item.set_property("status", "waiting for download slot".to_string());
         item.set_total_size(dm[0].total_download_size());
         item.set_total_files(dm[0].file_manifest_list.len() as u64);
+        self.queue_set_totals(
+            id,
+            dm[0].total_download_size(),
+            dm[0].file_manifest_list.len() as u64,
+        );
         item.set_property("path", target.as_path().display().to_string());
 
+        if !local.is_empty() {
+            info!(
+                "Reusing {} chunks from already downloaded releases for {}",
+                local.len(),
+                id
+            );
+            self_
+                .local_chunks
+                .borrow_mut()
+                .insert(id.to_string(), local);
+        }
+
         // consolidate manifests
-        for manifest in dm {
-            for m in manifest.files().values() {
-                for chunk in m.file_chunk_parts.clone() {
-                    if let Some(url) = chunk.link {
-                        let mut chunks = self_.chunk_urls.borrow_mut();
-                        match chunks.get_mut(&chunk.guid) {
-                            None => {
-                                chunks.insert(chunk.guid, vec![url.clone()]);
-                            }
-                            Some(v) => {
-                                v.push(url.clone());
-                            }
-                        }
-                    }
-                }
+        {
+            let mut chunks = self_.chunk_urls.borrow_mut();
+            for (guid, urls) in pipeline::chunk_urls(dm) {
+                chunks.entry(guid).or_default().extend(urls);
             }
         }
 
         item.set_property("status", "validating".to_string());
+        let mut pending: Vec<egs_api::api::types::download_manifest::FileManifestList> = Vec::new();
         for (filename, manifest) in dm[0].files() {
             info!("Starting download of {} file {}", id, filename);
             let r_id = id.to_string();
@@ -350,11 +441,42 @@ impl Asset for super::EpicDownloadManager {
             let sender = self_.sender.clone();
 
             let m = manifest.clone();
-            let full_path = target.clone().as_path().join(filename);
+            let full_path = target.clone().as_path().join(&filename);
+            let repair = self_
+                .repair_files
+                .borrow()
+                .get(id)
+                .map(|files| files.contains(&filename));
+            // Files finished before the application was closed and files a repair keeps are not verified again
+            if repair == Some(false)
+                || self.stored_file_finished(id, &filename, &full_path, m.size())
+            {
+                debug!("Skipping {}", filename);
+                item.add_downloaded_size(m.size());
+                self.space_written(id, u64::try_from(m.size()).unwrap_or(u64::MAX));
+                sender.send(Msg::FileExtracted(r_id)).ok();
+                continue;
+            }
+            pending.push(manifest);
+            if repair == Some(true) {
+                sender
+                    .send(Msg::PerformAssetDownload(r_id, r_name, f_name, m))
+                    .ok();
+                continue;
+            }
             self_.download_pool.execute(move || {
                 initiate_file_download(&r_id, &r_name, &f_name, &sender, m, &full_path);
             });
         }
+        // Chunks read from other releases count as downloaded
+        if let Some(local) = self_.local_chunks.borrow().get(id) {
+            for (guid, size) in pipeline::reused_chunks(&dm[0], &pending, local) {
+                self_
+                    .sender
+                    .send(Msg::ChunkDownloadProgress(guid, size, false))
+                    .ok();
+            }
+        }
     }
     /// Download individual files
     /// This is a third step in the asset download process
@@ -381,19 +503,23 @@ impl Asset for super::EpicDownloadManager {
         target.push(release.clone());
         target.push("temp");
         let full_filename = format!("{id}/{release}/{filename}");
-        self_.downloaded_files.borrow_mut().insert(
-            full_filename.clone(),
-            DownloadedFile {
-                asset: id.clone(),
-                release,
-                name: filename,
-                chunks: manifest.file_chunk_parts.clone(),
-                finished_chunks: vec![],
-                hash: manifest.file_hash,
-            },
-        );
+        let mut file = DownloadedFile::from_manifest(&id, &release, &filename, &manifest);
+        if let Some(local) = self_.local_chunks.borrow().get(&id) {
+            file = file.with_local_parts(local);
+        }
+        let missing: Vec<FileChunkPart> = file.missing_chunks().into_iter().cloned().collect();
+        let rebuild_from_local = file.is_local();
+        self_
+            .downloaded_files
+            .borrow_mut()
+            .insert(full_filename.clone(), file.clone());
+        if rebuild_from_local {
+            debug!("Rebuilding {} from already downloaded files", full_filename);
+            self.extract_file_from_chunks(&mut Vec::new(), &full_filename, &mut file);
+            return;
+        }
         let sender = self_.sender.clone();
-        for chunk in manifest.file_chunk_parts {
+        for chunk in missing {
             // perform chunk download make sure we do not download the same chunk twice
             self_
                 .asset_guids
@@ -408,13 +534,25 @@ impl Asset for super::EpicDownloadManager {
                     let mut p = target.clone();
                     let g = chunk.guid.clone();
                     p.push(format!("{g}.chunk"));
-                    sender
-                        .send(super::Msg::RedownloadChunk(
-                            reqwest::Url::parse("unix:/").unwrap(),
-                            p,
-                            g,
-                        ))
-                        .unwrap();
+                    // Reuse chunks finished before the application was closed
+                    if let Some(size) = self.stored_chunk_size(&id, &g, &p) {
+                        debug!("Reusing already downloaded chunk {}", g);
+                        sender
+                            .send(super::Msg::ChunkDownloadProgress(
+                                g.clone(),
+                                u128::from(size),
+                                false,
+                            ))
+                            .ok();
+                        sender
+                            .send(super::Msg::ChunkDownloadProgress(g, u128::from(size), true))
+                            .ok();
+                        continue;
+                    }
+                    // A link without a host starts with the best known host
+                    if let Ok(link) = reqwest::Url::parse("unix:/") {
+                        sender.send(super::Msg::RedownloadChunk(link, p, g)).ok();
+                    }
                 }
                 Some(files) => files.push(full_filename.clone()),
             }
@@ -423,146 +561,46 @@ impl Asset for super::EpicDownloadManager {
 
     fn redownload_chunk(&self, link: &reqwest::Url, p: PathBuf, g: &str) {
         let self_ = self.imp();
-        let sender = self_.sender.clone();
-        let mut chunks = self_.chunk_urls.borrow_mut();
-        match chunks.get_mut(g) {
+        if link.has_host() {
+            self.retry_chunk(link, p, g);
+            return;
+        }
+        // First download of the chunk, start with the best known host
+        match self.best_chunk_url(g, None) {
             None => {
-                // Unable to get chunk urls
-                sender
-                    .send(super::Msg::PerformChunkDownload(
-                        link.clone(),
-                        p,
-                        g.to_string(),
-                    ))
-                    .unwrap();
+                error!("No URL to download chunk {}", g);
             }
-            Some(v) => {
-                v.retain(|x| !x.eq(link));
-                if v.is_empty() {
-                    // No other URL available, redownloading
-                    //TODO: This has the potential to loop forever
-                    sender
-                        .send(super::Msg::PerformChunkDownload(
-                            link.clone(),
-                            p.clone(),
-                            g.to_string(),
-                        ))
-                        .unwrap();
-                };
-                let mut rng = rand::thread_rng();
-                let index = rng.gen_range(0..v.len());
-                let new: Option<&reqwest::Url> = v.get(index);
-                match new {
-                    None => {
-                        // Unable to get random URL, retrying the same one
-                        sender
-                            .send(super::Msg::PerformChunkDownload(
-                                link.clone(),
-                                p,
-                                g.to_string(),
-                            ))
-                            .unwrap();
-                    }
-                    Some(u) => {
-                        // Using new url to redownload the chunk
-                        sender
-                            .send(super::Msg::PerformChunkDownload(
-                                u.clone(),
-                                p,
-                                g.to_string(),
-                            ))
-                            .unwrap();
-                    }
-                }
+            Some(u) => {
+                self_
+                    .sender
+                    .send(super::Msg::PerformChunkDownload(u, p, g.to_string()))
+                    .ok();
             }
         }
     }
 
     /// Download Chunks
     fn download_chunk(&self, link: reqwest::Url, p: PathBuf, g: String) {
-        let self_ = self.imp();
         if !link.has_host() {
             return;
         }
-        let (send, recv) = std::sync::mpsc::channel::<super::ThreadMessages>();
+        self.queue_chunk(link, p, g);
+    }
+
+    fn start_chunk_download(&self, link: reqwest::Url, p: PathBuf, g: String) {
+        let self_ = self.imp();
+        let (send, recv) = std::sync::mpsc::channel::<ThreadMessages>();
         self.add_thread_sender(g.clone(), send);
         let sender = self_.sender.clone();
+        let limiter = self_.limiter.clone();
         self_.download_pool.execute(move || {
-            if let Ok(w) = crate::RUNNING.read() {
-                if !*w {
-                    return;
-                }
-            };
-            if let Ok(m) = recv.try_recv() {
-                process_thread_message(&link, &p, &g, &sender, &m);
-                return;
-            }
-            debug!(
-                "Downloading chunk {} from {} to {:?}",
-                g,
-                link.to_string(),
-                p
-            );
-            std::fs::create_dir_all(p.parent().unwrap()).unwrap();
-            let mut client = match reqwest::blocking::get(link.clone()) {
-                Ok(c) => c,
-                Err(e) => {
-                    error!("Failed to start chunk download, trying again later: {}", e);
-                    sender
-                        .send(super::Msg::RedownloadChunk(
-                            link.clone(),
-                            p.clone(),
-                            g.clone(),
-                        ))
-                        .unwrap();
-                    return;
-                }
-            };
-            let mut buffer: [u8; 1024] = [0; 1024];
-            let mut downloaded: u128 = 0;
-            let mut file = File::create(&p).unwrap();
-            loop {
-                if let Ok(m) = recv.try_recv() {
-                    process_thread_message(&link, &p, &g, &sender, &m);
-                    return;
-                }
-                match client.read(&mut buffer) {
-                    Ok(size) => {
-                        if let Ok(m) = recv.try_recv() {
-                            process_thread_message(&link, &p, &g, &sender, &m);
-                            return;
-                        }
-                        if size > 0 {
-                            downloaded += size as u128;
-                            file.write_all(&buffer[0..size]).unwrap();
-                            sender
-                                .send(super::Msg::ChunkDownloadProgress(
-                                    g.clone(),
-                                    size as u128,
-                                    false,
-                                ))
-                                .unwrap();
-                        } else {
-                            break;
-                        }
-                    }
-                    Err(e) => {
-                        error!("Download error: {:?}", e);
-                        break;
-                    }
-                }
-            }
-            sender
-                .send(super::Msg::ChunkDownloadProgress(
-                    g.clone(),
-                    downloaded,
-                    true,
-                ))
-                .unwrap();
+            fetch_chunk(&link, &p, &g, &recv, &sender, &limiter);
+            sender.send(super::Msg::ChunkSlotFreed).ok();
         });
     }
 
     fn remove_chunk(&self, path: PathBuf, _g: String) {
+        self.imp().chunk_cache.forget(&path);
         if let Err(e) = std::fs::remove_file(&path) {
             warn!("Unable to remove chunk {:?}", e);
         };
@@ -581,9 +619,17 @@ impl Asset for super::EpicDownloadManager {
             let chunks = self_.downloaded_chunks.borrow();
             self_.chunk_urls.borrow_mut().remove(guid);
             if let Some(files) = chunks.get(guid) {
+                let mut saved: Vec<String> = Vec::new();
                 for file in files {
                     debug!("Affected files: {}", file);
                     if let Some(f) = self_.downloaded_files.borrow_mut().get_mut(file) {
+                        if !saved.contains(&f.asset) {
+                            self.queue_chunk_finished(&f.asset, guid);
+                            saved.push(f.asset.clone());
+                        }
+                        if f.finished_chunks.iter().any(|c| c.guid == guid) {
+                            continue;
+                        }
                         for chunk in &f.chunks {
                             if chunk.guid == guid {
                                 f.finished_chunks.push(chunk.clone());
@@ -597,25 +643,167 @@ impl Asset for super::EpicDownloadManager {
                 }
             }
         } else {
-            let chunks = self_.downloaded_chunks.borrow();
-            if let Some(files) = chunks.get(guid) {
-                for file in files {
-                    if let Some(f) = self_.downloaded_files.borrow_mut().get_mut(file) {
-                        let Some(item) = self.get_item(&f.asset) else {
-                                                             break;
-                                                    };
-                        item.add_downloaded_size(progress);
-                        self.emit_by_name::<()>("tick", &[]);
-                        break;
-                    }
+            let asset = self_
+                .downloaded_chunks
+                .borrow()
+                .get(guid)
+                .and_then(|files| {
+                    let downloaded = self_.downloaded_files.borrow();
+                    files
+                        .iter()
+                        .find_map(|file| downloaded.get(file).map(|f| f.asset.clone()))
+                })
+                .or_else(|| {
+                    // Chunks read from other releases are not downloaded for any file
+                    self_
+                        .local_chunks
+                        .borrow()
+                        .iter()
+                        .find(|(_, local)| local.contains_key(guid))
+                        .map(|(asset, _)| asset.clone())
+                });
+            if let Some(item) = asset.and_then(|a| self.get_item(&a)) {
+                item.add_downloaded_size(progress);
+                self.emit_by_name::<()>("tick", &[]);
+            }
+        }
+    }
+
+    fn file_hash_failed(&self, file: &str, details: DownloadedFile) {
+        let self_ = self.imp();
+        let Some(item) = self.get_item(&details.asset) else { return };
+        if item.canceled() {
+            return;
+        }
+        let retries = {
+            let mut retries = self_.file_retries.borrow_mut();
+            let count = retries.entry(file.to_string()).or_default();
+            *count += 1;
+            *count
+        };
+        if retries > MAX_FILE_RETRIES {
+            error!(
+                "Giving up on {} after {} failed hash validations",
+                file, MAX_FILE_RETRIES
+            );
+            self_.file_retries.borrow_mut().remove(file);
+            let name = details.name.clone();
+            self.file_failed(file, details, DownloadError::Corrupted(name));
+            return;
+        }
+        warn!(
+            "Downloading {} again, attempt {}/{}",
+            file, retries, MAX_FILE_RETRIES
+        );
+        let vaults = self_.settings.strv("unreal-vault-directories");
+        let mut temp = std::path::PathBuf::from(vaults.first().map_or_else(
+            || {
+                self_
+                    .settings
+                    .string("temporary-download-directory")
+                    .to_string()
+            },
+            std::string::ToString::to_string,
+        ));
+        temp.push(&details.release);
+        temp.push("temp");
+        // Data copied from other releases can not be trusted anymore, download all chunks
+        if let Some(df) = self_.downloaded_files.borrow_mut().get_mut(file) {
+            df.local_parts.clear();
+        }
+        if details.chunks.iter().any(|c| c.link.is_none()) {
+            error!("No link to download the chunks of {} again", file);
+            self_.file_retries.borrow_mut().remove(file);
+            let name = details.name.clone();
+            self.file_failed(file, details, DownloadError::Corrupted(name));
+            return;
+        }
+        let mut seen: Vec<String> = Vec::new();
+        for chunk in &details.chunks {
+            if seen.contains(&chunk.guid) {
+                continue;
+            }
+            seen.push(chunk.guid.clone());
+            {
+                let mut chunks = self_.downloaded_chunks.borrow_mut();
+                let files = chunks.entry(chunk.guid.clone()).or_default();
+                if !files.iter().any(|f| f.eq(file)) {
+                    files.push(file.to_string());
                 }
             }
+            {
+                let mut guids = self_.asset_guids.borrow_mut();
+                let guids = guids.entry(details.asset.clone()).or_default();
+                if !guids.contains(&chunk.guid) {
+                    guids.push(chunk.guid.clone());
+                }
+            }
+            if let Some(df) = self_.downloaded_files.borrow_mut().get_mut(file) {
+                df.finished_chunks.retain(|c| c.guid != chunk.guid);
+            }
+            if let Some(sg) = self_.stored_chunks.borrow_mut().get_mut(&details.asset) {
+                sg.remove(&chunk.guid);
+            }
+            let Some(link) = chunk.link.clone() else { continue };
+            self_.corrupted_chunks.borrow_mut().insert(
+                chunk.guid.clone(),
+                (
+                    details.asset.clone(),
+                    link,
+                    temp.join(format!("{}.chunk", chunk.guid)),
+                ),
+            );
+            self.redownload_corrupted_chunk(&chunk.guid);
+        }
+    }
+
+    fn redownload_corrupted_chunk(&self, guid: &str) {
+        let self_ = self.imp();
+        // Files that already have the chunk keep reading the old data until they are extracted
+        let in_use = self_
+            .downloaded_chunks
+            .borrow()
+            .get(guid)
+            .map_or(false, |files| {
+                let downloaded = self_.downloaded_files.borrow();
+                files.iter().any(|f| {
+                    downloaded
+                        .get(f)
+                        .map_or(false, |d| d.finished_chunks.iter().any(|c| c.guid == guid))
+                })
+            });
+        if in_use {
+            debug!(
+                "Keeping chunk {} until the files using it are extracted",
+                guid
+            );
+            return;
         }
+        let Some((_, link, p)) = self_.corrupted_chunks.borrow_mut().remove(guid) else {
+            return;
+        };
+        self_.chunk_cache.forget(&p);
+        if let Err(e) = std::fs::remove_file(&p) {
+            debug!("Unable to remove corrupted chunk {:?}: {}", p, e);
+        }
+        // The hosts delivered what they were asked for, the new download is not a retry after a host failure
+        self_.chunk_attempts.borrow_mut().remove(guid);
+        self_
+            .chunk_urls
+            .borrow_mut()
+            .entry(guid.to_string())
+            .or_insert_with(|| vec![link.clone()]);
+        let url = self.best_chunk_url(guid, None).unwrap_or(link);
+        self_
+            .sender
+            .send(super::Msg::PerformChunkDownload(url, p, guid.to_string()))
+            .ok();
     }
 
     fn asset_finished(&self, item: &super::download_item::EpicDownloadItem) {
         self.finish(item);
         if let Some(r) = item.release() {
+            self.queue_remove(&r);
             self.asset_cleanup(r);
         };
     }
@@ -630,27 +818,36 @@ impl Asset for super::EpicDownloadManager {
         let self_ = self.imp();
         let Some(item) = self.get_item(&asset_id) else { return; };
 
-        let mut targets: Vec<(String, bool)> = Vec::new();
+        let mut targets: Vec<(String, ConflictPolicy)> = Vec::new();
         {
             let actions = item.actions();
 
             for act in actions {
-                if let PostDownloadAction::Copy(t, over) = act {
-                    targets.push((t.clone(), over));
+                if let PostDownloadAction::Copy(t, policy) = act {
+                    targets.push((t.clone(), policy));
                 }
             }
         }
 
+        self.queue_file_finished(&asset_id, &filename);
+        let sender = self_.sender.clone();
+        let asset = asset_id.clone();
+        let staging = self.conflict_staging(&asset_id);
         self_.file_pool.execute(move || {
-            copy_files(&PathBuf::from_str(&fullname).unwrap(), targets, &filename);
+            copy_files(
+                &PathBuf::from(&fullname),
+                targets,
+                &filename,
+                &asset,
+                &staging,
+                &sender,
+            );
         });
 
         item.add_downloaded_size(progress);
+        self.space_written(&asset_id, u64::try_from(progress).unwrap_or(u64::MAX));
         self.emit_by_name::<()>("tick", &[]);
-        self_
-            .sender
-            .send(super::Msg::FileExtracted(asset_id))
-            .unwrap();
+        self_.sender.send(super::Msg::FileExtracted(asset_id)).ok();
     }
 
     fn pause_asset_download(&self, asset: String) {
@@ -668,6 +865,16 @@ impl Asset for super::EpicDownloadManager {
 
     fn asset_cleanup(&self, asset: String) {
         let self_ = self.imp();
+        self_.delta_sources.borrow_mut().remove(&asset);
+        self_.local_chunks.borrow_mut().remove(&asset);
+        self_.item_hosts.borrow_mut().remove(&asset);
+        self_.failed_files.borrow_mut().remove(&asset);
+        self_.repair_files.borrow_mut().remove(&asset);
+        self_
+            .corrupted_chunks
+            .borrow_mut()
+            .retain(|_, (a, _, _)| a.ne(&asset));
+        self.drop_queued_chunks(&asset);
         if let Some(guids) = self_.asset_guids.borrow_mut().remove(&asset) {
             if let Some(item) = self.get_item(&asset) {
                 if let Some(v) = item.version() {
@@ -688,6 +895,10 @@ impl Asset for super::EpicDownloadManager {
                 }
             }
         }
+        // Nothing is being assembled anymore, release the decoded chunks
+        if self_.asset_guids.borrow().is_empty() {
+            self_.chunk_cache.clear();
+        }
     }
 
     fn pause_asset_chunk(&self, url: Url, path: PathBuf, guid: String) {
@@ -702,26 +913,69 @@ impl Asset for super::EpicDownloadManager {
 
     fn resume_asset_download(&self, asset: String) {
         let self_ = self.imp();
+        if let Some(queued) = self.take_restored(&asset) {
+            if let Some(info) = queued.asset {
+                if let Some(item) = self.get_item(&asset) {
+                    item.set_property("status", "initializing...".to_string());
+                }
+                self.fetch_asset_manifest(asset, info);
+            }
+            return;
+        }
         if let Some(guids) = self_.asset_guids.borrow().get(&asset) {
             for guid in guids {
                 if let Some(values) = self_.paused_asset_chunks.borrow_mut().remove(guid.as_str()) {
                     for (url, path) in values {
                         self_
                             .sender
-                            .send(Msg::RedownloadChunk(
+                            .send(Msg::PerformChunkDownload(
                                 url.clone(),
                                 path.clone(),
                                 guid.clone(),
                             ))
-                            .unwrap();
+                            .ok();
                     }
                 }
             }
         }
+        self.schedule_chunks();
+    }
+
+    fn retry_asset_download(&self, asset: &str) {
+        let self_ = self.imp();
+        let failed = self_
+            .failed_files
+            .borrow_mut()
+            .remove(asset)
+            .unwrap_or_default();
+        for (file, mut details, error) in failed {
+            if let DownloadError::Corrupted(_) = error {
+                self.file_hash_failed(&file, details);
+            } else {
+                let mut finished: Vec<String> = Vec::new();
+                self.extract_file_from_chunks(&mut finished, &file, &mut details);
+            }
+        }
+        self.resume_asset_download(asset.to_string());
     }
 
     fn cancel_asset_download(&self, asset: String) {
         let self_ = self.imp();
+        self_.failed_files.borrow_mut().remove(&asset);
+        self.drop_conflicts(&asset);
+        self.queue_remove(&asset);
+        self_.delta_sources.borrow_mut().remove(&asset);
+        self_.local_chunks.borrow_mut().remove(&asset);
+        self_.item_hosts.borrow_mut().remove(&asset);
+        self.drop_queued_chunks(&asset);
+        if self.take_restored(&asset).is_some() {
+            if let Some(item) = self.get_item(&asset) {
+                item.set_property("status", "Canceled".to_string());
+                item.set_property("speed", String::new());
+            }
+            self_.download_items.borrow_mut().remove(&asset);
+            return;
+        }
         if let Some(guids) = self_.asset_guids.borrow_mut().remove(&asset) {
             if let Some(item) = self.get_item(&asset) {
                 item.set_property("status", "Canceled".to_string());
@@ -749,10 +1003,7 @@ impl Asset for super::EpicDownloadManager {
                             self_.paused_asset_chunks.borrow_mut().remove(guid.as_str())
                         {
                             for (url, path) in values {
-                                self_
-                                    .sender
-                                    .send(CancelChunk(url, path, guid.clone()))
-                                    .unwrap();
+                                self_.sender.send(CancelChunk(url, path, guid.clone())).ok();
                             }
                         }
                     }
@@ -762,6 +1013,75 @@ impl Asset for super::EpicDownloadManager {
     }
 }
 
+/// Download a chunk, continuing from the partial file if there is one
+fn fetch_chunk(
+    link: &Url,
+    p: &Path,
+    g: &str,
+    recv: &std::sync::mpsc::Receiver<ThreadMessages>,
+    sender: &Sender<Msg>,
+    limiter: &crate::download::limiter::RateLimiter,
+) {
+    if let Ok(w) = crate::RUNNING.read() {
+        if !*w {
+            return;
+        }
+    };
+    let result = pipeline::fetch_chunk_data(link, p, g, recv, limiter, &mut |size| {
+        sender
+            .send(super::Msg::ChunkDownloadProgress(
+                g.to_string(),
+                size,
+                false,
+            ))
+            .ok();
+    });
+    match result {
+        Ok(ChunkFetch::Complete(size)) => {
+            sender
+                .send(super::Msg::ChunkDownloadProgress(g.to_string(), size, true))
+                .ok();
+        }
+        Ok(ChunkFetch::Finished(downloaded, elapsed)) => {
+            sender
+                .send(super::Msg::ChunkHostReport(
+                    link.clone(),
+                    g.to_string(),
+                    downloaded,
+                    elapsed,
+                ))
+                .ok();
+            sender
+                .send(super::Msg::ChunkDownloadProgress(
+                    g.to_string(),
+                    downloaded,
+                    true,
+                ))
+                .ok();
+        }
+        Ok(ChunkFetch::Interrupted(m)) => process_thread_message(link, p, g, sender, &m),
+        Ok(ChunkFetch::Retry) => {
+            sender
+                .send(super::Msg::RedownloadChunk(
+                    link.clone(),
+                    p.to_path_buf(),
+                    g.to_string(),
+                ))
+                .ok();
+        }
+        Err(e) => {
+            sender
+                .send(super::Msg::ChunkFailed(
+                    link.clone(),
+                    p.to_path_buf(),
+                    g.to_string(),
+                    e,
+                ))
+                .ok();
+        }
+    }
+}
+
 fn process_thread_message(link: &Url, p: &Path, g: &str, sender: &Sender<Msg>, m: &ThreadMessages) {
     match m {
         ThreadMessages::Cancel => {
@@ -771,7 +1091,7 @@ fn process_thread_message(link: &Url, p: &Path, g: &str, sender: &Sender<Msg>, m
                     p.to_path_buf(),
                     g.to_string(),
                 ))
-                .unwrap();
+                .ok();
         }
         ThreadMessages::Pause => {
             sender
@@ -780,60 +1100,91 @@ fn process_thread_message(link: &Url, p: &Path, g: &str, sender: &Sender<Msg>, m
                     p.to_path_buf(),
                     g.to_string(),
                 ))
-                .unwrap();
+                .ok();
         }
     }
 }
 
-fn save_asset_manifest(
-    t: &Path,
-    manifest: &egs_api::api::types::download_manifest::DownloadManifest,
-) {
-    if let Ok(w) = crate::RUNNING.read() {
-        if !*w {
-            return;
-        }
-    }
-    let tar = match t.file_name() {
-        None => {
-            return;
-        }
-        Some(fname) => {
-            if fname.eq("data") {
-                match t.parent() {
-                    None => {
-                        return;
-                    }
-                    Some(p) => p,
-                }
-            } else {
-                t
-            }
+/// Get the download manifests of an asset release, empty if they are not available
+pub fn request_download_manifests(
+    eg: &mut egs_api::EpicGames,
+    release_id: &str,
+    asset: &egs_api::api::types::asset_info::AssetInfo,
+) -> Vec<egs_api::api::types::download_manifest::DownloadManifest> {
+    let start = std::time::Instant::now();
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Unable to start the async runtime: {}", e);
+            return Vec::new();
         }
     };
+    let mut d = Vec::new();
+    if let Some(release_info) = asset.release_info(&release_id.to_string()) {
+        if let Some(manifest) = runtime.block_on(eg.asset_manifest(
+            None,
+            None,
+            Some(asset.namespace.clone()),
+            Some(asset.id.clone()),
+            Some(release_info.app_id.unwrap_or_default()),
+        )) {
+            debug!("Got asset manifest: {:?}", manifest);
+            d = runtime.block_on(eg.asset_download_manifests(manifest));
+            debug!("Got asset download manifests for {}", release_id);
+            // TODO cache download manifest
+        };
+    }
+    debug!("Download Manifest requests took {:?}", start.elapsed());
+    d
+}
 
-    std::fs::create_dir_all(t).expect("Unable to create target directory");
-    match File::create(tar.join("manifest.json")) {
-        Ok(mut json_manifest_file) => match serde_json::to_string(&manifest) {
-            Ok(json) => {
-                json_manifest_file
-                    .write_all(json.as_bytes().as_ref())
-                    .unwrap();
+/// Hash every file of a downloaded asset and compare it with the saved manifest
+pub fn verify_asset(data: &Path) -> Option<VerificationReport> {
+    let manifest = pipeline::load_asset_manifest(data)?;
+    let mut report = VerificationReport::default();
+    let files = manifest.files();
+    for (filename, m) in &files {
+        if let Ok(w) = crate::RUNNING.read() {
+            if !*w {
+                return None;
             }
-            Err(e) => {
-                error!("Unable to save json manifest: {}", e);
-            }
-        },
-        Err(e) => {
-            error!("Unable to save Manifest: {:?}", e);
+        };
+        let path = data.join(filename);
+        if !path.exists() {
+            report.missing.push(filename.clone());
+            continue;
+        }
+        if !pipeline::file_hash(&path).map_or(false, |hash| hash.eq(&m.file_hash)) {
+            report.corrupt.push(filename.clone());
         }
     }
-    match File::create(tar.join("manifest")) {
-        Ok(mut manifest_file) => {
-            manifest_file.write_all(&manifest.to_vec()).unwrap();
+    let mut local: Vec<String> = Vec::new();
+    list_files(data, data, &mut local);
+    for file in local {
+        if !files.contains_key(&file) {
+            report.extra.push(file);
         }
-        Err(e) => {
-            error!("Unable to save binary Manifest: {:?}", e);
+    }
+    report.missing.sort();
+    report.corrupt.sort();
+    report.extra.sort();
+    Some(report)
+}
+
+fn list_files(root: &Path, dir: &Path, result: &mut Vec<String>) {
+    let Ok(entries) = std::fs::read_dir(dir) else { return };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            list_files(root, &path, result);
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            result.push(
+                relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/"),
+            );
         }
     }
 }
@@ -851,31 +1202,17 @@ fn initiate_file_download(
             return;
         }
     };
-    match File::open(full_path) {
-        Ok(mut f) => {
-            let mut buffer: [u8; 1024] = [0; 1024];
-            let mut hasher = sha1::Sha1::new();
-            loop {
-                if let Ok(size) = f.read(&mut buffer) {
-                    if size > 0 {
-                        hasher.update(&buffer[..size]);
-                    } else {
-                        break;
-                    }
-                }
-            }
-            let hash = hasher.finalize();
-            if m.file_hash
-                .eq(&hash.iter().map(|b| format!("{b:02x}")).collect::<String>())
-            {
+    match pipeline::file_hash(full_path) {
+        Some(hash) => {
+            if m.file_hash.eq(&hash) {
                 sender
                     .send(super::Msg::FileAlreadyDownloaded(
                         r_id.to_string(),
                         m.size(),
-                        full_path.to_str().unwrap().to_string(),
+                        full_path.to_string_lossy().to_string(),
                         f_name.to_string(),
                     ))
-                    .unwrap();
+                    .ok();
             } else {
                 warn!("Hashes do not match, downloading again: {:?}", full_path);
                 sender
@@ -885,11 +1222,11 @@ fn initiate_file_download(
                         f_name.to_string(),
                         m,
                     ))
-                    .unwrap();
+                    .ok();
             };
         }
         // File does not exist perform download
-        Err(_) => {
+        None => {
             sender
                 .send(super::Msg::PerformAssetDownload(
                     r_id.to_string(),
@@ -897,12 +1234,97 @@ fn initiate_file_download(
                     f_name.to_string(),
                     m,
                 ))
-                .unwrap();
+                .ok();
         }
     }
 }
 
 impl AssetPriv for super::EpicDownloadManager {
+    fn create_asset_item(
+        &self,
+        release_id: &str,
+        asset: &egs_api::api::types::asset_info::AssetInfo,
+        target: &Option<String>,
+        actions: Option<Vec<super::PostDownloadAction>>,
+    ) -> Option<super::download_item::EpicDownloadItem> {
+        let self_ = self.imp();
+
+        let item = {
+            let mut items = self_.download_items.borrow_mut();
+            if items.contains_key(release_id) {
+                return None;
+            }
+            // A new download of the release asks again about conflicts
+            self_.conflict_choices.borrow_mut().remove(release_id);
+            let item = super::download_item::EpicDownloadItem::new();
+            debug!("Adding item to the list under: {}", release_id);
+            items.insert(release_id.to_string(), item.clone());
+            item
+        };
+        if let Some(w) = self_.window.get() {
+            item.set_window(w);
+        }
+        item.set_download_manager(self);
+        if let Some(actions) = actions {
+            item.add_actions(&actions);
+        };
+        item.set_property("item-type", ItemType::Asset);
+        item.set_property("asset", asset.id.clone());
+        item.set_property("release", release_id.to_string());
+        item.set_property("label", asset.title.clone());
+        item.set_property("target", target.clone());
+        item.set_property("status", "initializing...".to_string());
+        self.load_thumbnail(release_id.to_string(), asset.thumbnail());
+
+        self_.downloads.append(&item);
+
+        self.set_property("has-items", self_.downloads.first_child().is_some());
+
+        item.connect_local(
+            "finished",
+            false,
+            clone!(@weak self as edm, @weak item => @default-return None, move |_| {
+                edm.asset_finished(&item);
+                None
+            }),
+        );
+        Some(item)
+    }
+
+    fn fetch_asset_manifest(
+        &self,
+        release_id: String,
+        asset: egs_api::api::types::asset_info::AssetInfo,
+    ) {
+        let (sender, receiver) = gtk4::glib::MainContext::channel(gtk4::glib::PRIORITY_DEFAULT);
+
+        receiver.attach(
+            None,
+            clone!(@weak self as download_manager => @default-panic, move |(id, manifest)| {
+                let self_ = download_manager.imp();
+                let sender = self_.sender.clone();
+                sender.send(super::Msg::StartAssetDownload(id, manifest)).ok();
+                glib::Continue(true)
+            }),
+        );
+
+        // Other releases already on disk can provide the chunks shared with this one
+        let self_ = self.imp();
+        let vaults: Vec<PathBuf> = self_
+            .settings
+            .strv("unreal-vault-directories")
+            .iter()
+            .map(|v| PathBuf::from(v.to_str()))
+            .collect();
+        let sources = pipeline::delta_sources(&asset, &release_id, &vaults);
+        self_
+            .delta_sources
+            .borrow_mut()
+            .insert(release_id.clone(), sources);
+
+        self.download_asset_manifest(release_id, asset, sender);
+    }
+
     fn load_thumbnail(
         &self,
         id: String,
@@ -930,7 +1352,7 @@ impl AssetPriv for super::EpicDownloadManager {
                     )) {
                         Ok(t) => sender
                             .send(super::Msg::ProcessItemThumbnail(id.clone(), t))
-                            .unwrap(),
+                            .ok(),
                         Err(e) => {
                             error!("Unable to load file to texture: {}", e);
                         }
@@ -959,7 +1381,7 @@ impl AssetPriv for super::EpicDownloadManager {
             },
             std::string::ToString::to_string,
         ));
-        let mut targets: Vec<(String, bool)> = Vec::new();
+        let mut targets: Vec<(String, ConflictPolicy)> = Vec::new();
         let mut to_vault = true;
         {
             let actions = self
@@ -968,12 +1390,13 @@ impl AssetPriv for super::EpicDownloadManager {
 
             for act in actions {
                 match act {
-                    PostDownloadAction::Copy(t, over) => {
-                        targets.push((t.clone(), over));
+                    PostDownloadAction::Copy(t, policy) => {
+                        targets.push((t.clone(), policy));
                     }
                     PostDownloadAction::NoVault => {
                         to_vault = false;
                     }
+                    PostDownloadAction::RunCommand(_) => {}
                 }
             }
         }
@@ -995,9 +1418,12 @@ impl AssetPriv for super::EpicDownloadManager {
             v.push("data");
             v
         } else {
-            PathBuf::from_str(&targets.pop().unwrap().0).unwrap()
+            let Some((t, _)) = targets.pop() else { return };
+            PathBuf::from(t)
         };
         let sender = self_.sender.clone();
+        let cache = self_.chunk_cache.clone();
+        let staging = self.conflict_staging(&f.asset);
         let f_c = f.clone();
         let file_c = file.to_string();
         self_.file_pool.execute(move || {
@@ -1007,107 +1433,34 @@ impl AssetPriv for super::EpicDownloadManager {
                 }
             };
             vault.push(&finished.name);
-            std::fs::create_dir_all(vault.parent().unwrap()).unwrap();
-            debug!("Created target directory: {:?}", vault.to_str());
-            match File::create(vault.clone()) {
-                Ok(mut target) => {
-                    let hash =
-                        extract_chunks(finished.chunks, &temp.clone(), &mut target).finalize();
-                    if finished
-                        .hash
-                        .eq(&hash.iter().map(|b| format!("{b:02x}")).collect::<String>())
-                    {
-                        copy_files(&vault.clone(), targets, &finished.name);
-                        sender
-                            .send(super::Msg::FinalizeFileDownload(
-                                file_c.to_string(),
-                                f_c.clone(),
-                            ))
-                            .unwrap();
-                    } else {
-                        error!("Failed to validate hash on: {:?}", vault);
-                        // TODO: Try to download this file again
-                    };
+            match pipeline::assemble_file(&finished, &temp, &vault, &cache) {
+                Ok(true) => {
+                    copy_files(
+                        &vault.clone(),
+                        targets,
+                        &finished.name,
+                        &finished.asset,
+                        &staging,
+                        &sender,
+                    );
+                    sender
+                        .send(super::Msg::FinalizeFileDownload(
+                            file_c.to_string(),
+                            f_c.clone(),
+                        ))
+                        .ok();
+                }
+                Ok(false) => {
+                    error!("Failed to validate hash on: {:?}", vault);
+                    sender
+                        .send(super::Msg::FileHashFailed(file_c.to_string(), f_c.clone()))
+                        .ok();
                 }
                 Err(e) => {
-                    error!("Error opening the target file: {:?}", e);
+                    sender.send(super::Msg::FileFailed(file_c, f_c, e)).ok();
                 }
             }
         });
     }
 }
 
-fn extract_chunks(
-    chunks: Vec<egs_api::api::types::download_manifest::FileChunkPart>,
-    temp: &Path,
-    target: &mut File,
-) -> CoreWrapper<Sha1Core> {
-    let mut hasher = Sha1::new();
-    for chunk in chunks {
-        let mut t = temp.to_path_buf();
-        t.push(format!("{}.chunk", chunk.guid));
-        match File::open(t) {
-            Ok(mut f) => {
-                let metadata = f.metadata().expect("Unable to read metadata");
-                let mut buffer = vec![0_u8; usize::try_from(metadata.len()).unwrap()];
-                f.read_exact(&mut buffer).expect("Read failed");
-                let ch = match egs_api::api::types::chunk::Chunk::from_vec(buffer) {
-                    None => {
-                        error!("Failed to parse chunk from file: {:?}", chunk.link);
-                        break;
-                    }
-                    Some(c) => c,
-                };
-                if u128::from(
-                    ch.uncompressed_size
-                        .unwrap_or_else(|| u32::try_from(ch.data.len()).unwrap()),
-                ) < chunk.offset + chunk.size
-                {
-                    error!("Chunk is not big enough");
-                    break;
-                };
-                hasher
-                    .update(&ch.data[chunk.offset as usize..(chunk.offset + chunk.size) as usize]);
-                target
-                    .write_all(
-                        &ch.data[chunk.offset as usize..(chunk.offset + chunk.size) as usize],
-                    )
-                    .unwrap();
-            }
-            Err(e) => {
-                error!("Error opening the chunk file: {:?}", e);
-            }
-        }
-        debug!("chunk: {:?}", chunk);
-    }
-    hasher
-}
-
-fn copy_files(from: &Path, targets: Vec<(String, bool)>, filename: &str) {
-    for t in targets {
-        let mut tar = PathBuf::from_str(&t.0).unwrap();
-        tar.push(filename);
-        if tar.exists() && !t.1 {
-            continue;
-        }
-        if tar.exists() {
-            let ext = tar.extension().map_or_else(
-                || OsString::from_str(".bak").unwrap(),
-                |ext| {
-                    let mut new = ext.to_os_string();
-                    new.push(".bak");
-                    new
-                },
-            );
-            let mut bak = tar.clone();
-            bak.set_extension(ext);
-            if let Err(err) = std::fs::rename(&tar, bak) {
-                error!("Unable to create backup: {:?}", err);
-            };
-        }
-        std::fs::create_dir_all(tar.parent().unwrap()).unwrap();
-        if let Err(e) = std::fs::copy(from, tar) {
-            error!("Unable to copy file: {:?}", e);
-        };
-    }
-}
@@ -0,0 +1,382 @@
+use crate::download::pipeline::file_hash;
+use crate::ui::widgets::download_manager::project_files::ProjectFiles;
+use crate::ui::widgets::download_manager::Msg;
+use egs_api::api::types::download_manifest::DownloadManifest;
+use gtk4::glib::clone;
+use gtk4::subclass::prelude::*;
+use gtk4::{self, glib, prelude::*};
+use log::{debug, error};
+use std::path::{Path, PathBuf};
+
+/// What the Copy action does with files that already exist in the target
+#[derive(Debug, Copy, Clone, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(from = "StoredPolicy")]
+pub enum ConflictPolicy {
+    Skip,
+    Overwrite,
+    /// Keep the old file next to the new one with a timestamp
+    Backup,
+    /// Let the user decide for every file
+    Ask,
+}
+
+/// Copy actions saved before policies existed only had an overwrite flag
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum StoredPolicy {
+    Overwrite(bool),
+    Policy(NamedPolicy),
+}
+
+#[derive(serde::Deserialize)]
+enum NamedPolicy {
+    Skip,
+    Overwrite,
+    Backup,
+    Ask,
+}
+
+impl From<StoredPolicy> for ConflictPolicy {
+    fn from(stored: StoredPolicy) -> Self {
+        match stored {
+            StoredPolicy::Overwrite(true) => Self::Backup,
+            StoredPolicy::Overwrite(false) | StoredPolicy::Policy(NamedPolicy::Skip) => Self::Skip,
+            StoredPolicy::Policy(NamedPolicy::Overwrite) => Self::Overwrite,
+            StoredPolicy::Policy(NamedPolicy::Backup) => Self::Backup,
+            StoredPolicy::Policy(NamedPolicy::Ask) => Self::Ask,
+        }
+    }
+}
+
+impl ConflictPolicy {
+    pub fn id(self) -> &'static str {
+        match self {
+            Self::Skip => "skip",
+            Self::Overwrite => "overwrite",
+            Self::Backup => "backup",
+            Self::Ask => "ask",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Self {
+        match id {
+            "overwrite" => Self::Overwrite,
+            "backup" => Self::Backup,
+            "ask" => Self::Ask,
+            _ => Self::Skip,
+        }
+    }
+}
+
+/// Files of a manifest compared with what is already in the target directory
+#[derive(Default, Debug, Clone)]
+pub struct CopyReport {
+    pub new: Vec<String>,
+    pub changed: Vec<String>,
+    pub unchanged: Vec<String>,
+}
+
+impl CopyReport {
+    pub fn summary(&self) -> String {
+        format!(
+            "{} new, {} changed and {} unchanged files",
+            self.new.len(),
+            self.changed.len(),
+            self.unchanged.len()
+        )
+    }
+}
+
+/// Check which files of the manifest would be added or changed in the target directory
+pub fn copy_report(manifest: &DownloadManifest, target: &Path) -> CopyReport {
+    let mut report = CopyReport::default();
+    for (filename, m) in manifest.files() {
+        let path = target.join(&filename);
+        if !path.exists() {
+            report.new.push(filename);
+        } else if file_hash(&path).map_or(false, |hash| hash.eq(&m.file_hash)) {
+            report.unchanged.push(filename);
+        } else {
+            report.changed.push(filename);
+        }
+    }
+    report.new.sort();
+    report.changed.sort();
+    report.unchanged.sort();
+    report
+}
+
+/// Path the existing file is moved to, `name.ext.<timestamp>.bak`
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(
+        ".{}.bak",
+        chrono::Local::now().format("%Y%m%d-%H%M%S")
+    ));
+    path.with_file_name(name)
+}
+
+/// Move the staged file over the target, copying it when both are on different filesystems
+fn replace_with_staged(staged: &Path, target: &Path) -> std::io::Result<()> {
+    if std::fs::rename(staged, target).is_ok() {
+        return Ok(());
+    }
+    std::fs::copy(staged, target)?;
+    std::fs::remove_file(staged)
+}
+
+/// Copy a downloaded file to the targets, resolving existing files with the target's policy
+///
+/// Files conflicting in a target with the Ask policy are staged in `staging`, outside of the project.
+pub fn copy_files(
+    from: &Path,
+    targets: Vec<(String, ConflictPolicy)>,
+    filename: &str,
+    asset: &str,
+    staging: &Path,
+    sender: &glib::Sender<Msg>,
+) {
+    for (index, (target, policy)) in targets.into_iter().enumerate() {
+        let mut tar = PathBuf::from(&target);
+        tar.push(filename);
+        if let Some(parent) = tar.parent() {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                error!("Unable to create {:?}: {:?}", parent, e);
+                continue;
+            }
+        }
+        let mut backup: Option<PathBuf> = None;
+        let replaced = tar.exists();
+        if replaced {
+            match policy {
+                ConflictPolicy::Skip => continue,
+                ConflictPolicy::Overwrite => {}
+                ConflictPolicy::Backup => {
+                    let bak = backup_path(&tar);
+                    if let Err(err) = std::fs::rename(&tar, &bak) {
+                        error!("Unable to create backup: {:?}", err);
+                        continue;
+                    };
+                    backup = Some(bak);
+                }
+                ConflictPolicy::Ask => {
+                    let staged = staging.join(index.to_string()).join(filename);
+                    if let Some(parent) = staged.parent() {
+                        if let Err(e) = std::fs::create_dir_all(parent) {
+                            error!("Unable to create {:?}: {:?}", parent, e);
+                            continue;
+                        }
+                    }
+                    match std::fs::copy(from, &staged) {
+                        Ok(_) => {
+                            sender
+                                .send(Msg::CopyConflict(asset.to_string(), target, tar, staged))
+                                .ok();
+                        }
+                        Err(e) => error!("Unable to copy file: {:?}", e),
+                    }
+                    continue;
+                }
+            }
+        }
+        match std::fs::copy(from, &tar) {
+            Ok(_) => {
+                sender
+                    .send(Msg::FileCopied(
+                        asset.to_string(),
+                        target,
+                        tar,
+                        backup,
+                        replaced,
+                    ))
+                    .ok();
+            }
+            Err(e) => error!("Unable to copy file: {:?}", e),
+        };
+    }
+}
+
+pub trait Conflicts {
+    /// Directory in the download location the conflicting files of all downloads are staged in
+    fn conflict_staging_root(&self) -> PathBuf {
+        unimplemented!()
+    }
+
+    /// Directory the conflicting files of the download wait in until the user decides
+    fn conflict_staging(&self, asset: &str) -> PathBuf {
+        self.conflict_staging_root().join(asset)
+    }
+
+    /// Forget the unanswered conflicts of a canceled download and remove its staged files
+    fn drop_conflicts(&self, _asset: &str) {
+        unimplemented!()
+    }
+
+    /// Remove files staged before the application was closed, nobody is going to answer for them
+    fn clear_staged_conflicts(&self) {
+        unimplemented!()
+    }
+
+    /// A file exists in a target with the Ask policy, the new file is staged until the user decides
+    fn copy_conflict(&self, _asset: &str, _project: &str, _target: PathBuf, _staged: PathBuf) {
+        unimplemented!()
+    }
+
+    /// Ask about the next conflict, one dialog at a time
+    fn show_next_conflict(&self) {
+        unimplemented!()
+    }
+
+    /// Keep the existing file or replace it with the staged one
+    fn resolve_conflict(
+        &self,
+        _asset: &str,
+        _project: &str,
+        _target: &Path,
+        _staged: &Path,
+        _replace: bool,
+    ) {
+        unimplemented!()
+    }
+}
+
+impl Conflicts for crate::ui::widgets::download_manager::EpicDownloadManager {
+    fn conflict_staging_root(&self) -> PathBuf {
+        let self_ = self.imp();
+        let vaults = self_.settings.strv("unreal-vault-directories");
+        let mut temp = PathBuf::from(vaults.first().map_or_else(
+            || {
+                self_
+                    .settings
+                    .string("temporary-download-directory")
+                    .to_string()
+            },
+            std::string::ToString::to_string,
+        ));
+        temp.push("conflicts");
+        temp
+    }
+
+    fn drop_conflicts(&self, asset: &str) {
+        let self_ = self.imp();
+        self_.conflict_choices.borrow_mut().remove(asset);
+        self_
+            .copy_conflicts
+            .borrow_mut()
+            .retain(|(a, _, _, _)| a.ne(asset));
+        let staging = self.conflict_staging(asset);
+        if let Err(e) = std::fs::remove_dir_all(&staging) {
+            debug!("Unable to remove staged files {:?}: {}", staging, e);
+        }
+    }
+
+    fn clear_staged_conflicts(&self) {
+        let staging = self.conflict_staging_root();
+        if staging.exists() {
+            if let Err(e) = std::fs::remove_dir_all(&staging) {
+                error!("Unable to remove staged files {:?}: {}", staging, e);
+            }
+        }
+    }
+
+    fn copy_conflict(&self, asset: &str, project: &str, target: PathBuf, staged: PathBuf) {
+        let self_ = self.imp();
+        let choice = self_.conflict_choices.borrow().get(asset).copied();
+        if let Some(replace) = choice {
+            self.resolve_conflict(asset, project, &target, &staged, replace);
+            return;
+        }
+        self_.copy_conflicts.borrow_mut().push_back((
+            asset.to_string(),
+            project.to_string(),
+            target,
+            staged,
+        ));
+        self.show_next_conflict();
+    }
+
+    fn show_next_conflict(&self) {
+        let self_ = self.imp();
+        if self_.conflict_dialog.borrow().is_some() {
+            return;
+        }
+        let Some((asset, project, target, staged)) = self_.copy_conflicts.borrow_mut().pop_front()
+        else {
+            return;
+        };
+        // The user may have applied a choice to the whole asset while this one was waiting
+        let choice = self_.conflict_choices.borrow().get(&asset).copied();
+        if let Some(replace) = choice {
+            self.resolve_conflict(&asset, &project, &target, &staged, replace);
+            self.show_next_conflict();
+            return;
+        }
+        let dialog = gtk4::MessageDialog::builder()
+            .modal(true)
+            .message_type(gtk4::MessageType::Question)
+            .text("File already exists")
+            .secondary_text(format!(
+                "{} was changed by the download, replace the existing file?",
+                target.display()
+            ))
+            .build();
+        if let Some(w) = self_.window.get() {
+            dialog.set_transient_for(Some(w));
+        }
+        dialog.add_button("Keep existing", gtk4::ResponseType::Reject);
+        dialog.add_button("Replace", gtk4::ResponseType::Accept);
+        let apply_all = gtk4::CheckButton::with_label("Do the same for the rest of this download");
+        dialog.message_area().append(&apply_all);
+        dialog.connect_response(
+            clone!(@weak self as dm, @weak apply_all => move |dialog, response| {
+                let replace = response == gtk4::ResponseType::Accept;
+                if response == gtk4::ResponseType::DeleteEvent {
+                    debug!("Conflict dialog closed, keeping {:?}", target);
+                }
+                if apply_all.is_active() {
+                    dm.imp().conflict_choices.borrow_mut().insert(asset.clone(), replace);
+                }
+                dm.resolve_conflict(&asset, &project, &target, &staged, replace);
+                dialog.close();
+                dm.imp().conflict_dialog.replace(None);
+                dm.show_next_conflict();
+            }),
+        );
+        self_.conflict_dialog.replace(Some(dialog.clone()));
+        dialog.show();
+    }
+
+    fn resolve_conflict(
+        &self,
+        asset: &str,
+        project: &str,
+        target: &Path,
+        staged: &Path,
+        replace: bool,
+    ) {
+        if !staged.exists() {
+            // The download was canceled while the dialog was open
+            debug!("Staged file for {:?} is gone", target);
+            return;
+        }
+        let result = if replace {
+            replace_with_staged(staged, target)
+        } else {
+            std::fs::remove_file(staged)
+        };
+        match result {
+            Ok(_) if replace => self.file_copied(asset, project, target, None, true),
+            Ok(_) => {}
+            Err(e) => error!("Unable to resolve conflict for {:?}: {}", target, e),
+        }
+        // Leave no empty directories behind in the staging area
+        let root = self.conflict_staging_root();
+        let mut dir = staged.parent();
+        while let Some(d) = dir {
+            if !d.starts_with(&root) || d.eq(&root) || std::fs::remove_dir(d).is_err() {
+                break;
+            }
+            dir = d.parent();
+        }
+    }
+}
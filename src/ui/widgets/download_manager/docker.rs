@@ -1,5 +1,14 @@
+use crate::download::error::DownloadError;
+use crate::download::pipeline::SpaceNeed;
+use crate::download::ThreadMessages;
+use crate::ui::widgets::download_manager::error::Failures;
+use crate::ui::widgets::download_manager::layer_cache;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use crate::ui::widgets::download_manager::layers;
+use crate::ui::widgets::download_manager::queue::{Queue, QueuedDownload};
+use crate::ui::widgets::download_manager::space::{DiskSpace, EXTRACTION_FACTOR};
 use crate::ui::widgets::download_manager::Msg::{DockerBlobFailed, DockerCanceled};
-use crate::ui::widgets::download_manager::{download_item, DownloadStatus, Msg, ThreadMessages};
+use crate::ui::widgets::download_manager::{download_item, DownloadStatus, Msg};
 use crate::ui::widgets::logged_in::refresh::Refresh;
 use glib::clone;
 use gtk4::glib;
@@ -8,6 +17,7 @@ use gtk4::subclass::prelude::*;
 use gtk4::{self, prelude::*};
 use log::{debug, error, warn};
 use regex::Regex;
+use std::collections::HashSet;
 use std::path::PathBuf;
 use std::thread;
 
@@ -37,6 +47,19 @@ pub trait Docker {
         unimplemented!()
     }
 
+    /// Show a docker download saved by a previous run
+    fn restore_docker_download(&self, _queued: &QueuedDownload) {
+        unimplemented!()
+    }
+
+    fn create_docker_item(&self, _version: &str) -> Option<download_item::EpicDownloadItem> {
+        unimplemented!()
+    }
+
+    fn fetch_docker_manifest(&self, _version: &str) {
+        unimplemented!()
+    }
+
     fn docker_target_directory(&self) -> Option<PathBuf> {
         unimplemented!()
     }
@@ -57,6 +80,11 @@ pub trait Docker {
         unimplemented!()
     }
 
+    /// Shrink the layer cache to the configured size, keeping layers of running downloads
+    fn evict_docker_layers(&self) {
+        unimplemented!()
+    }
+
     fn docker_finished(&self, _item: &download_item::EpicDownloadItem) {
         unimplemented!()
     }
@@ -71,6 +99,11 @@ pub trait Docker {
     fn resume_docker_download(&self, _version: String) {
         unimplemented!()
     }
+
+    /// Continue a failed download, extracting layers again if the extraction failed
+    fn retry_docker_download(&self, _version: String) {
+        unimplemented!()
+    }
 }
 
 impl Docker for crate::ui::widgets::download_manager::EpicDownloadManager {
@@ -78,11 +111,34 @@ impl Docker for crate::ui::widgets::download_manager::EpicDownloadManager {
     fn perform_docker_blob_downloads(&self, version: &str, size: u64, digests: Vec<(String, u64)>) {
         let self_ = self.imp();
         let Some(item) = self.get_item(version) else {
-                           return;
-                       };
+            return;
+        };
+        if self_.docker_digests.borrow().get(version).is_none() {
+            let Some(blobs) = self.docker_target_directory() else { return };
+            let mut engine = blobs.clone();
+            engine.pop();
+            engine.push(version);
+            // Layers shared with another version are already in the cache
+            let cached: u64 = digests
+                .iter()
+                .filter(|(d, _)| layer_cache::is_cached(d))
+                .map(|(_, s)| s)
+                .sum();
+            if !self.reserve_space(
+                version,
+                vec![
+                    SpaceNeed::new(blobs, size.saturating_sub(cached)),
+                    SpaceNeed::new(engine, size * EXTRACTION_FACTOR),
+                ],
+                0,
+            ) {
+                return;
+            }
+        }
         item.set_property("status", "waiting for download slot".to_string());
         item.set_total_size(u128::from(size));
         item.set_total_files(digests.len() as u64);
+        self.queue_set_totals(version, u128::from(size), digests.len() as u64);
 
         let v = version.to_string();
 
@@ -104,6 +160,20 @@ impl Docker for crate::ui::widgets::download_manager::EpicDownloadManager {
     #[cfg(any(target_os = "linux", target_os = "macos"))]
     fn download_docker_digest(&self, version: &str, digest: (String, u64)) {
         let self_ = self.imp();
+        if let Some(target) = self.docker_target_directory() {
+            if layer_cache::cached_layer(&target, &digest.0, digest.1) {
+                debug!("Using cached layer {} for {}", digest.0, version);
+                self_
+                    .sender
+                    .send(Msg::DockerDownloadProgress(version.to_string(), digest.1))
+                    .ok();
+                self_
+                    .sender
+                    .send(Msg::DockerBlobFinished(version.to_string(), digest.0))
+                    .ok();
+                return;
+            }
+        }
         if let Some(window) = self_.window.get() {
             let win_: &crate::window::imp::EpicAssetManagerWindow =
                 crate::window::imp::EpicAssetManagerWindow::from_obj(window);
@@ -114,9 +184,10 @@ impl Docker for crate::ui::widgets::download_manager::EpicDownloadManager {
                 let client = dclient.clone();
                 let sender = self_.sender.clone();
                 let pool = self_.download_pool.clone();
+                let limiter = self_.limiter.clone();
                 let Some(target) = self.docker_target_directory() else { return };
                 debug!("Going to download to {:?}", target);
-                let (send, recv) = std::sync::mpsc::channel::<super::ThreadMessages>();
+                let (send, recv) = std::sync::mpsc::channel::<ThreadMessages>();
                 self.add_thread_sender(ver.clone(), send);
                 thread::spawn(move || {
                     let (tx, rx): (std::sync::mpsc::Sender<u64>, std::sync::mpsc::Receiver<u64>) =
@@ -131,17 +202,22 @@ impl Docker for crate::ui::widgets::download_manager::EpicDownloadManager {
                             Some(tx),
                             target.as_path(),
                         ) {
-                            Ok(_) => s.send(crate::ui::widgets::download_manager::Msg::DockerBlobFinished(v, d)).unwrap(),
+                            Ok(_) => {
+                                layer_cache::record_layer(&d, size);
+                                s.send(crate::ui::widgets::download_manager::Msg::DockerBlobFinished(v, d)).ok()
+                            }
                             Err(e) => {
                                 match &e {
                                     ghregistry::errors::Error::IO(err) => {
                                         error!("Failed blob download because: {:?}", err);
-                                        s.send(crate::ui::widgets::download_manager::Msg::IOError(err.to_string())).unwrap();
+                                        // Keep the blob so retry continues it
+                                        s.send(crate::ui::widgets::download_manager::Msg::DockerPaused(v.clone(), (d.clone(), size))).ok();
+                                        s.send(crate::ui::widgets::download_manager::Msg::DownloadFailed(v, DownloadError::io(&target.join(&d), err))).ok()
                                     },
-                                    ghregistry::errors::Error::Sender(_e) => {}
+                                    ghregistry::errors::Error::Sender(_e) => None,
                                     _ => {
                                         error!("Failed blob download because: {:?}", e);
-                                        s.send(crate::ui::widgets::download_manager::Msg::DockerBlobFailed(v, (d, size))).unwrap();
+                                        s.send(crate::ui::widgets::download_manager::Msg::DockerBlobFailed(v, (d, size))).ok()
                                     }
                                 }
                             }
@@ -158,6 +234,9 @@ impl Docker for crate::ui::widgets::download_manager::EpicDownloadManager {
                             process_docker_thread_message(ver.clone(), digest.clone(), &sender, &m);
                             return;
                         }
+                        // The registry client reads the blob itself, so this only slows down the
+                        // other downloads sharing the limit
+                        limiter.acquire(usize::try_from(progress).unwrap_or(usize::MAX));
                         sender
                             .send(
                                 crate::ui::widgets::download_manager::Msg::DockerDownloadProgress(
@@ -165,7 +244,7 @@ impl Docker for crate::ui::widgets::download_manager::EpicDownloadManager {
                                     progress,
                                 ),
                             )
-                            .unwrap();
+                            .ok();
                     }
                 });
             }
@@ -173,6 +252,9 @@ impl Docker for crate::ui::widgets::download_manager::EpicDownloadManager {
     }
     #[cfg(any(target_os = "linux", target_os = "macos"))]
     fn cancel_docker_digest(&self, _version: &str, digest: (String, u64)) {
+        if layer_cache::is_cached(&digest.0) {
+            return;
+        }
         let Some(mut target) = self.docker_target_directory() else { return };
         target.push(digest.0);
         if let Err(e) = std::fs::remove_file(target) {
@@ -194,21 +276,44 @@ impl Docker for crate::ui::widgets::download_manager::EpicDownloadManager {
     #[cfg(any(target_os = "linux", target_os = "macos"))]
     fn download_engine_from_docker(&self, version: &str) {
         debug!("Initializing docker engine download of {}", version);
+        if self.create_docker_item(version).is_none() {
+            return;
+        };
+        self.queue_add(
+            version,
+            download_item::ItemType::Docker,
+            version,
+            &None,
+            None,
+            &[],
+        );
+        self.fetch_docker_manifest(version);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    fn restore_docker_download(&self, queued: &QueuedDownload) {
+        let Some(item) = self.create_docker_item(&queued.id) else {
+            return;
+        };
+        item.set_total_size(queued.total_size);
+        item.set_total_files(queued.total_files);
+        item.set_restored(queued.finished_files);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    fn create_docker_item(&self, version: &str) -> Option<download_item::EpicDownloadItem> {
         let self_ = self.imp();
 
         let re = Regex::new(r"dev-(?:slim-)?(\d\.\d+.\d+)").unwrap();
-        let mut items = self_.download_items.borrow_mut();
-        let item = match items.get_mut(version) {
-            None => {
-                let item =
-                    crate::ui::widgets::download_manager::download_item::EpicDownloadItem::new();
-                debug!("Adding item to the list under: {}", version);
-                items.insert(version.to_string(), item.clone());
-                item
-            }
-            Some(_) => {
-                return;
+        let item = {
+            let mut items = self_.download_items.borrow_mut();
+            if items.contains_key(version) {
+                return None;
             }
+            let item = crate::ui::widgets::download_manager::download_item::EpicDownloadItem::new();
+            debug!("Adding item to the list under: {}", version);
+            items.insert(version.to_string(), item.clone());
+            item
         };
         if let Some(w) = self_.window.get() {
             item.set_window(w);
@@ -237,7 +342,12 @@ impl Docker for crate::ui::widgets::download_manager::EpicDownloadManager {
         self_.downloads.append(&item);
 
         self.set_property("has-items", self_.downloads.first_child().is_some());
+        Some(item)
+    }
 
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    fn fetch_docker_manifest(&self, version: &str) {
+        let self_ = self.imp();
         if let Some(window) = self_.window.get() {
             let win_ = window.imp();
             if let Some(dclient) = &*win_.model.borrow().dclient.borrow() {
@@ -254,14 +364,26 @@ impl Docker for crate::ui::widgets::download_manager::EpicDownloadManager {
                                         manifest.download_size().unwrap_or(0),
                                         digests,
                                     ))
-                                    .unwrap();
+                                    .ok();
                             }
                             Err(e) => {
                                 error!("Unable to get manifest layers: {:?}", e);
+                                sender
+                                    .send(crate::ui::widgets::download_manager::Msg::DownloadFailed(
+                                        v,
+                                        DownloadError::Http(format!("Unable to get manifest layers: {e:?}")),
+                                    ))
+                                    .ok();
                             }
                         },
                         Err(e) => {
                             error!("Unable to get docker manifest {:?}", e);
+                            sender
+                                .send(crate::ui::widgets::download_manager::Msg::DownloadFailed(
+                                    v,
+                                    DownloadError::Http(format!("Unable to get docker manifest: {e:?}")),
+                                ))
+                                .ok();
                         }
                     };
                 });
@@ -292,8 +414,8 @@ impl Docker for crate::ui::widgets::download_manager::EpicDownloadManager {
     #[cfg(any(target_os = "linux", target_os = "macos"))]
     fn docker_download_progress(&self, version: &str, progress: u64) {
         let Some(item) = self.get_item(version) else {
-                            return;
-                      };
+            return;
+        };
         item.add_downloaded_size(u128::from(progress));
 
         self.emit_by_name::<()>("tick", &[]);
@@ -316,7 +438,8 @@ impl Docker for crate::ui::widgets::download_manager::EpicDownloadManager {
     fn docker_extract_digests(&self, version: &str) {
         let self_ = self.imp();
         if let Some(digests) = self_.docker_digests.borrow_mut().get_mut(version) {
-            let mut to_extract: Vec<String> = Vec::new();
+            // Layers are applied strictly in manifest order, a finished layer waits for the ones before it
+            let mut to_extract: Vec<PathBuf> = Vec::new();
             let Some(target) = self.docker_target_directory() else { return };
             for d in digests {
                 match d.1 {
@@ -326,7 +449,7 @@ impl Docker for crate::ui::widgets::download_manager::EpicDownloadManager {
                     DownloadStatus::Downloaded => {
                         let mut p = target.clone();
                         p.push(&d.0);
-                        to_extract.push(p.to_str().unwrap_or_default().to_string());
+                        to_extract.push(p);
                         d.1 = DownloadStatus::Extracting;
                     }
                     DownloadStatus::Extracted => {
@@ -351,28 +474,34 @@ impl Docker for crate::ui::widgets::download_manager::EpicDownloadManager {
                         },
                     );
                 if let Err(e) = std::fs::create_dir_all(&path) {
-                    warn!("Unable to create target directory: {}", e);
+                    self.download_failed(version, &DownloadError::io(&path, &e));
+                    return;
+                };
+                let can_path = match path.canonicalize() {
+                    Ok(p) => p,
+                    Err(e) => {
+                        self.download_failed(version, &DownloadError::io(&path, &e));
+                        return;
+                    }
                 };
-                let can_path = path.canonicalize().unwrap();
                 let sender = self_.sender.clone();
                 let v = version.to_string();
                 #[cfg(any(target_os = "linux", target_os = "macos"))]
                 {
                     self_.file_pool.execute(move || {
-                        match ghregistry::render::unpack_partial_files(
-                            to_extract.clone(),
-                            &can_path,
-                            "home/ue4/UnrealEngine/",
-                        ) {
+                        match layers::apply_layers(&to_extract, &can_path, "home/ue4/UnrealEngine/") {
                             Ok(_) => {
                                 sender.send(
                                     crate::ui::widgets::download_manager::Msg::DockerExtractionFinished(
                                         v,
                                     ),
-                                ).unwrap();
+                                ).ok();
                             }
                             Err(e) => {
-                                error!("Error during render of {:?}: {:?}", to_extract, e);
+                                error!("Error during render of {:?}: {}", to_extract, e);
+                                sender
+                                    .send(crate::ui::widgets::download_manager::Msg::DownloadFailed(v, e))
+                                    .ok();
                             }
                         };
                     });
@@ -389,6 +518,7 @@ impl Docker for crate::ui::widgets::download_manager::EpicDownloadManager {
                                     return;
                              };
             let Some(target) = self.docker_target_directory() else { return };
+            let keep_layers = layer_cache::cache_limit(&self_.settings) > 0;
             let mut remaining = 0;
             for d in digests {
                 match d.1 {
@@ -398,9 +528,15 @@ impl Docker for crate::ui::widgets::download_manager::EpicDownloadManager {
                     DownloadStatus::Extracted => {}
                     DownloadStatus::Extracting => {
                         d.1 = DownloadStatus::Extracted;
-                        let mut t = target.clone();
-                        t.push(&d.0);
-                        std::fs::remove_file(t).expect("Unable to remove digest file");
+                        if !keep_layers {
+                            let mut t = target.clone();
+                            t.push(&d.0);
+                            if let Err(e) = std::fs::remove_file(t) {
+                                warn!("Unable to remove digest file {:?}", e);
+                            }
+                            layer_cache::forget_layer(&d.0);
+                        }
+                        self.queue_file_finished(version, &d.0);
                         item.file_processed();
                     }
                 };
@@ -424,12 +560,35 @@ impl Docker for crate::ui::widgets::download_manager::EpicDownloadManager {
             let e = l.engines.clone();
             e.run_refresh();
         }
+        if let Some(v) = item.version() {
+            self.queue_remove(&v);
+            self_.docker_digests.borrow_mut().remove(&v);
+        }
         self.finish(item);
+        self.evict_docker_layers();
+    }
+
+    fn evict_docker_layers(&self) {
+        let self_ = self.imp();
+        let Some(target) = self.docker_target_directory() else { return };
+        let keep: HashSet<String> = self_
+            .docker_digests
+            .borrow()
+            .values()
+            .flat_map(|digests| digests.iter().map(|(d, _)| d.clone()))
+            .collect();
+        let (removed, freed) =
+            layer_cache::evict(&target, layer_cache::cache_limit(&self_.settings), &keep);
+        if removed > 0 {
+            debug!("Removed {} cached layers, freed {} bytes", removed, freed);
+        }
     }
 
     #[cfg(any(target_os = "linux", target_os = "macos"))]
     fn cancel_docker_download(&self, version: String) {
         let self_ = self.imp();
+        self.queue_remove(&version);
+        self.take_restored(&version);
         if let Some(item) = self.get_item(&version) {
             self.send_to_thread_sender(&version.clone(), &ThreadMessages::Cancel);
             item.set_property("status", "Canceled".to_string());
@@ -446,7 +605,7 @@ impl Docker for crate::ui::widgets::download_manager::EpicDownloadManager {
                         self_
                             .sender
                             .send(DockerCanceled(version.clone(), digest))
-                            .unwrap();
+                            .ok();
                     }
                 }
             }
@@ -465,15 +624,45 @@ impl Docker for crate::ui::widgets::download_manager::EpicDownloadManager {
     #[cfg(any(target_os = "linux", target_os = "macos"))]
     fn resume_docker_download(&self, version: String) {
         let self_ = self.imp();
+        if self.take_restored(&version).is_some() {
+            if let Some(item) = self.get_item(&version) {
+                item.set_property("status", "initializing...".to_string());
+            }
+            self.fetch_docker_manifest(&version);
+            return;
+        }
         if let Some(values) = self_.paused_docker_digests.borrow_mut().remove(&version) {
             for digest in values {
                 self_
                     .sender
                     .send(DockerBlobFailed(version.clone(), digest))
-                    .unwrap();
+                    .ok();
             }
         }
     }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    fn retry_docker_download(&self, version: String) {
+        let self_ = self.imp();
+        let started = match self_.docker_digests.borrow_mut().get_mut(&version) {
+            None => false,
+            Some(digests) => {
+                for d in digests {
+                    if matches!(d.1, DownloadStatus::Extracting) {
+                        d.1 = DownloadStatus::Downloaded;
+                    }
+                }
+                true
+            }
+        };
+        if !started {
+            // Failed before the layers were known
+            self.fetch_docker_manifest(&version);
+            return;
+        }
+        self.resume_docker_download(version.clone());
+        self.docker_extract_digests(&version);
+    }
 }
 
 #[cfg(any(target_os = "linux", target_os = "macos"))]
@@ -481,14 +670,14 @@ fn process_docker_thread_message(
     version: String,
     digest: (String, u64),
     sender: &Sender<crate::ui::widgets::download_manager::Msg>,
-    m: &crate::ui::widgets::download_manager::ThreadMessages,
+    m: &crate::download::ThreadMessages,
 ) {
     match m {
         ThreadMessages::Cancel => {
-            sender.send(Msg::DockerCanceled(version, digest)).unwrap();
+            sender.send(Msg::DockerCanceled(version, digest)).ok();
         }
         ThreadMessages::Pause => {
-            sender.send(Msg::DockerPaused(version, digest)).unwrap();
+            sender.send(Msg::DockerPaused(version, digest)).ok();
         }
     }
 }
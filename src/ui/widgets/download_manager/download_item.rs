@@ -1,6 +1,10 @@
 use crate::ui::widgets::download_manager::asset::Asset;
 use crate::ui::widgets::download_manager::docker::Docker;
 use crate::ui::widgets::download_manager::epic_file::EpicFile;
+use crate::ui::widgets::download_manager::error::Failures;
+use crate::ui::widgets::download_manager::history::{DownloadResult, History};
+use crate::ui::widgets::download_manager::priority::Priority;
+use crate::ui::widgets::download_manager::space::DiskSpace;
 use crate::ui::widgets::download_manager::PostDownloadAction;
 use gtk4::glib::clone;
 use gtk4::{gio, glib, prelude::*, subclass::prelude::*, CompositeTemplate};
@@ -39,10 +43,13 @@ pub mod imp {
         release: RefCell<Option<String>>,
         paused: RefCell<bool>,
         canceled: RefCell<bool>,
+        failed: RefCell<bool>,
         item_type: RefCell<ItemType>,
         speed: RefCell<Option<String>>,
+        hosts: RefCell<Option<String>>,
         target: RefCell<Option<String>>,
         path: RefCell<Option<String>>,
+        error: RefCell<Option<String>>,
         pub total_size: RefCell<u128>,
         pub downloaded_size: RefCell<u128>,
         pub total_files: RefCell<u64>,
@@ -80,10 +87,13 @@ pub mod imp {
                 release: RefCell::new(None),
                 paused: RefCell::new(false),
                 canceled: RefCell::new(false),
+                failed: RefCell::new(false),
                 item_type: RefCell::new(ItemType::Unknown),
                 speed: RefCell::new(None),
+                hosts: RefCell::new(None),
                 target: RefCell::new(None),
                 path: RefCell::new(None),
+                error: RefCell::new(None),
                 total_size: RefCell::new(0),
                 downloaded_size: RefCell::new(0),
                 total_files: RefCell::new(0),
@@ -120,13 +130,16 @@ pub mod imp {
                     glib::ParamSpecString::builder("label").build(),
                     glib::ParamSpecBoolean::builder("paused").build(),
                     glib::ParamSpecBoolean::builder("canceled").build(),
+                    glib::ParamSpecBoolean::builder("failed").build(),
                     glib::ParamSpecString::builder("asset").build(),
                     glib::ParamSpecString::builder("version").build(),
                     glib::ParamSpecString::builder("release").build(),
                     glib::ParamSpecString::builder("speed").build(),
+                    glib::ParamSpecString::builder("hosts").build(),
                     glib::ParamSpecString::builder("target").build(),
                     glib::ParamSpecString::builder("path").build(),
                     glib::ParamSpecString::builder("status").build(),
+                    glib::ParamSpecString::builder("error").build(),
                     glib::ParamSpecObject::builder::<Texture>("thumbnail").build(),
                 ]
             });
@@ -180,6 +193,12 @@ pub mod imp {
                     self.stack.set_visible_child_name("label");
                     self.status.replace(status);
                 }
+                "error" => {
+                    let error = value
+                        .get::<Option<String>>()
+                        .expect("type conformity checked by `Object::set_property`");
+                    self.error.replace(error);
+                }
                 "target" => {
                     let target = value
                         .get::<Option<String>>()
@@ -204,12 +223,24 @@ pub mod imp {
                         .expect("type conformity checked by `Object::set_property`");
                     self.canceled.replace(canceled);
                 }
+                "failed" => {
+                    let failed = value
+                        .get()
+                        .expect("type conformity checked by `Object::set_property`");
+                    self.failed.replace(failed);
+                }
                 "speed" => {
                     let speed = value
                         .get::<Option<String>>()
                         .expect("type conformity checked by `Object::set_property`");
                     self.speed.replace(speed);
                 }
+                "hosts" => {
+                    let hosts = value
+                        .get::<Option<String>>()
+                        .expect("type conformity checked by `Object::set_property`");
+                    self.hosts.replace(hosts);
+                }
                 "asset" => {
                     let asset = value
                         .get::<Option<String>>()
@@ -253,9 +284,12 @@ pub mod imp {
                 "status" => self.status.borrow().to_value(),
                 "paused" => self.paused.borrow().to_value(),
                 "canceled" => self.canceled.borrow().to_value(),
+                "failed" => self.failed.borrow().to_value(),
                 "item-type" => self.item_type.borrow().to_value(),
                 "speed" => self.speed.borrow().to_value(),
+                "hosts" => self.hosts.borrow().to_value(),
                 "path" => self.path.borrow().to_value(),
+                "error" => self.error.borrow().to_value(),
                 "thumbnail" => self.thumbnail.borrow().to_value(),
                 _ => unimplemented!(),
             }
@@ -267,6 +301,7 @@ pub mod imp {
             obj.setup_actions();
             obj.setup_messaging();
             obj.setup_timer();
+            obj.setup_drag_and_drop();
         }
     }
 
@@ -325,7 +360,7 @@ impl EpicDownloadItem {
 
     fn speed_update(&self) {
         let self_ = self.imp();
-        if self.canceled() || self.paused() {
+        if self.canceled() || self.paused() || self.failed() {
             return;
         }
         if let Some(speed) = {
@@ -382,6 +417,80 @@ impl EpicDownloadItem {
             })
         );
         get_action!(self_.actions, @pause).set_enabled(false);
+
+        action!(
+            self_.actions,
+            "retry",
+            clone!(@weak self as item =>  move |_, _| {
+                if let Some(dm) = item.imp().download_manager.get() {
+                    dm.retry_download(&item);
+                }
+            })
+        );
+        get_action!(self_.actions, @retry).set_enabled(false);
+
+        action!(
+            self_.actions,
+            "move_top",
+            clone!(@weak self as item =>  move |_, _| {
+                if let Some(dm) = item.imp().download_manager.get() {
+                    dm.move_item_to_top(&item);
+                }
+            })
+        );
+        action!(
+            self_.actions,
+            "move_up",
+            clone!(@weak self as item =>  move |_, _| {
+                if let Some(dm) = item.imp().download_manager.get() {
+                    dm.move_item_up(&item);
+                }
+            })
+        );
+        action!(
+            self_.actions,
+            "move_down",
+            clone!(@weak self as item =>  move |_, _| {
+                if let Some(dm) = item.imp().download_manager.get() {
+                    dm.move_item_down(&item);
+                }
+            })
+        );
+        action!(
+            self_.actions,
+            "pause_others",
+            clone!(@weak self as item =>  move |_, _| {
+                if let Some(dm) = item.imp().download_manager.get() {
+                    dm.pause_others(&item);
+                }
+            })
+        );
+    }
+
+    /// Allow reordering the download list by dragging items onto each other
+    pub fn setup_drag_and_drop(&self) {
+        let drag = gtk4::DragSource::new();
+        drag.set_actions(gtk4::gdk::DragAction::MOVE);
+        drag.connect_prepare(
+            clone!(@weak self as item => @default-return None, move |_, _, _| {
+                item.key()
+                    .map(|key| gtk4::gdk::ContentProvider::for_value(&key.to_value()))
+            }),
+        );
+        self.add_controller(&drag);
+
+        let drop = gtk4::DropTarget::new(String::static_type(), gtk4::gdk::DragAction::MOVE);
+        drop.connect_drop(
+            clone!(@weak self as item => @default-return false, move |_, value, _, _| {
+                let Ok(key) = value.get::<String>() else { return false };
+                if let Some(dm) = item.imp().download_manager.get() {
+                    dm.move_item_before(&key, &item);
+                    return true;
+                }
+                false
+            }),
+        );
+        self.add_controller(&drop);
     }
 
     fn cancel(&self) {
@@ -391,6 +500,14 @@ impl EpicDownloadItem {
         self.set_property("canceled", true);
 
         if let Some(dm) = self_.download_manager.get() {
+            dm.history_record(
+                self,
+                if self.failed() {
+                    DownloadResult::Failed
+                } else {
+                    DownloadResult::Canceled
+                },
+            );
             match self.item_type() {
                 ItemType::Unknown => {}
                 ItemType::Asset => {
@@ -409,11 +526,15 @@ impl EpicDownloadItem {
                     }
                 }
             }
+            if let Some(key) = self.key() {
+                dm.release_space(&key);
+            }
         }
         self.remove_from_parent_with_timer(15);
     }
 
-    fn pause(&self) {
+    /// Toggle between paused and running
+    pub fn pause(&self) {
         let self_ = self.imp();
         get_action!(self_.actions, @pause).set_enabled(false);
         glib::timeout_add_seconds_local(
@@ -424,12 +545,15 @@ impl EpicDownloadItem {
                 glib::Continue(false)
             }),
         );
+        // Update the state first so the download manager sees the new state
+        let paused = self.paused();
+        self.set_property("paused", !paused);
         if let Some(dm) = self_.download_manager.get() {
             match self.item_type() {
                 ItemType::Unknown => {}
                 ItemType::Asset => {
                     if let Some(asset) = self.release() {
-                        if self.paused() {
+                        if paused {
                             self_
                                 .pause_button
                                 .set_icon_name("media-playback-pause-symbolic");
@@ -444,7 +568,7 @@ impl EpicDownloadItem {
                 }
                 ItemType::Docker => {
                     if let Some(v) = self.version() {
-                        if self.paused() {
+                        if paused {
                             self_
                                 .pause_button
                                 .set_icon_name("media-playback-pause-symbolic");
@@ -459,7 +583,7 @@ impl EpicDownloadItem {
                 }
                 ItemType::Epic => {
                     if let Some(v) = self.version() {
-                        if self.paused() {
+                        if paused {
                             self_
                                 .pause_button
                                 .set_icon_name("media-playback-pause-symbolic");
@@ -474,13 +598,32 @@ impl EpicDownloadItem {
                 }
             }
         }
-        self.set_property("paused", !self.paused());
     }
 
     pub fn setup_messaging(&self) {
         let _self_: &imp::EpicDownloadItem = self.imp();
     }
 
+    /// Show an item restored from the download queue as paused, waiting for the user to resume it
+    pub fn set_restored(&self, finished_files: u64) {
+        let self_ = self.imp();
+        self.set_property("paused", true);
+        self_
+            .pause_button
+            .set_icon_name("media-playback-start-symbolic");
+        get_action!(self_.actions, @pause).set_enabled(true);
+        get_action!(self_.actions, @cancel).set_enabled(true);
+        let total = *self_.total_files.borrow();
+        if total > 0 {
+            self.set_property(
+                "status",
+                format!("Paused ({finished_files}/{total} files finished)"),
+            );
+        } else {
+            self.set_property("status", "Paused".to_string());
+        }
+    }
+
     pub fn set_total_size(&self, size: u128) {
         let self_ = self.imp();
         self_.total_size.replace(size);
@@ -504,6 +647,11 @@ impl EpicDownloadItem {
         self.property("target")
     }
 
+    /// Reason of the last failure
+    pub fn error(&self) -> Option<String> {
+        self.property("error")
+    }
+
     pub fn asset(&self) -> Option<String> {
         self.property("asset")
     }
@@ -516,6 +664,15 @@ impl EpicDownloadItem {
         self.property("release")
     }
 
+    /// Key of the item in the download manager
+    pub fn key(&self) -> Option<String> {
+        match self.item_type() {
+            ItemType::Asset => self.release(),
+            ItemType::Docker | ItemType::Epic => self.version(),
+            ItemType::Unknown => None,
+        }
+    }
+
     pub fn paused(&self) -> bool {
         self.property("paused")
     }
@@ -524,6 +681,43 @@ impl EpicDownloadItem {
         self.property("canceled")
     }
 
+    pub fn failed(&self) -> bool {
+        self.property("failed")
+    }
+
+    /// Pausing is only possible once the download is running
+    pub fn can_pause(&self) -> bool {
+        let self_ = self.imp();
+        get_action!(self_.actions, @pause).is_enabled()
+    }
+
+    /// Stop showing progress and display the reason the download failed
+    pub fn fail(&self, reason: &str) {
+        let self_ = self.imp();
+        self.set_property("failed", true);
+        self.set_property("error", reason);
+        get_action!(self_.actions, @pause).set_enabled(false);
+        get_action!(self_.actions, @cancel).set_enabled(true);
+        self.set_property("speed", String::new());
+        self.set_property(
+            "status",
+            format!("Failed: {}", glib::markup_escape_text(reason)),
+        );
+        self_.stack.add_css_class("error");
+        get_action!(self_.actions, @retry).set_enabled(true);
+    }
+
+    /// Leave the failed state before the download is retried
+    pub fn recover(&self) {
+        let self_ = self.imp();
+        self.set_property("failed", false);
+        self.set_property("error", None::<String>);
+        self_.stack.remove_css_class("error");
+        get_action!(self_.actions, @retry).set_enabled(false);
+        get_action!(self_.actions, @pause).set_enabled(true);
+        self.set_property("status", "Retrying...".to_string());
+    }
+
     pub fn item_type(&self) -> ItemType {
         self.property("item-type")
     }
@@ -535,7 +729,7 @@ impl EpicDownloadItem {
 
     pub fn file_processed(&self) {
         let self_ = self.imp();
-        if self.canceled() || self.paused() {
+        if self.canceled() || self.paused() || self.failed() {
             return;
         }
         self_.stack.set_visible_child_name("progress");
@@ -580,7 +774,7 @@ impl EpicDownloadItem {
 
     pub fn add_downloaded_size(&self, size: u128) {
         let self_ = self.imp();
-        if self.canceled() || self.paused() {
+        if self.canceled() || self.paused() || self.failed() {
             return;
         }
         get_action!(self_.actions, @cancel).set_enabled(true);
@@ -612,6 +806,20 @@ impl EpicDownloadItem {
         }
     }
 
+    /// Take back data that was counted as downloaded but has to be downloaded again
+    pub fn remove_downloaded_size(&self, size: u128) {
+        let self_ = self.imp();
+        let new_size = self.downloaded_size().saturating_sub(size);
+        let total = self.total_size();
+        if total > 0 {
+            self_
+                .download_progress
+                .set_fraction(new_size as f64 / total as f64);
+        }
+        self_.downloaded_size.replace(new_size);
+        self_.download_progress.set_sensitive(true);
+    }
+
     pub fn add_extracted_size(&self, size: u128) {
         let self_ = self.imp();
         if self.canceled() || self.paused() {
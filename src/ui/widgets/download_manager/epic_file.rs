@@ -1,4 +1,10 @@
-use crate::ui::widgets::download_manager::{download_item, Msg, ThreadMessages};
+use crate::download::error::DownloadError;
+use crate::download::pipeline::SpaceNeed;
+use crate::download::ThreadMessages;
+use crate::ui::widgets::download_manager::error::Failures;
+use crate::ui::widgets::download_manager::queue::{Queue, QueuedDownload};
+use crate::ui::widgets::download_manager::space::{DiskSpace, EXTRACTION_FACTOR};
+use crate::ui::widgets::download_manager::{download_item, Msg};
 use crate::ui::widgets::logged_in::engines::epic_download::Blob;
 use crate::ui::widgets::logged_in::refresh::Refresh;
 use glib::clone;
@@ -20,6 +26,9 @@ use std::path::PathBuf;
 use std::sync::mpsc::Receiver;
 use zip::ZipArchive;
 
+/// How many times an interrupted engine download is continued before giving up
+const MAX_DOWNLOAD_ATTEMPTS: u32 = 5;
+
 pub trait EpicFile {
     fn perform_file_download(&self, _url: &str, _size: u64, _version: &str) {
         unimplemented!()
@@ -29,6 +38,15 @@ pub trait EpicFile {
         unimplemented!()
     }
 
+    /// Show an engine download saved by a previous run
+    fn restore_epic_download(&self, _queued: &QueuedDownload) {
+        unimplemented!()
+    }
+
+    fn create_epic_item(&self, _version: &str) -> Option<download_item::EpicDownloadItem> {
+        unimplemented!()
+    }
+
     fn epic_finished(&self, _item: &download_item::EpicDownloadItem) {
         unimplemented!()
     }
@@ -45,6 +63,11 @@ pub trait EpicFile {
         unimplemented!()
     }
 
+    /// The download started over, forget the progress reported so far
+    fn epic_download_restarted(&self, _version: &str) {
+        unimplemented!()
+    }
+
     fn epic_file_extraction_progress(&self, _version: &str, _data: u64) {
         unimplemented!()
     }
@@ -76,21 +99,39 @@ impl EpicFile for crate::ui::widgets::download_manager::EpicDownloadManager {
         let Some(item) = self.get_item(version) else {
             return;
         };
+        let Some(engines) = self.engine_target_directory() else { return };
+        let mut archive = engines.clone();
+        archive.push("epic");
+        archive.push(version);
+        if !self.reserve_space(
+            version,
+            vec![
+                SpaceNeed::new(archive.clone(), size),
+                SpaceNeed::new(engines, size * EXTRACTION_FACTOR),
+            ],
+            0,
+        ) {
+            return;
+        }
         item.set_property("status", "waiting for download slot".to_string());
         item.set_total_size(u128::from(size));
         item.set_total_files(1);
-        let (send, recv) = std::sync::mpsc::channel::<super::ThreadMessages>();
+        self.queue_set_totals(version, u128::from(size), 1);
+        let link = match Url::parse(url) {
+            Ok(l) => l,
+            Err(e) => {
+                self.download_failed(version, &DownloadError::Http(format!("{url}: {e}")));
+                return;
+            }
+        };
+        let (send, recv) = std::sync::mpsc::channel::<ThreadMessages>();
         self.add_thread_sender(version.to_string(), send);
         let sender = self_.sender.clone();
-        let link = Url::parse(url).expect("Valid URL");
         let ver = version.to_string();
-        let mut p = self
-            .engine_target_directory()
-            .expect("Invalid Target directory");
-        p.push("epic");
-        p.push(version);
+        let mut p = archive;
+        let limiter = self_.limiter.clone();
         self_.download_pool.execute(move || {
-            run(size, &recv, &sender, &link, ver, &mut p);
+            run(size, &recv, &sender, &link, ver, &mut p, &limiter);
         });
     }
 
@@ -115,20 +156,41 @@ impl EpicFile for crate::ui::widgets::download_manager::EpicDownloadManager {
 
     fn download_engine_from_epic(&self, version: &str) {
         debug!("Initializing epic engine download of {}", version);
+        if self.create_epic_item(version).is_none() {
+            return;
+        }
+        self.queue_add(
+            version,
+            download_item::ItemType::Epic,
+            version,
+            &None,
+            None,
+            &[],
+        );
+        self.start_version_file_download(version);
+    }
+
+    fn restore_epic_download(&self, queued: &QueuedDownload) {
+        let Some(item) = self.create_epic_item(&queued.id) else {
+            return;
+        };
+        item.set_total_size(queued.total_size);
+        item.set_total_files(queued.total_files);
+        item.set_restored(queued.finished_files);
+    }
+
+    fn create_epic_item(&self, version: &str) -> Option<download_item::EpicDownloadItem> {
         let self_ = self.imp();
         let re = Regex::new(r"Linux_Unreal_Engine_(\d\.\d+.\d+)_?(preview-\d+)?").unwrap();
-        let mut items = self_.download_items.borrow_mut();
-        let item = match items.get_mut(version) {
-            None => {
-                let item =
-                    crate::ui::widgets::download_manager::download_item::EpicDownloadItem::new();
-                debug!("Adding item to the list under: {}", version);
-                items.insert(version.to_string(), item.clone());
-                item
-            }
-            Some(_) => {
-                return;
+        let item = {
+            let mut items = self_.download_items.borrow_mut();
+            if items.contains_key(version) {
+                return None;
             }
+            let item = crate::ui::widgets::download_manager::download_item::EpicDownloadItem::new();
+            debug!("Adding item to the list under: {}", version);
+            items.insert(version.to_string(), item.clone());
+            item
         };
         if let Some(w) = self_.window.get() {
             item.set_window(w);
@@ -169,7 +231,7 @@ impl EpicFile for crate::ui::widgets::download_manager::EpicDownloadManager {
         self_.downloads.append(&item);
 
         self.set_property("has-items", self_.downloads.first_child().is_some());
-        self.start_version_file_download(version);
+        Some(item)
     }
 
     fn start_version_file_download(&self, version: &str) {
@@ -183,7 +245,7 @@ impl EpicFile for crate::ui::widgets::download_manager::EpicDownloadManager {
                 let self_ = dm.imp();
                 let s = self_.sender.clone();
                 if let Some(ver) = filter_versions(v, &vers) {
-                    s.send(Msg::EpicDownloadStart(ver.name, ver.url, ver.size)).unwrap();
+                    s.send(Msg::EpicDownloadStart(ver.name, ver.url, ver.size)).ok();
                 }
                 glib::Continue(false)
             }),
@@ -202,18 +264,18 @@ impl EpicFile for crate::ui::widgets::download_manager::EpicDownloadManager {
     fn epic_finished(&self, item: &download_item::EpicDownloadItem) {
         let self_ = self.imp();
         if let Some(version) = item.version() {
-            let mut p = self
-                .engine_target_directory()
-                .expect("Invalid Target directory");
-            p.push("epic");
-            p.push(version);
-            if let Err(e) = std::fs::remove_file(&p) {
-                error!("Unable to remove downloaded file: {}", e);
-            };
-            if let Some(parent) = p.parent() {
-                if let Err(e) = std::fs::remove_dir(parent) {
-                    error!("Unable to remove epic download directory: {}", e);
+            self.queue_remove(&version);
+            if let Some(mut p) = self.engine_target_directory() {
+                p.push("epic");
+                p.push(version);
+                if let Err(e) = std::fs::remove_file(&p) {
+                    error!("Unable to remove downloaded file: {}", e);
                 };
+                if let Some(parent) = p.parent() {
+                    if let Err(e) = std::fs::remove_dir(parent) {
+                        error!("Unable to remove epic download directory: {}", e);
+                    };
+                }
             }
             if let Some(window) = self_.window.get() {
                 let win_ = window.imp();
@@ -235,8 +297,18 @@ impl EpicFile for crate::ui::widgets::download_manager::EpicDownloadManager {
         self.emit_by_name::<()>("tick", &[]);
     }
 
+    fn epic_download_restarted(&self, version: &str) {
+        let Some(item) = self.get_item(version) else {
+            return;
+        };
+        item.remove_downloaded_size(item.downloaded_size());
+        self.emit_by_name::<()>("tick", &[]);
+    }
+
     fn cancel_epic_download(&self, version: String) {
         let self_ = self.imp();
+        self.queue_remove(&version);
+        self.take_restored(&version);
         if let Some(item) = self.get_item(&version) {
             self.send_to_thread_sender(&version.clone(), &ThreadMessages::Cancel);
             item.set_property("status", "Canceled".to_string());
@@ -246,9 +318,7 @@ impl EpicFile for crate::ui::widgets::download_manager::EpicDownloadManager {
             }
         }
 
-        let mut p = self
-            .engine_target_directory()
-            .expect("Invalid Target directory");
+        let Some(mut p) = self.engine_target_directory() else { return };
         p.push("epic");
         p.push(version);
         if let Err(e) = std::fs::remove_file(p) {
@@ -266,6 +336,11 @@ impl EpicFile for crate::ui::widgets::download_manager::EpicDownloadManager {
     }
 
     fn resume_epic_download(&self, version: String) {
+        if self.take_restored(&version).is_some() {
+            if let Some(item) = self.get_item(&version) {
+                item.set_property("status", "initializing...".to_string());
+            }
+        }
         self.start_version_file_download(&version);
     }
 
@@ -279,33 +354,57 @@ impl EpicFile for crate::ui::widgets::download_manager::EpicDownloadManager {
     fn epic_file_finished(&self, version: &str) {
         let self_ = self.imp();
         info!("Finished file download");
-        let mut p = self
-            .engine_target_directory()
-            .expect("Invalid Target directory");
+        let Some(engines) = self.engine_target_directory() else {
+            self.download_failed(
+                version,
+                &DownloadError::NotConfigured("Unreal Engine directory".to_string()),
+            );
+            return;
+        };
+        let mut p = engines.clone();
         p.push("epic");
         p.push(version);
         let re = Regex::new(r"Linux_Unreal_Engine_(\d\.\d+.\d+(?:_preview-\d+)?)").unwrap();
 
-        let mut target = self
-            .engine_target_directory()
-            .expect("Invalid Target directory");
+        let mut target = engines;
         if let Some(cap) = re.captures_iter(version).next() {
             target.push(&cap[1]);
         }
         if p.exists() {
             if let Some(item) = self.get_item(version) {
-                let metadata = std::fs::metadata(p.as_path()).expect("unable to read metadata");
+                let metadata = match std::fs::metadata(p.as_path()) {
+                    Ok(m) => m,
+                    Err(e) => {
+                        self.download_failed(version, &DownloadError::io(&p, &e));
+                        return;
+                    }
+                };
                 item.add_downloaded_size(item.total_size() - item.downloaded_size());
                 if u128::from(metadata.size()) == item.total_size() {
-                    let file = fs::File::open(&p).unwrap();
+                    let file = match fs::File::open(&p) {
+                        Ok(f) => f,
+                        Err(e) => {
+                            self.download_failed(version, &DownloadError::io(&p, &e));
+                            return;
+                        }
+                    };
                     if target.exists() {
                         warn!("Target already exists.");
                     }
-                    let archive = zip::ZipArchive::new(file).unwrap();
+                    let archive = match zip::ZipArchive::new(file) {
+                        Ok(a) => a,
+                        Err(e) => {
+                            self.download_failed(
+                                version,
+                                &DownloadError::Extraction(e.to_string()),
+                            );
+                            return;
+                        }
+                    };
                     item.set_total_files(archive.len() as u64);
                     let sender = self_.sender.clone();
                     let ver = version.to_string();
-                    let (send, recv) = std::sync::mpsc::channel::<super::ThreadMessages>();
+                    let (send, recv) = std::sync::mpsc::channel::<ThreadMessages>();
                     self.add_thread_sender(version.to_string(), send);
                     self_.file_pool.execute(move || {
                         extract(&target, archive, &sender, ver, &recv);
@@ -335,31 +434,66 @@ fn extract(
             }
         }
         let mut file_target = target.to_path_buf();
-        let mut file = archive.by_index(i).unwrap();
+        let mut file = match archive.by_index(i) {
+            Ok(f) => f,
+            Err(e) => {
+                sender
+                    .send(Msg::DownloadFailed(
+                        ver,
+                        DownloadError::Extraction(e.to_string()),
+                    ))
+                    .ok();
+                return;
+            }
+        };
         let outpath = if let Some(path) = file.enclosed_name() {
             path.to_owned()
         } else {
-            sender.send(Msg::EpicFileExtracted(ver.clone())).unwrap();
+            sender.send(Msg::EpicFileExtracted(ver.clone())).ok();
             continue;
         };
         file_target.push(&outpath);
         if file_target.exists() {
-            let metadata =
-                std::fs::metadata(file_target.as_path()).expect("unable to read metadata");
-            if metadata.size() == file.size() {
-                sender.send(Msg::EpicFileExtracted(ver.clone())).unwrap();
+            let same_size = std::fs::metadata(file_target.as_path())
+                .map_or(false, |metadata| metadata.size() == file.size());
+            if same_size {
+                sender.send(Msg::EpicFileExtracted(ver.clone())).ok();
                 continue;
             }
         }
         if (*file.name()).ends_with('/') {
-            fs::create_dir_all(&file_target).unwrap();
+            if let Err(e) = fs::create_dir_all(&file_target) {
+                sender
+                    .send(Msg::DownloadFailed(
+                        ver,
+                        DownloadError::io(&file_target, &e),
+                    ))
+                    .ok();
+                return;
+            }
         } else {
             if let Some(p) = file_target.parent() {
                 if !p.exists() {
-                    fs::create_dir_all(p).unwrap();
+                    if let Err(e) = fs::create_dir_all(p) {
+                        sender
+                            .send(Msg::DownloadFailed(ver, DownloadError::io(p, &e)))
+                            .ok();
+                        return;
+                    }
                 }
             }
-            let mut outfile = fs::File::create(&file_target).unwrap();
+            let mut outfile = match fs::File::create(&file_target) {
+                Ok(f) => f,
+                Err(e) => {
+                    sender
+                        .send(Msg::DownloadFailed(
+                            ver,
+                            DownloadError::io(&file_target, &e),
+                        ))
+                        .ok();
+                    return;
+                }
+            };
 
             let mut buffer: [u8; 1024] = [0; 1024];
             loop {
@@ -375,13 +509,21 @@ fn extract(
                             return;
                         }
                         if size > 0 {
-                            outfile.write_all(&buffer[0..size]).unwrap();
+                            if let Err(e) = outfile.write_all(&buffer[0..size]) {
+                                sender
+                                    .send(Msg::DownloadFailed(
+                                        ver,
+                                        DownloadError::io(&file_target, &e),
+                                    ))
+                                    .ok();
+                                return;
+                            }
                             sender
                                 .send(super::Msg::EpicFileExtractionProgress(
                                     ver.clone(),
                                     size as u64,
                                 ))
-                                .unwrap();
+                                .ok();
                         } else {
                             break;
                         }
@@ -407,9 +549,9 @@ fn extract(
                 }
             }
         }
-        sender.send(Msg::EpicFileExtracted(ver.clone())).unwrap();
+        sender.send(Msg::EpicFileExtracted(ver.clone())).ok();
     }
-    sender.send(Msg::EpicFileFinished(ver)).unwrap();
+    sender.send(Msg::EpicFileFinished(ver)).ok();
 }
 
 fn run(
@@ -419,6 +561,7 @@ fn run(
     link: &Url,
     ver: String,
     p: &mut PathBuf,
+    limiter: &crate::download::limiter::RateLimiter,
 ) {
     if let Ok(w) = crate::RUNNING.read() {
         if !*w {
@@ -435,77 +578,113 @@ fn run(
         link.to_string(),
         p
     );
-    fs::create_dir_all(p.parent().unwrap()).unwrap();
-    let mut client = if p.exists() {
-        let metadata = std::fs::metadata(p.as_path()).expect("unable to read metadata");
-        if metadata.size() == size {
+    if let Some(parent) = p.parent() {
+        if let Err(e) = fs::create_dir_all(parent) {
+            sender
+                .send(Msg::DownloadFailed(ver, DownloadError::io(parent, &e)))
+                .ok();
+            return;
+        }
+    }
+    let mut attempts: u32 = 0;
+    loop {
+        let offset = crate::download::http::partial_size(p);
+        if offset == size {
             debug!("Already downloaded {}", p.to_str().unwrap_or_default());
             sender
                 .send(super::Msg::EpicDownloadProgress(ver.clone(), size))
-                .unwrap();
-            sender.send(Msg::EpicFileFinished(ver)).unwrap();
+                .ok();
+            sender.send(Msg::EpicFileFinished(ver)).ok();
             return;
         };
-
-        let c = reqwest::blocking::Client::new();
-        debug!("Trying to resume download");
-        match c
-            .get(link.clone())
-            .header(
-                reqwest::header::RANGE,
-                format!("bytes={}-{}", metadata.size(), size),
-            )
-            .send()
-        {
-            Ok(c) => c,
-            Err(e) => {
-                error!("Failed to resume Engine download: {}", e);
-                return;
+        let error = match crate::download::http::get_from_offset(link, offset, Some(size)) {
+            Ok(response) => {
+                let mut file = match response.open_target(p) {
+                    Ok(f) => f,
+                    Err(e) => {
+                        error!("Unable to open {:?}: {}", p, e);
+                        sender
+                            .send(Msg::DownloadFailed(ver, DownloadError::io(p, &e)))
+                            .ok();
+                        return;
+                    }
+                };
+                let mut client = match response {
+                    crate::download::http::RangeResponse::Complete => {
+                        sender.send(Msg::EpicFileFinished(ver)).ok();
+                        return;
+                    }
+                    crate::download::http::RangeResponse::Resumed(r) => r,
+                    crate::download::http::RangeResponse::Restarted(r) => {
+                        if offset > 0 {
+                            // The data already on disk is received again
+                            sender.send(Msg::EpicDownloadRestarted(ver.clone())).ok();
+                        }
+                        r
+                    }
+                };
+                let mut buffer: [u8; 1024] = [0; 1024];
+                loop {
+                    if let Ok(m) = recv.try_recv() {
+                        process_epic_thread_message(ver, sender, &m);
+                        return;
+                    }
+                    if let Ok(w) = crate::RUNNING.read() {
+                        if !*w {
+                            return;
+                        }
+                    }
+                    match client.read(&mut buffer) {
+                        Ok(0) => {
+                            let received = crate::download::http::partial_size(p);
+                            if received == size {
+                                sender.send(Msg::EpicFileFinished(ver)).ok();
+                                return;
+                            }
+                            break DownloadError::Http(format!(
+                                "Connection closed after {received} of {size} bytes"
+                            ));
+                        }
+                        Ok(read) => {
+                            if let Ok(m) = recv.try_recv() {
+                                process_epic_thread_message(ver, sender, &m);
+                                return;
+                            }
+                            limiter.acquire(read);
+                            if let Err(e) = file.write_all(&buffer[0..read]) {
+                                sender
+                                    .send(Msg::DownloadFailed(ver, DownloadError::io(p, &e)))
+                                    .ok();
+                                return;
+                            }
+                            sender
+                                .send(super::Msg::EpicDownloadProgress(ver.clone(), read as u64))
+                                .ok();
+                        }
+                        Err(e) => {
+                            error!("Download error: {:?}", e);
+                            break DownloadError::Http(e.to_string());
+                        }
+                    }
+                }
             }
-        }
-    } else {
-        match reqwest::blocking::get(link.clone()) {
-            Ok(c) => c,
             Err(e) => {
                 error!("Failed to start Engine download: {}", e);
-                return;
+                DownloadError::Http(e.to_string())
             }
-        }
-    };
-    let mut buffer: [u8; 1024] = [0; 1024];
-    let mut file = File::create(&p).unwrap();
-    loop {
-        if let Ok(m) = recv.try_recv() {
-            process_epic_thread_message(ver, sender, &m);
+        };
+        attempts += 1;
+        if attempts > MAX_DOWNLOAD_ATTEMPTS {
+            error!(
+                "Giving up on Engine download {} after {} attempts: {}",
+                ver, attempts, error
+            );
+            sender.send(Msg::DownloadFailed(ver, error)).ok();
             return;
         }
-        if let Ok(w) = crate::RUNNING.read() {
-            if !*w {
-                return;
-            }
-        }
-        match client.read(&mut buffer) {
-            Ok(size) => {
-                if let Ok(m) = recv.try_recv() {
-                    process_epic_thread_message(ver, sender, &m);
-                    return;
-                }
-                if size > 0 {
-                    file.write_all(&buffer[0..size]).unwrap();
-                    sender
-                        .send(super::Msg::EpicDownloadProgress(ver.clone(), size as u64))
-                        .unwrap();
-                } else {
-                    break;
-                }
-            }
-            Err(e) => {
-                error!("Download error: {:?}", e);
-                break;
-            }
-        }
+        // Continue from what is already on disk after a short wait
+        std::thread::sleep(std::time::Duration::from_secs(2_u64.pow(attempts)));
     }
-    sender.send(Msg::EpicFileFinished(ver)).unwrap();
 }
 
 fn filter_versions(versions: Vec<Blob>, version: &str) -> Option<Blob> {
@@ -515,10 +694,10 @@ fn filter_versions(versions: Vec<Blob>, version: &str) -> Option<Blob> {
 fn process_epic_thread_message(version: String, sender: &Sender<Msg>, m: &ThreadMessages) {
     match m {
         ThreadMessages::Cancel => {
-            sender.send(Msg::EpicCanceled(version)).unwrap();
+            sender.send(Msg::EpicCanceled(version)).ok();
         }
         ThreadMessages::Pause => {
-            sender.send(Msg::EpicPaused(version)).unwrap();
+            sender.send(Msg::EpicPaused(version)).ok();
         }
     }
 }
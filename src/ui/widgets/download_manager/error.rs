@@ -0,0 +1,109 @@
+use crate::download::error::DownloadError;
+use crate::download::pipeline::DownloadedFile;
+use crate::ui::widgets::download_manager::asset::Asset;
+use crate::ui::widgets::download_manager::docker::Docker;
+use crate::ui::widgets::download_manager::download_item::{EpicDownloadItem, ItemType};
+use crate::ui::widgets::download_manager::epic_file::EpicFile;
+use crate::ui::widgets::download_manager::notifications::Notifications;
+use crate::ui::widgets::download_manager::priority::Priority;
+use gtk4::subclass::prelude::*;
+use log::{debug, error};
+use reqwest::Url;
+use std::path::PathBuf;
+
+pub trait Failures {
+    /// Stop the download and show the error on its item
+    fn download_failed(&self, _id: &str, _error: &DownloadError) {
+        unimplemented!()
+    }
+
+    /// A chunk could not be downloaded, it is downloaded again on retry
+    fn chunk_failed(&self, _link: Url, _p: PathBuf, _g: &str, _error: &DownloadError) {
+        unimplemented!()
+    }
+
+    /// A file could not be assembled, it is processed again on retry
+    fn file_failed(&self, _file: &str, _details: DownloadedFile, _error: DownloadError) {
+        unimplemented!()
+    }
+
+    /// Continue a failed download from where it stopped
+    fn retry_download(&self, _item: &EpicDownloadItem) {
+        unimplemented!()
+    }
+}
+
+impl Failures for crate::ui::widgets::download_manager::EpicDownloadManager {
+    fn download_failed(&self, id: &str, error: &DownloadError) {
+        let self_ = self.imp();
+        error!("Download of {} failed: {}", id, error);
+        let Some(item) = self.get_item(id) else { return };
+        if item.canceled() {
+            return;
+        }
+        if !item.failed() {
+            // Keep the work that is still running so retry can continue it
+            match item.item_type() {
+                ItemType::Asset => self.pause_asset_download(id.to_string()),
+                #[cfg(any(target_os = "linux", target_os = "macos"))]
+                ItemType::Docker => self.pause_docker_download(id.to_string()),
+                ItemType::Epic => self.pause_epic_download(id.to_string()),
+                _ => {}
+            }
+        }
+        item.fail(&error.to_string());
+        self.notify_failed(&item, &error.to_string());
+        if let Some(w) = self_.window.get() {
+            w.add_notification(
+                &format!("downloadfailed{id}"),
+                &format!(
+                    "Download of {} failed: {}",
+                    item.property::<Option<String>>("label").unwrap_or_default(),
+                    error
+                ),
+                gtk4::MessageType::Error,
+            );
+        }
+    }
+
+    fn chunk_failed(&self, link: Url, p: PathBuf, g: &str, error: &DownloadError) {
+        let self_ = self.imp();
+        self_
+            .paused_asset_chunks
+            .borrow_mut()
+            .entry(g.to_string())
+            .or_default()
+            .push((link, p));
+        if let Some(owner) = self.chunk_owner(g) {
+            self.download_failed(&owner, error);
+        }
+    }
+
+    fn file_failed(&self, file: &str, details: DownloadedFile, error: DownloadError) {
+        let self_ = self.imp();
+        let asset = details.asset.clone();
+        self.download_failed(&asset, &error);
+        self_
+            .failed_files
+            .borrow_mut()
+            .entry(asset)
+            .or_default()
+            .push((file.to_string(), details, error));
+    }
+
+    fn retry_download(&self, item: &EpicDownloadItem) {
+        let Some(key) = item.key() else { return };
+        debug!("Retrying {}", key);
+        if let Some(w) = self.imp().window.get() {
+            w.clear_notification(&format!("downloadfailed{key}"));
+        }
+        item.recover();
+        match item.item_type() {
+            ItemType::Asset => self.retry_asset_download(&key),
+            #[cfg(any(target_os = "linux", target_os = "macos"))]
+            ItemType::Docker => self.retry_docker_download(key),
+            ItemType::Epic => self.resume_epic_download(key),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,322 @@
+use crate::schema::download_history;
+use crate::ui::widgets::download_manager::asset::Asset;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use crate::ui::widgets::download_manager::docker::Docker;
+use crate::ui::widgets::download_manager::download_item::{EpicDownloadItem, ItemType};
+use crate::ui::widgets::download_manager::epic_file::EpicFile;
+use crate::ui::widgets::download_manager::queue::{
+    item_type_from_name, item_type_name, QueuedDownload,
+};
+use crate::ui::widgets::download_manager::PostDownloadAction;
+use chrono::{DateTime, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use gtk4::subclass::prelude::*;
+use log::{debug, error};
+
+/// How many entries the history view loads
+const HISTORY_LIMIT: i64 = 500;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DownloadResult {
+    Finished,
+    Failed,
+    Canceled,
+}
+
+impl DownloadResult {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Finished => "finished",
+            Self::Failed => "failed",
+            Self::Canceled => "canceled",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "finished" => Self::Finished,
+            "failed" => Self::Failed,
+            _ => Self::Canceled,
+        }
+    }
+}
+
+/// A download that ended, as stored in the `download_history` table
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub id: i32,
+    /// Release id for assets, version for engines
+    pub item_id: String,
+    pub item_type: ItemType,
+    pub label: String,
+    pub release: String,
+    pub size: u128,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    /// Seconds between adding the download and its end
+    pub duration: i64,
+    /// Bytes per second
+    pub average_speed: u128,
+    /// Vault selected when the download was added
+    pub target: Option<String>,
+    /// Directories the download was written to
+    pub targets: Vec<String>,
+    pub result: DownloadResult,
+    pub error: Option<String>,
+    pub asset: Option<egs_api::api::types::asset_info::AssetInfo>,
+    pub actions: Vec<PostDownloadAction>,
+}
+
+impl HistoryEntry {
+    /// Create an entry for a download that started at `started_at` and ends now
+    pub fn ended(
+        queued: QueuedDownload,
+        started_at: DateTime<Utc>,
+        release: String,
+        downloaded: u128,
+        targets: Vec<String>,
+        result: DownloadResult,
+        error: Option<String>,
+    ) -> Self {
+        let finished_at = Utc::now();
+        let duration = (finished_at - started_at).num_seconds().max(0);
+        Self {
+            id: 0,
+            item_id: queued.id,
+            item_type: queued.item_type,
+            label: queued.label,
+            release,
+            size: queued.total_size,
+            started_at,
+            finished_at,
+            duration,
+            average_speed: if duration > 0 {
+                downloaded / u128::from(duration.unsigned_abs())
+            } else {
+                downloaded
+            },
+            target: queued.target,
+            targets,
+            result,
+            error,
+            asset: queued.asset,
+            actions: queued.actions,
+        }
+    }
+
+    /// Check if the text appears in the name, release or any of the target paths
+    pub fn matches(&self, text: &str) -> bool {
+        let text = text.to_lowercase();
+        self.label.to_lowercase().contains(&text)
+            || self.release.to_lowercase().contains(&text)
+            || self
+                .targets
+                .iter()
+                .any(|t| t.to_lowercase().contains(&text))
+    }
+}
+
+/// Store an entry in the database
+pub fn insert(entry: &HistoryEntry) {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else { return };
+    if let Err(e) = diesel::insert_into(download_history::table)
+        .values((
+            download_history::item_id.eq(&entry.item_id),
+            download_history::item_type.eq(item_type_name(entry.item_type)),
+            download_history::label.eq(&entry.label),
+            download_history::release.eq(&entry.release),
+            download_history::size.eq(i64::try_from(entry.size).unwrap_or(i64::MAX)),
+            download_history::started_at.eq(entry.started_at.to_rfc3339()),
+            download_history::finished_at.eq(entry.finished_at.to_rfc3339()),
+            download_history::duration.eq(entry.duration),
+            download_history::average_speed
+                .eq(i64::try_from(entry.average_speed).unwrap_or(i64::MAX)),
+            download_history::target.eq(entry.target.clone().unwrap_or_default()),
+            download_history::targets
+                .eq(serde_json::to_string(&entry.targets).unwrap_or_else(|_| "[]".to_string())),
+            download_history::result.eq(entry.result.name()),
+            download_history::error.eq(entry.error.clone().unwrap_or_default()),
+            download_history::asset_info.eq(entry
+                .asset
+                .as_ref()
+                .and_then(|a| serde_json::to_string(a).ok())
+                .unwrap_or_default()),
+            download_history::actions
+                .eq(serde_json::to_string(&entry.actions).unwrap_or_else(|_| "[]".to_string())),
+        ))
+        .execute(&mut conn)
+    {
+        error!(
+            "Unable to save {} to the download history: {}",
+            entry.item_id, e
+        );
+    }
+}
+
+/// Load the most recent entries, newest first
+pub fn load() -> Vec<HistoryEntry> {
+    type Row = (
+        i32,
+        String,
+        String,
+        String,
+        String,
+        i64,
+        String,
+        String,
+        i64,
+        i64,
+        String,
+        String,
+        String,
+        String,
+        String,
+        String,
+    );
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else {
+        return Vec::new();
+    };
+    let rows: Vec<Row> = match download_history::table
+        .order(download_history::finished_at.desc())
+        .limit(HISTORY_LIMIT)
+        .load(&mut conn)
+    {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Unable to load the download history: {}", e);
+            return Vec::new();
+        }
+    };
+    rows.into_iter()
+        .map(
+            |(
+                id,
+                item_id,
+                item_type,
+                label,
+                release,
+                size,
+                started_at,
+                finished_at,
+                duration,
+                average_speed,
+                target,
+                targets,
+                result,
+                error,
+                asset_info,
+                actions,
+            )| HistoryEntry {
+                id,
+                item_id,
+                item_type: item_type_from_name(&item_type),
+                label,
+                release,
+                size: u128::try_from(size).unwrap_or_default(),
+                started_at: parse_time(&started_at),
+                finished_at: parse_time(&finished_at),
+                duration,
+                average_speed: u128::try_from(average_speed).unwrap_or_default(),
+                target: if target.is_empty() {
+                    None
+                } else {
+                    Some(target)
+                },
+                targets: serde_json::from_str(&targets).unwrap_or_default(),
+                result: DownloadResult::from_name(&result),
+                error: if error.is_empty() { None } else { Some(error) },
+                asset: serde_json::from_str(&asset_info).ok(),
+                actions: serde_json::from_str(&actions).unwrap_or_default(),
+            },
+        )
+        .collect()
+}
+
+fn parse_time(time: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(time).map_or_else(|_| Utc::now(), |d| d.with_timezone(&Utc))
+}
+
+pub trait History {
+    /// Remember when a download started and how to run it again
+    fn history_start(&self, _queued: QueuedDownload) {
+        unimplemented!()
+    }
+
+    /// Store the result of a download that ended
+    fn history_record(&self, _item: &EpicDownloadItem, _result: DownloadResult) {
+        unimplemented!()
+    }
+
+    /// Add a download from the history again
+    fn history_rerun(&self, _entry: &HistoryEntry) {
+        unimplemented!()
+    }
+}
+
+impl History for crate::ui::widgets::download_manager::EpicDownloadManager {
+    fn history_start(&self, queued: QueuedDownload) {
+        let self_ = self.imp();
+        self_
+            .history_started
+            .borrow_mut()
+            .insert(queued.id.clone(), (queued, Utc::now()));
+    }
+
+    fn history_record(&self, item: &EpicDownloadItem, result: DownloadResult) {
+        let self_ = self.imp();
+        let Some(key) = item.key() else { return };
+        let Some((mut queued, started_at)) = self_.history_started.borrow_mut().remove(&key) else {
+            debug!("No history start for {}", key);
+            return;
+        };
+        queued.total_size = item.total_size();
+        let release = queued
+            .asset
+            .as_ref()
+            .and_then(|a| a.release_info(&key))
+            .and_then(|r| r.version_title.filter(|v| !v.is_empty()).or(r.app_id))
+            .unwrap_or_else(|| key.clone());
+        let mut targets: Vec<String> = item.path().into_iter().collect();
+        for action in &queued.actions {
+            if let PostDownloadAction::Copy(t, _) = action {
+                targets.push(t.clone());
+            }
+        }
+        let error = if result == DownloadResult::Failed {
+            item.error()
+        } else {
+            None
+        };
+        insert(&HistoryEntry::ended(
+            queued,
+            started_at,
+            release,
+            item.downloaded_size(),
+            targets,
+            result,
+            error,
+        ));
+    }
+
+    fn history_rerun(&self, entry: &HistoryEntry) {
+        debug!("Downloading {} again", entry.item_id);
+        match entry.item_type {
+            ItemType::Asset => {
+                if let Some(asset) = &entry.asset {
+                    self.add_asset_download(
+                        entry.item_id.clone(),
+                        asset.clone(),
+                        &entry.target,
+                        Some(entry.actions.clone()),
+                    );
+                }
+            }
+            #[cfg(any(target_os = "linux", target_os = "macos"))]
+            ItemType::Docker => self.download_engine_from_docker(&entry.item_id),
+            ItemType::Epic => self.download_engine_from_epic(&entry.item_id),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use crate::ui::widgets::download_manager::download_item::EpicDownloadItem;
+use crate::ui::widgets::download_manager::queue::item_type_name;
+use crate::ui::widgets::download_manager::PostDownloadAction;
+use gtk4::prelude::*;
+use gtk4::subclass::prelude::*;
+use log::{debug, error, info};
+use std::process::Command;
+
+/// Details of a finished download, passed to the commands as environment variables
+#[derive(Debug, Clone, Default)]
+pub struct HookContext {
+    pub item_type: String,
+    pub asset_id: String,
+    /// Release id for assets, version for engines
+    pub release: String,
+    pub vault_path: Option<String>,
+    pub targets: Vec<String>,
+}
+
+impl HookContext {
+    fn environment(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("EAM_ITEM_TYPE", self.item_type.clone()),
+            ("EAM_ASSET_ID", self.asset_id.clone()),
+            ("EAM_RELEASE", self.release.clone()),
+            (
+                "EAM_VAULT_PATH",
+                self.vault_path.clone().unwrap_or_default(),
+            ),
+            (
+                "EAM_TARGETS",
+                std::env::join_paths(&self.targets)
+                    .map(|p| p.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+            ),
+        ]
+    }
+}
+
+/// Run the commands one after another, returns the failed commands with the reason
+pub fn run_commands(commands: &[String], context: &HookContext) -> Vec<(String, String)> {
+    let mut failed = Vec::new();
+    for command in commands {
+        info!("Running post download command: {}", command);
+        match build_command(command, context).output() {
+            Ok(output) if output.status.success() => {
+                debug!(
+                    "Post download command output: {}",
+                    String::from_utf8_lossy(&output.stdout)
+                );
+            }
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+                error!("Post download command {} failed: {}", command, stderr);
+                failed.push((
+                    command.clone(),
+                    if stderr.is_empty() {
+                        output.status.to_string()
+                    } else {
+                        stderr
+                    },
+                ));
+            }
+            Err(e) => {
+                error!("Unable to run post download command {}: {}", command, e);
+                failed.push((command.clone(), e.to_string()));
+            }
+        }
+    }
+    failed
+}
+
+#[cfg(target_os = "windows")]
+fn build_command(command: &str, context: &HookContext) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.args(["/C", command]).envs(context.environment());
+    cmd
+}
+
+#[cfg(not(target_os = "windows"))]
+fn build_command(command: &str, context: &HookContext) -> Command {
+    // Inside flatpak the command needs to run on the host to see the user's tools
+    if std::path::Path::new("/.flatpak-info").exists() {
+        let mut cmd = Command::new("flatpak-spawn");
+        cmd.arg("--host");
+        for (key, value) in context.environment() {
+            cmd.arg(format!("--env={key}={value}"));
+        }
+        cmd.args(["sh", "-c", command]);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", command]).envs(context.environment());
+        cmd
+    }
+}
+
+pub trait Hooks {
+    /// Run the global hook and the commands added to the download
+    fn run_post_download_hooks(&self, _item: &EpicDownloadItem) {
+        unimplemented!()
+    }
+
+    /// A post download command did not succeed
+    fn hook_failed(&self, _label: &str, _command: &str, _reason: &str) {
+        unimplemented!()
+    }
+}
+
+impl Hooks for crate::ui::widgets::download_manager::EpicDownloadManager {
+    fn run_post_download_hooks(&self, item: &EpicDownloadItem) {
+        let self_ = self.imp();
+        let mut commands: Vec<String> = Vec::new();
+        let global = self_.settings.string("post-download-hook").to_string();
+        if !global.trim().is_empty() {
+            commands.push(global);
+        }
+        let mut context = HookContext {
+            item_type: item_type_name(item.item_type()).to_string(),
+            asset_id: item.asset().unwrap_or_default(),
+            release: item.key().unwrap_or_default(),
+            vault_path: item.path(),
+            targets: Vec::new(),
+        };
+        for action in item.actions() {
+            match action {
+                PostDownloadAction::Copy(t, _) => context.targets.push(t),
+                PostDownloadAction::NoVault => context.vault_path = None,
+                PostDownloadAction::RunCommand(c) => commands.push(c),
+            }
+        }
+        if commands.is_empty() {
+            return;
+        }
+        let label = item.property::<Option<String>>("label").unwrap_or_default();
+        let sender = self_.sender.clone();
+        std::thread::spawn(move || {
+            for (command, reason) in run_commands(&commands, &context) {
+                sender
+                    .send(super::Msg::HookFailed(label.clone(), command, reason))
+                    .ok();
+            }
+        });
+    }
+
+    fn hook_failed(&self, label: &str, command: &str, reason: &str) {
+        let self_ = self.imp();
+        if let Some(w) = self_.window.get() {
+            w.add_notification(
+                &format!("hookfailed{command}"),
+                &format!("Command {command} after downloading {label} failed: {reason}"),
+                gtk4::MessageType::Error,
+            );
+        }
+    }
+}
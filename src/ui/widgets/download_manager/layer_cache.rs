@@ -0,0 +1,146 @@
+use crate::schema::docker_layer_cache;
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use gtk4::gio;
+use gtk4::prelude::*;
+use log::{debug, error, warn};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+const GIB: u64 = 1024 * 1024 * 1024;
+
+/// Directory the docker layers are downloaded to, named by their digest
+pub fn cache_directory(settings: &gio::Settings) -> Option<PathBuf> {
+    let mut target = PathBuf::from(settings.strv("unreal-engine-directories").get(0)?.to_str());
+    target.push("docker");
+    Some(target)
+}
+
+/// Maximum size of the kept layers in bytes, 0 removes layers after extraction
+pub fn cache_limit(settings: &gio::Settings) -> u64 {
+    u64::try_from(settings.int("docker-layer-cache-size")).unwrap_or_default() * GIB
+}
+
+/// Check if the layer is complete in the cache and mark it as used
+pub fn cached_layer(dir: &Path, digest: &str, size: u64) -> bool {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else { return false };
+    let known: Option<i64> = docker_layer_cache::table
+        .filter(docker_layer_cache::digest.eq(digest))
+        .select(docker_layer_cache::size)
+        .first(&mut conn)
+        .ok();
+    let Some(known) = known else { return false };
+    let complete = std::fs::metadata(dir.join(digest)).map_or(false, |m| {
+        m.len() == size && i64::try_from(size).map_or(false, |s| s == known)
+    });
+    if complete {
+        record_layer(digest, size);
+    } else {
+        forget_layer(digest);
+    }
+    complete
+}
+
+/// Remember a fully downloaded layer
+pub fn record_layer(digest: &str, size: u64) {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else { return };
+    if let Err(e) = diesel::replace_into(docker_layer_cache::table)
+        .values((
+            docker_layer_cache::digest.eq(digest),
+            docker_layer_cache::size.eq(i64::try_from(size).unwrap_or(i64::MAX)),
+            docker_layer_cache::last_used.eq(chrono::Utc::now().to_rfc3339()),
+        ))
+        .execute(&mut conn)
+    {
+        error!("Unable to add layer {} to the cache: {}", digest, e);
+    }
+}
+
+pub fn is_cached(digest: &str) -> bool {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else { return false };
+    docker_layer_cache::table
+        .filter(docker_layer_cache::digest.eq(digest))
+        .count()
+        .get_result::<i64>(&mut conn)
+        .map_or(false, |c| c > 0)
+}
+
+pub fn forget_layer(digest: &str) {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else { return };
+    if let Err(e) =
+        diesel::delete(docker_layer_cache::table.filter(docker_layer_cache::digest.eq(digest)))
+            .execute(&mut conn)
+    {
+        error!("Unable to remove layer {} from the cache: {}", digest, e);
+    }
+}
+
+/// Number of cached layers and their size in bytes
+pub fn cache_usage() -> (usize, u64) {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else {
+        return (0, 0);
+    };
+    let sizes: Vec<i64> = docker_layer_cache::table
+        .select(docker_layer_cache::size)
+        .load(&mut conn)
+        .unwrap_or_default();
+    (
+        sizes.len(),
+        sizes
+            .iter()
+            .map(|s| u64::try_from(*s).unwrap_or_default())
+            .sum(),
+    )
+}
+
+/// Remove the least recently used layers until the cache fits the limit, layers in `keep` are
+/// still needed by running downloads. Returns the number of removed layers and freed bytes
+pub fn evict(dir: &Path, limit: u64, keep: &HashSet<String>) -> (usize, u64) {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else {
+        return (0, 0);
+    };
+    let layers: Vec<(String, i64)> = match docker_layer_cache::table
+        .order(docker_layer_cache::last_used.asc())
+        .select((docker_layer_cache::digest, docker_layer_cache::size))
+        .load(&mut conn)
+    {
+        Ok(l) => l,
+        Err(e) => {
+            error!("Unable to load the docker layer cache: {}", e);
+            return (0, 0);
+        }
+    };
+    let mut total: u64 = layers
+        .iter()
+        .map(|(_, s)| u64::try_from(*s).unwrap_or_default())
+        .sum();
+    let mut removed = 0;
+    let mut freed = 0;
+    for (digest, size) in layers {
+        if total <= limit {
+            break;
+        }
+        if keep.contains(&digest) {
+            continue;
+        }
+        let size = u64::try_from(size).unwrap_or_default();
+        let path = dir.join(&digest);
+        if path.exists() {
+            if let Err(e) = std::fs::remove_file(&path) {
+                warn!("Unable to remove cached layer {:?}: {}", path, e);
+                continue;
+            }
+        }
+        debug!("Evicted docker layer {}", digest);
+        forget_layer(&digest);
+        total = total.saturating_sub(size);
+        removed += 1;
+        freed += size;
+    }
+    (removed, freed)
+}
@@ -0,0 +1,416 @@
+use crate::download::error::DownloadError;
+use log::{debug, warn};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+
+/// Marks a file or directory of a lower layer as deleted
+const WHITEOUT_PREFIX: &str = ".wh.";
+/// Hides everything lower layers put into the directory
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+/// Apply the layers in manifest order, later layers change and delete what earlier ones added
+pub fn apply_layers(layers: &[PathBuf], target: &Path, prefix: &str) -> Result<(), DownloadError> {
+    for layer in layers {
+        debug!("Applying layer {:?} to {:?}", layer, target);
+        apply_layer(layer, target, prefix)?;
+    }
+    Ok(())
+}
+
+/// Apply a single layer, only the files under `prefix` are written to the target
+pub fn apply_layer(layer: &Path, target: &Path, prefix: &str) -> Result<(), DownloadError> {
+    let extraction =
+        |e: std::io::Error| DownloadError::Extraction(format!("{}: {}", layer.display(), e));
+    // Whiteouts only hide files of the lower layers, so they go before anything of this layer is written
+    let mut archive = open_layer(layer).map_err(extraction)?;
+    for entry in archive.entries().map_err(extraction)? {
+        let entry = entry.map_err(extraction)?;
+        let Some(path) = layer_path(&entry.path().map_err(extraction)?, prefix) else {
+            continue;
+        };
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if name == OPAQUE_WHITEOUT {
+            let dir = target.join(path.parent().unwrap_or_else(|| Path::new("")));
+            clear_directory(&dir)?;
+        } else if let Some(hidden) = name.strip_prefix(WHITEOUT_PREFIX) {
+            // A whiteout of `.` or `..` would remove directories above the entry
+            if matches!(hidden, "" | "." | "..") {
+                warn!("Skipping invalid whiteout {:?}", path);
+                continue;
+            }
+            remove_path(&target.join(path.with_file_name(hidden)))?;
+        }
+    }
+
+    let mut archive = open_layer(layer).map_err(extraction)?;
+    for entry in archive.entries().map_err(extraction)? {
+        let mut entry = entry.map_err(extraction)?;
+        let Some(path) = layer_path(&entry.path().map_err(extraction)?, prefix) else {
+            continue;
+        };
+        if path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .map_or(false, |n| n.starts_with(WHITEOUT_PREFIX))
+        {
+            continue;
+        }
+        let dest = target.join(&path);
+        let kind = entry.header().entry_type();
+        if kind.is_dir() {
+            if dest.symlink_metadata().map_or(false, |m| !m.is_dir()) {
+                std::fs::remove_file(&dest).map_err(|e| DownloadError::io(&dest, &e))?;
+            }
+            std::fs::create_dir_all(&dest).map_err(|e| DownloadError::io(&dest, &e))?;
+            entry.unpack(&dest).map_err(extraction)?;
+            continue;
+        }
+        // A file of this layer replaces whatever the lower layers had at the path
+        remove_path(&dest)?;
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| DownloadError::io(parent, &e))?;
+        }
+        if kind.is_hard_link() {
+            let source = entry
+                .link_name()
+                .map_err(extraction)?
+                .and_then(|l| layer_path(&l, prefix));
+            match source {
+                Some(s) => link_file(&target.join(s), &dest)?,
+                None => warn!(
+                    "Skipping hard link {:?} pointing outside of the engine",
+                    path
+                ),
+            }
+        } else {
+            entry.unpack(&dest).map_err(extraction)?;
+        }
+    }
+    Ok(())
+}
+
+/// Layers are usually gzip compressed, but plain tar layers are allowed too
+fn open_layer(layer: &Path) -> std::io::Result<tar::Archive<Box<dyn Read>>> {
+    let mut file = BufReader::new(File::open(layer)?);
+    let mut magic = [0_u8; 2];
+    let compressed = file.read_exact(&mut magic).is_ok() && magic == [0x1f, 0x8b];
+    file.seek(SeekFrom::Start(0))?;
+    let reader: Box<dyn Read> = if compressed {
+        Box::new(flate2::read::GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+/// Path of the entry relative to the prefix, entries outside of it or leaving it are skipped
+fn layer_path(path: &Path, prefix: &str) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(c) => normalized.push(c),
+            Component::CurDir | Component::RootDir => {}
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+    let relative = normalized.strip_prefix(prefix).ok()?;
+    if relative.as_os_str().is_empty() {
+        None
+    } else {
+        Some(relative.to_path_buf())
+    }
+}
+
+/// Remove a file, link or directory tree, a missing path is fine
+fn remove_path(path: &Path) -> Result<(), DownloadError> {
+    let result = match path.symlink_metadata() {
+        Ok(m) if m.is_dir() => std::fs::remove_dir_all(path),
+        Ok(_) => std::fs::remove_file(path),
+        Err(_) => return Ok(()),
+    };
+    result.map_err(|e| DownloadError::io(path, &e))
+}
+
+fn clear_directory(dir: &Path) -> Result<(), DownloadError> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Ok(());
+    };
+    for entry in entries.flatten() {
+        remove_path(&entry.path())?;
+    }
+    Ok(())
+}
+
+fn link_file(source: &Path, dest: &Path) -> Result<(), DownloadError> {
+    if std::fs::hard_link(source, dest).is_ok() {
+        return Ok(());
+    }
+    std::fs::copy(source, dest)
+        .map(|_| ())
+        .map_err(|e| DownloadError::io(source, &e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    enum Entry<'a> {
+        File(&'a str, &'a str),
+        Dir(&'a str),
+        Link(&'a str, &'a str),
+    }
+
+    /// Scratch directory with a `target` the layers are applied to
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("eam-layers-{}-{}", name, std::process::id()));
+            std::fs::remove_dir_all(&dir).ok();
+            std::fs::create_dir_all(dir.join("target")).unwrap();
+            Self(dir)
+        }
+
+        fn target(&self) -> PathBuf {
+            self.0.join("target")
+        }
+
+        fn read(&self, path: &str) -> Option<String> {
+            std::fs::read_to_string(self.target().join(path)).ok()
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    fn header(path: &str, kind: tar::EntryType, size: u64) -> tar::Header {
+        let mut header = tar::Header::new_gnu();
+        // The name is written directly, `set_path` refuses the `..` the traversal test needs
+        header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+        header.set_entry_type(kind);
+        header.set_size(size);
+        header.set_mode(if kind.is_dir() { 0o755 } else { 0o644 });
+        header.set_cksum();
+        header
+    }
+
+    fn tar_layer(entries: &[Entry]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for entry in entries {
+            match entry {
+                Entry::File(path, content) => builder
+                    .append(
+                        &header(path, tar::EntryType::Regular, content.len() as u64),
+                        content.as_bytes(),
+                    )
+                    .unwrap(),
+                Entry::Dir(path) => builder
+                    .append(
+                        &header(path, tar::EntryType::Directory, 0),
+                        std::io::empty(),
+                    )
+                    .unwrap(),
+                Entry::Link(path, source) => {
+                    let mut h = header(path, tar::EntryType::Link, 0);
+                    h.set_link_name(source).unwrap();
+                    h.set_cksum();
+                    builder.append(&h, std::io::empty()).unwrap();
+                }
+            }
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn write_layer(scratch: &Scratch, name: &str, entries: &[Entry]) -> PathBuf {
+        let path = scratch.0.join(name);
+        std::fs::write(&path, tar_layer(entries)).unwrap();
+        path
+    }
+
+    #[test]
+    fn later_layer_overrides_earlier_file() {
+        let scratch = Scratch::new("override");
+        let layers = vec![
+            write_layer(
+                &scratch,
+                "1",
+                &[
+                    Entry::Dir("engine/"),
+                    Entry::File("engine/a.txt", "one"),
+                    Entry::File("engine/b.txt", "kept"),
+                ],
+            ),
+            write_layer(&scratch, "2", &[Entry::File("engine/a.txt", "two")]),
+        ];
+        apply_layers(&layers, &scratch.target(), "engine").unwrap();
+        assert_eq!(scratch.read("a.txt").as_deref(), Some("two"));
+        assert_eq!(scratch.read("b.txt").as_deref(), Some("kept"));
+    }
+
+    #[test]
+    fn files_outside_the_prefix_are_skipped() {
+        let scratch = Scratch::new("prefix");
+        let layers = vec![write_layer(
+            &scratch,
+            "1",
+            &[
+                Entry::File("etc/passwd", "root"),
+                Entry::File("engine/a.txt", "one"),
+            ],
+        )];
+        apply_layers(&layers, &scratch.target(), "engine").unwrap();
+        assert_eq!(scratch.read("a.txt").as_deref(), Some("one"));
+        assert!(!scratch.target().join("etc").exists());
+    }
+
+    #[test]
+    fn whiteout_removes_file_of_lower_layer() {
+        let scratch = Scratch::new("whiteout");
+        let layers = vec![
+            write_layer(
+                &scratch,
+                "1",
+                &[
+                    Entry::File("engine/a.txt", "one"),
+                    Entry::File("engine/b.txt", "kept"),
+                    Entry::File("engine/dir/c.txt", "nested"),
+                ],
+            ),
+            write_layer(
+                &scratch,
+                "2",
+                &[
+                    Entry::File("engine/.wh.a.txt", ""),
+                    Entry::File("engine/.wh.dir", ""),
+                ],
+            ),
+        ];
+        apply_layers(&layers, &scratch.target(), "engine").unwrap();
+        assert!(!scratch.target().join("a.txt").exists());
+        assert!(!scratch.target().join("dir").exists());
+        assert!(!scratch.target().join(".wh.a.txt").exists());
+        assert_eq!(scratch.read("b.txt").as_deref(), Some("kept"));
+    }
+
+    #[test]
+    fn whiteout_does_not_remove_file_of_same_layer() {
+        let scratch = Scratch::new("whiteout-same");
+        let layers = vec![
+            write_layer(&scratch, "1", &[Entry::File("engine/a.txt", "one")]),
+            write_layer(
+                &scratch,
+                "2",
+                &[
+                    Entry::File("engine/a.txt", "two"),
+                    Entry::File("engine/.wh.a.txt", ""),
+                ],
+            ),
+        ];
+        apply_layers(&layers, &scratch.target(), "engine").unwrap();
+        assert_eq!(scratch.read("a.txt").as_deref(), Some("two"));
+    }
+
+    #[test]
+    fn opaque_whiteout_clears_directory() {
+        let scratch = Scratch::new("opaque");
+        let layers = vec![
+            write_layer(
+                &scratch,
+                "1",
+                &[
+                    Entry::File("engine/dir/old.txt", "old"),
+                    Entry::File("engine/dir/sub/older.txt", "older"),
+                    Entry::File("engine/other.txt", "kept"),
+                ],
+            ),
+            write_layer(
+                &scratch,
+                "2",
+                &[
+                    Entry::Dir("engine/dir/"),
+                    Entry::File("engine/dir/.wh..wh..opq", ""),
+                    Entry::File("engine/dir/new.txt", "new"),
+                ],
+            ),
+        ];
+        apply_layers(&layers, &scratch.target(), "engine").unwrap();
+        assert!(!scratch.target().join("dir/old.txt").exists());
+        assert!(!scratch.target().join("dir/sub").exists());
+        assert!(!scratch.target().join("dir/.wh..wh..opq").exists());
+        assert_eq!(scratch.read("dir/new.txt").as_deref(), Some("new"));
+        assert_eq!(scratch.read("other.txt").as_deref(), Some("kept"));
+    }
+
+    #[test]
+    fn hard_link_points_at_file_in_target() {
+        let scratch = Scratch::new("hardlink");
+        let layers = vec![write_layer(
+            &scratch,
+            "1",
+            &[
+                Entry::File("engine/a.txt", "data"),
+                Entry::Link("engine/b.txt", "engine/a.txt"),
+            ],
+        )];
+        apply_layers(&layers, &scratch.target(), "engine").unwrap();
+        assert_eq!(scratch.read("b.txt").as_deref(), Some("data"));
+    }
+
+    #[test]
+    fn hard_link_outside_of_prefix_is_skipped() {
+        let scratch = Scratch::new("hardlink-outside");
+        let layers = vec![write_layer(
+            &scratch,
+            "1",
+            &[
+                Entry::File("etc/shadow", "secret"),
+                Entry::Link("engine/b.txt", "etc/shadow"),
+            ],
+        )];
+        apply_layers(&layers, &scratch.target(), "engine").unwrap();
+        assert!(!scratch.target().join("b.txt").exists());
+    }
+
+    #[test]
+    fn path_traversal_is_rejected() {
+        let scratch = Scratch::new("traversal");
+        let layers = vec![write_layer(
+            &scratch,
+            "1",
+            &[
+                Entry::File("engine/../../escaped.txt", "evil"),
+                Entry::File("engine/dir/../../../escaped2.txt", "evil"),
+                Entry::File("engine/.wh...", ""),
+                Entry::File("engine/a.txt", "one"),
+            ],
+        )];
+        apply_layers(&layers, &scratch.target(), "engine").unwrap();
+        assert!(!scratch.0.join("escaped.txt").exists());
+        assert!(!scratch.0.join("escaped2.txt").exists());
+        assert!(!std::env::temp_dir().join("escaped2.txt").exists());
+        // A whiteout of `..` must not remove the target's parent
+        assert!(scratch.0.join("1").exists());
+        assert_eq!(scratch.read("a.txt").as_deref(), Some("one"));
+    }
+
+    #[test]
+    fn gzip_layers_are_detected() {
+        let scratch = Scratch::new("gzip");
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder
+            .write_all(&tar_layer(&[Entry::File("engine/a.txt", "zipped")]))
+            .unwrap();
+        let path = scratch.0.join("1");
+        std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+        apply_layers(&[path], &scratch.target(), "engine").unwrap();
+        assert_eq!(scratch.read("a.txt").as_deref(), Some("zipped"));
+    }
+}
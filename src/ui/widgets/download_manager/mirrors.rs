@@ -0,0 +1,168 @@
+use crate::download::error::DownloadError;
+use crate::download::pipeline;
+use crate::ui::widgets::download_manager::error::Failures;
+use crate::ui::widgets::download_manager::priority::Priority;
+use gtk4::glib;
+use gtk4::glib::clone;
+use gtk4::subclass::prelude::*;
+use log::{debug, error, warn};
+use reqwest::Url;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub trait Mirrors {
+    /// Record a finished chunk download
+    fn host_succeeded(&self, _link: &Url, _g: &str, _bytes: u128, _duration: Duration) {
+        unimplemented!()
+    }
+
+    fn host_failed(&self, _link: &Url) {
+        unimplemented!()
+    }
+
+    /// Pick the best URL for a chunk, avoiding `exclude` when there are other options
+    fn best_chunk_url(&self, _guid: &str, _exclude: Option<&Url>) -> Option<Url> {
+        unimplemented!()
+    }
+
+    /// Download the chunk again from another host after an exponential backoff,
+    /// failing the download once all hosts were tried too often
+    fn retry_chunk(&self, _link: &Url, _p: PathBuf, _g: &str) {
+        unimplemented!()
+    }
+
+    /// Show statistics of the hosts used by a download item
+    fn update_item_hosts(&self, _id: &str) {
+        unimplemented!()
+    }
+}
+
+impl Mirrors for crate::ui::widgets::download_manager::EpicDownloadManager {
+    fn host_succeeded(&self, link: &Url, g: &str, bytes: u128, duration: Duration) {
+        let self_ = self.imp();
+        let Some(host) = link.host_str() else { return };
+        {
+            let mut stats = self_.host_stats.borrow_mut();
+            stats
+                .entry(host.to_string())
+                .or_default()
+                .succeeded(bytes, duration);
+        }
+        self_.chunk_attempts.borrow_mut().remove(g);
+        if let Some(id) = self.chunk_owner(g) {
+            self_
+                .item_hosts
+                .borrow_mut()
+                .entry(id.clone())
+                .or_default()
+                .insert(host.to_string());
+            self.update_item_hosts(&id);
+        }
+    }
+
+    fn host_failed(&self, link: &Url) {
+        let self_ = self.imp();
+        let Some(host) = link.host_str() else { return };
+        self_
+            .host_stats
+            .borrow_mut()
+            .entry(host.to_string())
+            .or_default()
+            .failures += 1;
+    }
+
+    fn best_chunk_url(&self, guid: &str, exclude: Option<&Url>) -> Option<Url> {
+        let self_ = self.imp();
+        let urls = self_.chunk_urls.borrow();
+        let candidates = urls.get(guid)?;
+        pipeline::best_url(candidates, &self_.host_stats.borrow(), exclude)
+    }
+
+    fn retry_chunk(&self, link: &Url, p: PathBuf, g: &str) {
+        let self_ = self.imp();
+        self.host_failed(link);
+        let attempt = {
+            let mut attempts = self_.chunk_attempts.borrow_mut();
+            let a = attempts.entry(g.to_string()).or_default();
+            *a += 1;
+            *a
+        };
+        let hosts = self_
+            .chunk_urls
+            .borrow()
+            .get(g)
+            .map_or(1, |u| u.len().max(1));
+        let limit = pipeline::attempt_limit(hosts);
+        if attempt > limit {
+            self_.chunk_attempts.borrow_mut().remove(g);
+            error!("Chunk {} failed {} times, giving up", g, limit);
+            self.chunk_failed(
+                link.clone(),
+                p,
+                g,
+                &DownloadError::Http(format!(
+                    "Chunk {g} could not be downloaded from any of {hosts} hosts"
+                )),
+            );
+            return;
+        }
+        let url = self
+            .best_chunk_url(g, Some(link))
+            .unwrap_or_else(|| link.clone());
+        let delay = pipeline::backoff_seconds(attempt);
+        warn!(
+            "Chunk {} failed on {:?}, attempt {} from {:?} in {}s",
+            g,
+            link.host_str(),
+            attempt,
+            url.host_str(),
+            delay
+        );
+        let guid = g.to_string();
+        glib::timeout_add_seconds_local(
+            delay,
+            clone!(@weak self as download_manager => @default-return glib::Continue(false), move || {
+                // Download was canceled while waiting
+                if download_manager.chunk_owner(&guid).is_none() {
+                    return glib::Continue(false);
+                }
+                let self_ = download_manager.imp();
+                self_
+                    .sender
+                    .send(super::Msg::PerformChunkDownload(url.clone(), p.clone(), guid.clone()))
+                    .ok();
+                glib::Continue(false)
+            }),
+        );
+    }
+
+    fn update_item_hosts(&self, id: &str) {
+        let self_ = self.imp();
+        let Some(item) = self.get_item(id) else { return };
+        let Some(hosts) = self_.item_hosts.borrow().get(id).cloned() else { return };
+        let stats = self_.host_stats.borrow();
+        let mut lines: Vec<String> = Vec::new();
+        let mut hosts: Vec<String> = hosts.into_iter().collect();
+        hosts.sort();
+        for host in hosts {
+            let Some(s) = stats.get(&host) else { continue };
+            let speed = s.speed().map_or_else(
+                || "-".to_string(),
+                |sp| {
+                    format!(
+                        "{}/s",
+                        byte_unit::Byte::from_bytes(sp)
+                            .get_appropriate_unit(false)
+                            .format(1)
+                    )
+                },
+            );
+            lines.push(format!(
+                "{}: {} chunks, {}, {} failures",
+                host, s.chunks, speed, s.failures
+            ));
+        }
+        debug!("Hosts for {}: {:?}", id, lines);
+        item.set_property("hosts", lines.join("\n"));
+    }
+}
@@ -1,12 +1,39 @@
 pub mod asset;
+pub mod conflict;
 pub mod docker;
-mod download_item;
+pub mod download_item;
 pub mod epic_file;
-
+pub mod error;
+pub mod history;
+pub mod hooks;
+pub mod layer_cache;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub mod layers;
+pub mod mirrors;
+pub mod notifications;
+pub mod priority;
+pub mod project_files;
+pub mod queue;
+pub mod schedule;
+pub mod space;
+
+use crate::download::error::DownloadError;
+use crate::download::pipeline;
+use crate::download::ThreadMessages;
 use crate::ui::widgets::download_manager::asset::Asset;
+use crate::ui::widgets::download_manager::conflict::{ConflictPolicy, Conflicts};
 use crate::ui::widgets::download_manager::docker::Docker;
 use crate::ui::widgets::download_manager::download_item::EpicDownloadItem;
 use crate::ui::widgets::download_manager::epic_file::EpicFile;
+use crate::ui::widgets::download_manager::error::Failures;
+use crate::ui::widgets::download_manager::history::{DownloadResult, History};
+use crate::ui::widgets::download_manager::hooks::Hooks;
+use crate::ui::widgets::download_manager::mirrors::Mirrors;
+use crate::ui::widgets::download_manager::notifications::Notifications;
+use crate::ui::widgets::download_manager::priority::Priority;
+use crate::ui::widgets::download_manager::project_files::ProjectFiles;
+use crate::ui::widgets::download_manager::queue::Queue;
+use crate::ui::widgets::download_manager::space::DiskSpace;
 use glib::clone;
 use gtk4::gdk::Texture;
 use gtk4::subclass::prelude::*;
@@ -37,8 +64,11 @@ pub enum Msg {
     RedownloadChunk(Url, PathBuf, String),
     PauseChunk(Url, PathBuf, String),
     CancelChunk(Url, PathBuf, String),
+    ChunkSlotFreed,
+    ChunkHostReport(Url, String, u128, std::time::Duration),
     ChunkDownloadProgress(String, u128, bool),
-    FinalizeFileDownload(String, asset::DownloadedFile),
+    FinalizeFileDownload(String, pipeline::DownloadedFile),
+    FileHashFailed(String, pipeline::DownloadedFile),
     FileAlreadyDownloaded(String, u128, String, String),
     FileExtracted(String),
     PerformDockerEngineDownload(String, u64, Vec<(String, u64)>),
@@ -55,7 +85,13 @@ pub enum Msg {
     EpicFileExtracted(String),
     EpicFileExtractionProgress(String, u64),
     EpicDownloadProgress(String, u64),
-    IOError(String),
+    EpicDownloadRestarted(String),
+    DownloadFailed(String, DownloadError),
+    ChunkFailed(Url, PathBuf, String, DownloadError),
+    FileFailed(String, pipeline::DownloadedFile, DownloadError),
+    HookFailed(String, String, String),
+    CopyConflict(String, String, PathBuf, PathBuf),
+    FileCopied(String, String, PathBuf, Option<PathBuf>, bool),
 }
 
 #[derive(Debug, Clone)]
@@ -66,16 +102,12 @@ pub enum DownloadStatus {
     Extracted,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum PostDownloadAction {
-    Copy(String, bool),
+    Copy(String, ConflictPolicy),
     NoVault,
-}
-
-#[derive(Debug, Clone, Eq, PartialEq)]
-pub enum ThreadMessages {
-    Cancel,
-    Pause,
+    /// Shell command to run once the download finished
+    RunCommand(String),
 }
 
 pub mod imp {
@@ -85,7 +117,8 @@ pub mod imp {
     use gtk4::glib::{ParamSpec, ParamSpecBoolean};
     use once_cell::sync::OnceCell;
     use std::cell::RefCell;
-    use std::collections::HashMap;
+    use std::collections::{HashMap, HashSet, VecDeque};
+    use std::sync::Arc;
     use threadpool::ThreadPool;
 
     #[derive(Debug, CompositeTemplate)]
@@ -98,12 +131,14 @@ pub mod imp {
         pub thumbnail_pool: ThreadPool,
         pub image_pool: ThreadPool,
         pub file_pool: ThreadPool,
+        pub limiter: Arc<crate::download::limiter::RateLimiter>,
+        pub chunk_cache: Arc<crate::download::chunk_cache::ChunkCache>,
         pub sender: gtk4::glib::Sender<super::Msg>,
         pub receiver: RefCell<Option<gtk4::glib::Receiver<super::Msg>>>,
         pub download_items: RefCell<
             HashMap<String, crate::ui::widgets::download_manager::download_item::EpicDownloadItem>,
         >,
-        pub downloaded_files: RefCell<HashMap<String, super::asset::DownloadedFile>>,
+        pub downloaded_files: RefCell<HashMap<String, crate::download::pipeline::DownloadedFile>>,
         pub downloaded_chunks: RefCell<HashMap<String, Vec<String>>>,
         pub asset_guids: RefCell<HashMap<String, Vec<String>>>,
         pub paused_asset_chunks: RefCell<HashMap<String, Vec<(Url, PathBuf)>>>,
@@ -111,8 +146,47 @@ pub mod imp {
         pub thread_senders: RefCell<HashMap<String, Vec<std::sync::mpsc::Sender<ThreadMessages>>>>,
         pub chunk_urls: RefCell<HashMap<String, Vec<Url>>>,
         pub docker_digests: RefCell<HashMap<String, Vec<(String, DownloadStatus)>>>,
+        pub restored_downloads: RefCell<HashMap<String, super::queue::QueuedDownload>>,
+        pub stored_chunks: RefCell<HashMap<String, HashSet<String>>>,
+        pub stored_files: RefCell<HashMap<String, HashSet<String>>>,
+        pub file_retries: RefCell<HashMap<String, u32>>,
+        pub delta_sources: RefCell<HashMap<String, Vec<PathBuf>>>,
+        pub local_chunks: RefCell<
+            HashMap<String, HashMap<String, Vec<crate::download::pipeline::LocalChunkPart>>>,
+        >,
+        pub queued_chunks: RefCell<HashMap<String, VecDeque<(Url, PathBuf, String)>>>,
+        pub running_chunks: RefCell<usize>,
+        pub host_stats: RefCell<HashMap<String, crate::download::pipeline::HostStats>>,
+        pub chunk_attempts: RefCell<HashMap<String, u32>>,
+        /// Chunks of files that failed hash validation, by guid with their asset, link and path
+        pub corrupted_chunks: RefCell<HashMap<String, (String, Url, PathBuf)>>,
+        pub item_hosts: RefCell<HashMap<String, HashSet<String>>>,
+        /// Files a repair downloads again by release, the other files are kept
+        pub repair_files: RefCell<HashMap<String, HashSet<String>>>,
+        pub space_reservations: RefCell<HashMap<String, super::space::Reservation>>,
+        pub failed_files: RefCell<
+            HashMap<
+                String,
+                Vec<(
+                    String,
+                    crate::download::pipeline::DownloadedFile,
+                    crate::download::error::DownloadError,
+                )>,
+            >,
+        >,
+        pub history_started:
+            RefCell<HashMap<String, (super::queue::QueuedDownload, chrono::DateTime<chrono::Utc>)>>,
+        pub launcher_state: RefCell<Option<(f64, usize)>>,
+        pub copy_conflicts: RefCell<VecDeque<(String, String, PathBuf, PathBuf)>>,
+        /// Choice applied to every conflict of a download, kept until the release is downloaded again
+        pub conflict_choices: RefCell<HashMap<String, bool>>,
+        pub conflict_dialog: RefCell<Option<gtk4::MessageDialog>>,
+        pub schedule_active: RefCell<Option<bool>>,
+        pub scheduled_pauses: RefCell<HashSet<String>>,
         #[template_child]
         pub downloads: TemplateChild<gtk4::ListBox>,
+        #[template_child]
+        pub schedule_label: TemplateChild<gtk4::Label>,
         has_children: RefCell<bool>,
     }
 
@@ -140,10 +214,35 @@ pub mod imp {
                 thread_senders: RefCell::new(HashMap::new()),
                 chunk_urls: RefCell::new(HashMap::new()),
                 docker_digests: RefCell::new(HashMap::new()),
+                restored_downloads: RefCell::new(HashMap::new()),
+                stored_chunks: RefCell::new(HashMap::new()),
+                stored_files: RefCell::new(HashMap::new()),
+                file_retries: RefCell::new(HashMap::new()),
+                delta_sources: RefCell::new(HashMap::new()),
+                local_chunks: RefCell::new(HashMap::new()),
+                queued_chunks: RefCell::new(HashMap::new()),
+                running_chunks: RefCell::new(0),
+                host_stats: RefCell::new(HashMap::new()),
+                chunk_attempts: RefCell::new(HashMap::new()),
+                corrupted_chunks: RefCell::new(HashMap::new()),
+                item_hosts: RefCell::new(HashMap::new()),
+                repair_files: RefCell::new(HashMap::new()),
+                space_reservations: RefCell::new(HashMap::new()),
+                failed_files: RefCell::new(HashMap::new()),
+                history_started: RefCell::new(HashMap::new()),
+                launcher_state: RefCell::new(None),
+                copy_conflicts: RefCell::new(VecDeque::new()),
+                conflict_choices: RefCell::new(HashMap::new()),
+                conflict_dialog: RefCell::new(None),
+                schedule_active: RefCell::new(None),
+                scheduled_pauses: RefCell::new(HashSet::new()),
                 downloads: TemplateChild::default(),
+                schedule_label: TemplateChild::default(),
                 thumbnail_pool: ThreadPool::with_name("Thumbnail Pool".to_string(), 5),
                 image_pool: ThreadPool::with_name("Image Pool".to_string(), 5),
                 file_pool: ThreadPool::with_name("File Pool".to_string(), 1),
+                limiter: Arc::new(crate::download::limiter::RateLimiter::default()),
+                chunk_cache: Arc::new(crate::download::chunk_cache::ChunkCache::default()),
                 has_children: RefCell::new(false),
             }
         }
@@ -164,6 +263,7 @@ pub mod imp {
             let obj = self.obj();
             obj.setup_actions();
             obj.setup_messaging();
+            obj.setup_settings();
         }
 
         fn signals() -> &'static [gtk4::glib::subclass::Signal] {
@@ -229,6 +329,8 @@ impl EpicDownloadManager {
         }
 
         self_.window.set(window.clone()).unwrap();
+        self.clear_staged_conflicts();
+        self.restore_queue();
     }
 
     pub fn setup_actions(&self) {
@@ -248,6 +350,146 @@ impl EpicDownloadManager {
         self.insert_action_group("download_manager", Some(&self_.actions));
     }
 
+    pub fn setup_settings(&self) {
+        let self_ = self.imp();
+        self.apply_download_settings();
+        for key in [
+            "download-speed-limit",
+            "download-threads",
+            "file-extraction-threads",
+            "chunk-cache-size",
+            "download-schedule-enabled",
+            "download-schedule-start",
+            "download-schedule-end",
+            "download-daytime-speed-limit",
+        ] {
+            self_.settings.connect_changed(
+                Some(key),
+                clone!(@weak self as download_manager => move |_, _| {
+                    download_manager.apply_download_settings();
+                }),
+            );
+        }
+        glib::timeout_add_seconds_local(
+            30,
+            clone!(@weak self as download_manager => @default-return glib::Continue(false), move || {
+                download_manager.check_schedule();
+                glib::Continue(true)
+            }),
+        );
+    }
+
+    fn schedule(&self) -> Option<schedule::Schedule> {
+        let self_ = self.imp();
+        if !self_.settings.boolean("download-schedule-enabled") {
+            return None;
+        }
+        schedule::Schedule::parse(
+            &self_.settings.string("download-schedule-start"),
+            &self_.settings.string("download-schedule-end"),
+        )
+    }
+
+    /// Current speed limit in KiB/s taking the download window into account
+    fn current_speed_limit(&self) -> u64 {
+        let self_ = self.imp();
+        let key = match *self_.schedule_active.borrow() {
+            Some(false) => "download-daytime-speed-limit",
+            _ => "download-speed-limit",
+        };
+        u64::try_from(self_.settings.int(key)).unwrap_or_default()
+    }
+
+    /// Pause or resume downloads when crossing the download window boundaries
+    pub fn check_schedule(&self) {
+        let self_ = self.imp();
+        let Some(schedule) = self.schedule() else {
+            self_.schedule_active.replace(None);
+            self_.schedule_label.set_visible(false);
+            self.resume_scheduled();
+            self_.limiter.set_rate(self.current_speed_limit() * 1024);
+            return;
+        };
+        let active = schedule.contains(chrono::Local::now().time());
+        let changed = self_.schedule_active.replace(Some(active)) != Some(active);
+        let daytime =
+            u64::try_from(self_.settings.int("download-daytime-speed-limit")).unwrap_or_default();
+        if changed {
+            debug!("Download window {} active: {}", schedule.label(), active);
+            self_.limiter.set_rate(self.current_speed_limit() * 1024);
+        }
+        if active {
+            self.resume_scheduled();
+        } else if daytime == 0 {
+            self.pause_scheduled();
+        }
+        self_.schedule_label.set_visible(true);
+        self_.schedule_label.set_markup(&if active {
+            format!("<b>Download window</b> {} active", schedule.label())
+        } else if daytime == 0 {
+            format!(
+                "<b>Outside download window</b> {}, downloads are paused",
+                schedule.label()
+            )
+        } else {
+            format!(
+                "<b>Outside download window</b> {}, limited to {} KiB/s",
+                schedule.label(),
+                daytime
+            )
+        });
+    }
+
+    /// Pause all running downloads, remembering them so they can be resumed by the schedule
+    fn pause_scheduled(&self) {
+        let self_ = self.imp();
+        let items: Vec<(String, EpicDownloadItem)> = self_
+            .download_items
+            .borrow()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        for (id, item) in items {
+            if item.paused() || item.canceled() || item.failed() || !item.can_pause() {
+                continue;
+            }
+            debug!("Pausing {} outside of the download window", id);
+            item.pause();
+            self_.scheduled_pauses.borrow_mut().insert(id);
+        }
+    }
+
+    fn resume_scheduled(&self) {
+        let self_ = self.imp();
+        let paused: Vec<String> = self_.scheduled_pauses.borrow_mut().drain().collect();
+        for id in paused {
+            if let Some(item) = self.get_item(&id) {
+                if item.paused() && !item.canceled() {
+                    debug!("Resuming {} in the download window", id);
+                    item.pause();
+                }
+            }
+        }
+    }
+
+    fn apply_download_settings(&self) {
+        let self_ = self.imp();
+        self.check_schedule();
+        let limit = self.current_speed_limit();
+        self_.limiter.set_rate(limit * 1024);
+        let threads = usize::try_from(self_.settings.int("download-threads")).unwrap_or(5);
+        self_.download_pool.set_num_threads(threads.max(1));
+        let threads = usize::try_from(self_.settings.int("file-extraction-threads")).unwrap_or(1);
+        self_.file_pool.set_num_threads(threads.max(1));
+        let cache = usize::try_from(self_.settings.int("chunk-cache-size")).unwrap_or(256);
+        self_.chunk_cache.set_capacity(cache * 1024 * 1024);
+        debug!(
+            "Download limit set to {} KiB/s, {} download threads",
+            limit,
+            self_.download_pool.max_count()
+        );
+    }
+
     pub fn setup_messaging(&self) {
         let self_ = self.imp();
         let receiver = self_.receiver.borrow_mut().take().unwrap();
@@ -285,6 +527,9 @@ impl EpicDownloadManager {
             Msg::FinalizeFileDownload(file, file_details) => {
                 self.finalize_file_download(&file, file_details);
             }
+            Msg::FileHashFailed(file, file_details) => {
+                self.file_hash_failed(&file, file_details);
+            }
             Msg::FileAlreadyDownloaded(id, progress, fullname, filename) => {
                 self.file_already_extracted(id, progress, fullname, filename);
             }
@@ -309,14 +554,23 @@ impl EpicDownloadManager {
             Msg::DockerExtractionFinished(version) => {
                 self.docker_extraction_finished(&version);
             }
-            Msg::IOError(e) => {
-                if let Some(w) = self_.window.get() {
-                    w.add_notification(
-                        "iodownloaderror",
-                        &format!("Unable to download file: {e}"),
-                        gtk4::MessageType::Error,
-                    );
-                }
+            Msg::DownloadFailed(id, error) => {
+                self.download_failed(&id, &error);
+            }
+            Msg::ChunkFailed(url, path, guid, error) => {
+                self.chunk_failed(url, path, &guid, &error);
+            }
+            Msg::FileFailed(file, details, error) => {
+                self.file_failed(&file, details, error);
+            }
+            Msg::HookFailed(label, command, reason) => {
+                self.hook_failed(&label, &command, &reason);
+            }
+            Msg::CopyConflict(asset, project, target, staged) => {
+                self.copy_conflict(&asset, &project, target, staged);
+            }
+            Msg::FileCopied(id, project, path, backup, replaced) => {
+                self.file_copied(&id, &project, &path, backup.as_deref(), replaced);
             }
             Msg::PauseChunk(url, path, guid) => {
                 self.pause_asset_chunk(url, path, guid);
@@ -324,6 +578,12 @@ impl EpicDownloadManager {
             Msg::CancelChunk(_url, path, guid) => {
                 self.remove_chunk(path, guid);
             }
+            Msg::ChunkSlotFreed => {
+                self.chunk_slot_freed();
+            }
+            Msg::ChunkHostReport(link, guid, size, duration) => {
+                self.host_succeeded(&link, &guid, size, duration);
+            }
             Msg::DockerCanceled(version, digest) => {
                 self.cancel_docker_digest(&version, digest);
             }
@@ -337,6 +597,7 @@ impl EpicDownloadManager {
             Msg::EpicDownloadProgress(ver, size) => {
                 self.epic_download_progress(&ver, size);
             }
+            Msg::EpicDownloadRestarted(ver) => self.epic_download_restarted(&ver),
             Msg::EpicFileFinished(version) => self.epic_file_finished(&version),
             Msg::EpicFileExtracted(version) => {
                 self.epic_file_extracted(&version);
@@ -355,6 +616,13 @@ impl EpicDownloadManager {
 
     fn finish(&self, item: &download_item::EpicDownloadItem) {
         let self_: &imp::EpicDownloadManager = self.imp();
+        self.history_record(item, DownloadResult::Finished);
+        self.notify_finished(item);
+        self.run_post_download_hooks(item);
+        // The conflict choice stays, files of the download may still be copied on the file pool
+        if let Some(key) = item.key() {
+            self.release_space(&key);
+        }
         if let Some(mut child) = self_.downloads.first_child() {
             loop {
                 let row = child.clone().downcast::<gtk4::ListBoxRow>().unwrap();
@@ -389,10 +657,14 @@ impl EpicDownloadManager {
             .map(std::string::ToString::to_string)
     }
 
-    fn finalize_file_download(&self, file: &str, file_details: asset::DownloadedFile) {
+    fn finalize_file_download(&self, file: &str, file_details: pipeline::DownloadedFile) {
         let self_ = self.imp();
         info!("File finished: {}", file);
         self_.downloaded_files.borrow_mut().remove(file);
+        self_.file_retries.borrow_mut().remove(file);
+        self.queue_file_finished(&file_details.asset, &file_details.name);
+        let size: u128 = file_details.chunks.iter().map(|c| c.size).sum();
+        self.space_written(&file_details.asset, u64::try_from(size).unwrap_or(u64::MAX));
         let vaults = self_.settings.strv("unreal-vault-directories");
         let temp_dir = std::path::PathBuf::from(vaults.first().map_or_else(
             || {
@@ -412,23 +684,37 @@ impl EpicDownloadManager {
                     p.push("temp");
 
                     p.push(format!("{}.chunk", chunk.guid));
-                    debug!("Removing chunk {}", p.as_path().to_str().unwrap());
+                    debug!("Removing chunk {}", p.display());
                     if let Err(e) = std::fs::remove_file(p.clone()) {
                         error!("Unable to remove chunk file: {}", e);
                     };
-                    if let Err(e) = std::fs::remove_dir(p.parent().unwrap()) {
-                        debug!("Unable to remove the temp directory(yet): {}", e);
-                    };
-                    if let Err(e) = std::fs::remove_dir(p.parent().unwrap().parent().unwrap()) {
-                        debug!("Unable to remove the temp directory(yet): {}", e);
-                    };
+                    if let Some(temp) = p.parent() {
+                        if let Err(e) = std::fs::remove_dir(temp) {
+                            debug!("Unable to remove the temp directory(yet): {}", e);
+                        };
+                        if let Some(release) = temp.parent() {
+                            if let Err(e) = std::fs::remove_dir(release) {
+                                debug!("Unable to remove the temp directory(yet): {}", e);
+                            };
+                        }
+                    }
                 }
             }
         }
+        // Corrupted chunks wait for the files still reading their old data
+        let corrupted: Vec<String> = file_details
+            .chunks
+            .iter()
+            .map(|c| c.guid.clone())
+            .filter(|g| self_.corrupted_chunks.borrow().contains_key(g))
+            .collect();
+        for guid in corrupted {
+            self.redownload_corrupted_chunk(&guid);
+        }
         self_
             .sender
             .send(Msg::FileExtracted(file_details.asset))
-            .unwrap();
+            .ok();
     }
 
     pub fn progress(&self) -> f32 {
@@ -479,7 +765,7 @@ impl EpicDownloadManager {
                     }
                     sender
                         .send(crate::ui::messages::Msg::ProcessAssetInfo(asset))
-                        .unwrap();
+                        .ok();
                 }
             };
         });
@@ -546,7 +832,7 @@ impl EpicDownloadManager {
                                 asset, img,
                             ),
                         )
-                        .unwrap();
+                        .ok();
                 }
             };
         });
@@ -0,0 +1,253 @@
+use crate::ui::widgets::download_manager::download_item::EpicDownloadItem;
+use crate::ui::widgets::download_manager::error::Failures;
+use gtk4::subclass::prelude::*;
+use gtk4::{gio, glib, prelude::*};
+use log::debug;
+use std::collections::HashMap;
+
+/// Object path the launcher entry signals are emitted from
+const LAUNCHER_PATH: &str = "/io/github/achetagames/epic_asset_manager/LauncherEntry";
+const LAUNCHER_INTERFACE: &str = "com.canonical.Unity.LauncherEntry";
+
+pub trait Notifications {
+    /// Let the desktop know a download finished
+    fn notify_finished(&self, _item: &EpicDownloadItem) {
+        unimplemented!()
+    }
+
+    /// Let the desktop know a download failed
+    fn notify_failed(&self, _item: &EpicDownloadItem, _error: &str) {
+        unimplemented!()
+    }
+
+    /// Retry a download from the notification action
+    fn retry_from_notification(&self, _id: &str) {
+        unimplemented!()
+    }
+
+    /// Publish the overall progress and the number of downloads to docks
+    fn update_launcher_entry(&self) {
+        unimplemented!()
+    }
+}
+
+impl Notifications for crate::ui::widgets::download_manager::EpicDownloadManager {
+    fn notify_finished(&self, item: &EpicDownloadItem) {
+        let Some(key) = item.key() else { return };
+        let notification = gio::Notification::new("Download finished");
+        notification.set_body(Some(&format!(
+            "{} is ready",
+            item.property::<Option<String>>("label").unwrap_or_default()
+        )));
+        if let Some(path) = item.path() {
+            notification.add_button_with_target_value(
+                "Open folder",
+                "app.open-download-folder",
+                Some(&path.to_variant()),
+            );
+        }
+        self.send_notification(&key, &notification);
+    }
+
+    fn notify_failed(&self, item: &EpicDownloadItem, error: &str) {
+        let Some(key) = item.key() else { return };
+        let notification = gio::Notification::new("Download failed");
+        notification.set_body(Some(&format!(
+            "{}: {}",
+            item.property::<Option<String>>("label").unwrap_or_default(),
+            error
+        )));
+        notification.set_priority(gio::NotificationPriority::High);
+        notification.add_button_with_target_value(
+            "Retry",
+            "app.retry-download",
+            Some(&key.to_variant()),
+        );
+        self.send_notification(&key, &notification);
+    }
+
+    fn retry_from_notification(&self, id: &str) {
+        let Some(item) = self.get_item(id) else { return };
+        if item.failed() {
+            self.retry_download(&item);
+        }
+    }
+
+    fn update_launcher_entry(&self) {
+        let self_ = self.imp();
+        let count = self_.download_items.borrow().len();
+        // Only signal changes of at least a percent
+        let progress = (f64::from(self.progress()) * 100.0).round() / 100.0;
+        let state = (progress, count);
+        if self_.launcher_state.borrow().map_or(false, |s| s == state) {
+            return;
+        }
+        self_.launcher_state.replace(Some(state));
+        let Some(connection) = gio::Application::default().and_then(|a| a.dbus_connection()) else {
+            return;
+        };
+        let uri = format!("application://{}.desktop", crate::config::APP_ID);
+        if let Err(e) = emit_launcher_update(&connection, &uri, progress, count) {
+            debug!("Unable to update the launcher entry: {}", e);
+        }
+    }
+}
+
+/// Broadcast the launcher entry `Update` signal of the desktop file `uri`
+fn emit_launcher_update(
+    connection: &gio::DBusConnection,
+    uri: &str,
+    progress: f64,
+    count: usize,
+) -> Result<(), glib::Error> {
+    let mut properties: HashMap<String, glib::Variant> = HashMap::new();
+    properties.insert("progress".to_string(), progress.to_variant());
+    properties.insert("progress-visible".to_string(), (count > 0).to_variant());
+    properties.insert(
+        "count".to_string(),
+        i64::try_from(count).unwrap_or_default().to_variant(),
+    );
+    properties.insert("count-visible".to_string(), (count > 0).to_variant());
+    connection.emit_signal(
+        None,
+        LAUNCHER_PATH,
+        LAUNCHER_INTERFACE,
+        "Update",
+        Some(&(uri.to_string(), properties).to_variant()),
+    )
+}
+
+impl crate::ui::widgets::download_manager::EpicDownloadManager {
+    fn send_notification(&self, key: &str, notification: &gio::Notification) {
+        let self_ = self.imp();
+        // The in window progress is enough when the user is looking at it
+        if self_.window.get().map_or(false, |w| w.is_active()) {
+            return;
+        }
+        if let Some(app) = gio::Application::default() {
+            app.send_notification(Some(&format!("download-{key}")), notification);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    /// Private session bus, stopped when the test ends
+    struct Bus(Child);
+
+    impl Drop for Bus {
+        fn drop(&mut self) {
+            self.0.kill().ok();
+            self.0.wait().ok();
+        }
+    }
+
+    fn start_bus() -> Option<(Bus, String)> {
+        let mut bus = Bus(Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .ok()?);
+        let mut address = String::new();
+        BufReader::new(bus.0.stdout.take()?)
+            .read_line(&mut address)
+            .ok()?;
+        Some((bus, address.trim().to_string()))
+    }
+
+    fn connect(address: &str) -> gio::DBusConnection {
+        gio::DBusConnection::for_address_sync(
+            address,
+            gio::DBusConnectionFlags::AUTHENTICATION_CLIENT
+                | gio::DBusConnectionFlags::MESSAGE_BUS_CONNECTION,
+            None,
+            None::<&gio::Cancellable>,
+        )
+        .unwrap()
+    }
+
+    /// Emit an update on a private bus and return the parameters another connection received
+    fn receive_update(
+        progress: f64,
+        count: usize,
+    ) -> Option<(String, HashMap<String, glib::Variant>)> {
+        // Systems without dbus-daemon have no bus to test on, the callers then skip their checks
+        let Some((_bus, address)) = start_bus() else {
+            return None;
+        };
+        let context = glib::MainContext::new();
+        context
+            .with_thread_default(|| {
+                let listener = connect(&address);
+                let received: Arc<Mutex<Option<glib::Variant>>> = Arc::default();
+                let r = received.clone();
+                listener.signal_subscribe(
+                    None,
+                    Some(LAUNCHER_INTERFACE),
+                    Some("Update"),
+                    Some(LAUNCHER_PATH),
+                    None,
+                    gio::DBusSignalFlags::NONE,
+                    move |_, _, _, _, _, parameters| {
+                        *r.lock().unwrap() = Some(parameters.clone());
+                    },
+                );
+                // The reply comes after the bus added the match rule of the subscription
+                listener
+                    .call_sync(
+                        Some("org.freedesktop.DBus"),
+                        "/org/freedesktop/DBus",
+                        "org.freedesktop.DBus",
+                        "GetId",
+                        None,
+                        None,
+                        gio::DBusCallFlags::NONE,
+                        -1,
+                        None::<&gio::Cancellable>,
+                    )
+                    .unwrap();
+
+                let sender = connect(&address);
+                emit_launcher_update(&sender, "application://test.desktop", progress, count)
+                    .unwrap();
+                sender.flush_sync(None::<&gio::Cancellable>).unwrap();
+                let deadline = Instant::now() + Duration::from_secs(5);
+                while received.lock().unwrap().is_none() && Instant::now() < deadline {
+                    if !context.iteration(false) {
+                        std::thread::sleep(Duration::from_millis(10));
+                    }
+                }
+                let parameters = received
+                    .lock()
+                    .unwrap()
+                    .clone()
+                    .expect("no Update signal received");
+                parameters.get::<(String, HashMap<String, glib::Variant>)>()
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn update_signal_carries_progress_and_count() {
+        let Some((uri, properties)) = receive_update(0.25, 2) else { return };
+        assert_eq!(uri, "application://test.desktop");
+        assert_eq!(properties["progress"].get::<f64>(), Some(0.25));
+        assert_eq!(properties["progress-visible"].get::<bool>(), Some(true));
+        assert_eq!(properties["count"].get::<i64>(), Some(2));
+        assert_eq!(properties["count-visible"].get::<bool>(), Some(true));
+    }
+
+    #[test]
+    fn update_signal_hides_progress_without_downloads() {
+        let Some((_, properties)) = receive_update(0.0, 0) else { return };
+        assert_eq!(properties["progress-visible"].get::<bool>(), Some(false));
+        assert_eq!(properties["count"].get::<i64>(), Some(0));
+        assert_eq!(properties["count-visible"].get::<bool>(), Some(false));
+    }
+}
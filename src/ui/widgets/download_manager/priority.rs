@@ -0,0 +1,230 @@
+use crate::ui::widgets::download_manager::asset::Asset;
+use crate::ui::widgets::download_manager::download_item::EpicDownloadItem;
+use gtk4::prelude::*;
+use gtk4::subclass::prelude::*;
+use log::debug;
+use reqwest::Url;
+use std::path::PathBuf;
+
+/// Items higher in the download list get their chunks downloaded first
+pub trait Priority {
+    /// Keys of the download items in the order they are shown
+    fn item_order(&self) -> Vec<String> {
+        unimplemented!()
+    }
+
+    fn move_item(&self, _item: &EpicDownloadItem, _position: i32) {
+        unimplemented!()
+    }
+
+    /// Move the item with the key `id` in front of `target`
+    fn move_item_before(&self, _id: &str, _target: &EpicDownloadItem) {
+        unimplemented!()
+    }
+
+    fn move_item_to_top(&self, _item: &EpicDownloadItem) {
+        unimplemented!()
+    }
+
+    fn move_item_up(&self, _item: &EpicDownloadItem) {
+        unimplemented!()
+    }
+
+    fn move_item_down(&self, _item: &EpicDownloadItem) {
+        unimplemented!()
+    }
+
+    /// Pause every running download except `item`
+    fn pause_others(&self, _item: &EpicDownloadItem) {
+        unimplemented!()
+    }
+
+    /// Key of the download item a chunk belongs to
+    fn chunk_owner(&self, _g: &str) -> Option<String> {
+        unimplemented!()
+    }
+
+    /// Put a chunk in the queue of the item it belongs to
+    fn queue_chunk(&self, _link: Url, _p: PathBuf, _g: String) {
+        unimplemented!()
+    }
+
+    /// Start queued chunks by item order while there are free download slots
+    fn schedule_chunks(&self) {
+        unimplemented!()
+    }
+
+    fn chunk_slot_freed(&self) {
+        unimplemented!()
+    }
+
+    /// Forget chunks of an item that were not started yet
+    fn drop_queued_chunks(&self, _id: &str) {
+        unimplemented!()
+    }
+}
+
+impl Priority for crate::ui::widgets::download_manager::EpicDownloadManager {
+    fn item_order(&self) -> Vec<String> {
+        let self_ = self.imp();
+        let mut result: Vec<String> = Vec::new();
+        let mut child = self_.downloads.first_child();
+        while let Some(row) = child {
+            if let Some(item) = row
+                .first_child()
+                .and_then(|i| i.downcast::<EpicDownloadItem>().ok())
+            {
+                if let Some(key) = item.key() {
+                    result.push(key);
+                }
+            }
+            child = row.next_sibling();
+        }
+        result
+    }
+
+    fn move_item(&self, item: &EpicDownloadItem, position: i32) {
+        let self_ = self.imp();
+        let Some(row) = item
+            .parent()
+            .and_then(|r| r.downcast::<gtk4::ListBoxRow>().ok())
+        else {
+            return;
+        };
+        let last = self_.downloads.observe_children().n_items() as i32 - 1;
+        let position = position.clamp(0, last.max(0));
+        if row.index() == position {
+            return;
+        }
+        self_.downloads.remove(&row);
+        self_.downloads.insert(&row, position);
+        debug!("Moved {:?} to position {}", item.key(), position);
+        self.schedule_chunks();
+    }
+
+    fn move_item_before(&self, id: &str, target: &EpicDownloadItem) {
+        let Some(item) = self.get_item(id) else { return };
+        if item.eq(target) {
+            return;
+        }
+        let Some(target_row) = target
+            .parent()
+            .and_then(|r| r.downcast::<gtk4::ListBoxRow>().ok())
+        else {
+            return;
+        };
+        let Some(row) = item
+            .parent()
+            .and_then(|r| r.downcast::<gtk4::ListBoxRow>().ok())
+        else {
+            return;
+        };
+        let position = target_row.index();
+        // Removing the item first shifts the target up by one
+        if row.index() < position {
+            self.move_item(&item, position - 1);
+        } else {
+            self.move_item(&item, position);
+        }
+    }
+
+    fn move_item_to_top(&self, item: &EpicDownloadItem) {
+        self.move_item(item, 0);
+    }
+
+    fn move_item_up(&self, item: &EpicDownloadItem) {
+        if let Some(row) = item
+            .parent()
+            .and_then(|r| r.downcast::<gtk4::ListBoxRow>().ok())
+        {
+            self.move_item(item, row.index() - 1);
+        }
+    }
+
+    fn move_item_down(&self, item: &EpicDownloadItem) {
+        if let Some(row) = item
+            .parent()
+            .and_then(|r| r.downcast::<gtk4::ListBoxRow>().ok())
+        {
+            self.move_item(item, row.index() + 1);
+        }
+    }
+
+    fn pause_others(&self, item: &EpicDownloadItem) {
+        let self_ = self.imp();
+        let items: Vec<EpicDownloadItem> =
+            self_.download_items.borrow().values().cloned().collect();
+        for other in items {
+            if other.eq(item) || other.paused() || other.canceled() || other.failed() {
+                continue;
+            }
+            if other.can_pause() {
+                other.pause();
+            }
+        }
+    }
+
+    fn chunk_owner(&self, g: &str) -> Option<String> {
+        let self_ = self.imp();
+        let chunks = self_.downloaded_chunks.borrow();
+        let files = self_.downloaded_files.borrow();
+        chunks
+            .get(g)
+            .and_then(|f| f.iter().find_map(|file| files.get(file)))
+            .map(|f| f.asset.clone())
+    }
+
+    fn queue_chunk(&self, link: Url, p: PathBuf, g: String) {
+        let self_ = self.imp();
+        let owner = self.chunk_owner(&g).unwrap_or_default();
+        self_
+            .queued_chunks
+            .borrow_mut()
+            .entry(owner)
+            .or_default()
+            .push_back((link, p, g));
+        self.schedule_chunks();
+    }
+
+    fn schedule_chunks(&self) {
+        let self_ = self.imp();
+        let max = self_.download_pool.max_count();
+        let mut order = self.item_order();
+        for id in self_.queued_chunks.borrow().keys() {
+            if !order.contains(id) {
+                order.push(id.clone());
+            }
+        }
+        while *self_.running_chunks.borrow() < max {
+            let next = order.iter().find_map(|id| {
+                if let Some(item) = self.get_item(id) {
+                    if item.paused() || item.canceled() || item.failed() {
+                        return None;
+                    }
+                }
+                self_
+                    .queued_chunks
+                    .borrow_mut()
+                    .get_mut(id)
+                    .and_then(std::collections::VecDeque::pop_front)
+            });
+            let Some((link, p, g)) = next else { break };
+            *self_.running_chunks.borrow_mut() += 1;
+            self.start_chunk_download(link, p, g);
+        }
+    }
+
+    fn chunk_slot_freed(&self) {
+        let self_ = self.imp();
+        {
+            let mut running = self_.running_chunks.borrow_mut();
+            *running = running.saturating_sub(1);
+        }
+        self.schedule_chunks();
+    }
+
+    fn drop_queued_chunks(&self, id: &str) {
+        let self_ = self.imp();
+        self_.queued_chunks.borrow_mut().remove(id);
+    }
+}
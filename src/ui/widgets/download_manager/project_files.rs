@@ -0,0 +1,209 @@
+use crate::schema::project_files;
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use gtk4::prelude::*;
+use log::{debug, error, warn};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Asset release copied into a project
+#[derive(Debug, Clone)]
+pub struct AddedAsset {
+    pub release: String,
+    pub label: String,
+    pub files: usize,
+    pub backups: usize,
+    pub added_at: String,
+}
+
+/// List the assets copied into the project directory, newest first
+pub fn added_assets(project: &str) -> Vec<AddedAsset> {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else {
+        return Vec::new();
+    };
+    let rows: Vec<(String, String, String, String)> = match project_files::table
+        .filter(project_files::project.eq(project))
+        .select((
+            project_files::release,
+            project_files::label,
+            project_files::backup,
+            project_files::added_at,
+        ))
+        .load(&mut conn)
+    {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Unable to load files added to {}: {}", project, e);
+            return Vec::new();
+        }
+    };
+    let mut assets: BTreeMap<String, AddedAsset> = BTreeMap::new();
+    for (release, label, backup, added_at) in rows {
+        let asset = assets.entry(release.clone()).or_insert_with(|| AddedAsset {
+            release,
+            label,
+            files: 0,
+            backups: 0,
+            added_at: added_at.clone(),
+        });
+        asset.files += 1;
+        if !backup.is_empty() {
+            asset.backups += 1;
+        }
+        if added_at > asset.added_at {
+            asset.added_at = added_at;
+        }
+    }
+    let mut result: Vec<AddedAsset> = assets.into_values().collect();
+    result.sort_by(|a, b| b.added_at.cmp(&a.added_at));
+    result
+}
+
+/// Remember a file written into a project, with the backup of the file it replaced.
+/// `replaced` tells if there was a file at the path before, with or without a backup.
+pub fn record_file(
+    project: &str,
+    path: &Path,
+    release: &str,
+    label: &str,
+    backup: Option<&Path>,
+    replaced: bool,
+) {
+    let db = crate::models::database::connection();
+    let Ok(mut conn) = db.get() else { return };
+    let path = path.to_string_lossy().to_string();
+    // When an asset is copied over a recorded file the backup holds a downloaded file, keep the original one
+    let previous: Option<(String, bool)> = project_files::table
+        .filter(project_files::project.eq(project))
+        .filter(project_files::path.eq(&path))
+        .select((project_files::backup, project_files::replaced))
+        .first(&mut conn)
+        .ok();
+    let (backup, replaced) = match previous {
+        Some(p) => {
+            if let Some(b) = backup {
+                if let Err(e) = std::fs::remove_file(b) {
+                    warn!("Unable to remove extra backup {:?}: {}", b, e);
+                }
+            }
+            p
+        }
+        None => (
+            backup
+                .map(|b| b.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            replaced,
+        ),
+    };
+    if let Err(e) = diesel::replace_into(project_files::table)
+        .values((
+            project_files::project.eq(project),
+            project_files::path.eq(&path),
+            project_files::release.eq(release),
+            project_files::label.eq(label),
+            project_files::backup.eq(backup),
+            project_files::replaced.eq(replaced),
+            project_files::added_at.eq(chrono::Utc::now().to_rfc3339()),
+        ))
+        .execute(&mut conn)
+    {
+        error!("Unable to record {} added to {}: {}", path, project, e);
+    }
+}
+
+/// Delete the files an asset added to the project and restore the backed up originals.
+/// Files that overwrote an original without a backup are kept.
+pub fn remove_from_project(project: &str, release: &str) -> Result<usize, String> {
+    let db = crate::models::database::connection();
+    let mut conn = db.get().map_err(|e| e.to_string())?;
+    let files: Vec<(String, String, bool)> = project_files::table
+        .filter(project_files::project.eq(project))
+        .filter(project_files::release.eq(release))
+        .select((
+            project_files::path,
+            project_files::backup,
+            project_files::replaced,
+        ))
+        .load(&mut conn)
+        .map_err(|e| e.to_string())?;
+    let mut failed: Vec<String> = Vec::new();
+    for (path, backup, replaced) in &files {
+        let file = PathBuf::from(path);
+        let result = if !backup.is_empty() && Path::new(backup).exists() {
+            std::fs::rename(backup, &file)
+        } else if *replaced {
+            warn!("Keeping {}, the file it replaced has no backup", path);
+            Ok(())
+        } else if file.exists() {
+            std::fs::remove_file(&file)
+        } else {
+            Ok(())
+        };
+        match result {
+            Ok(_) => {
+                debug!("Removed {} from {}", path, project);
+                remove_empty_parents(&file, Path::new(project));
+                if let Err(e) = diesel::delete(
+                    project_files::table
+                        .filter(project_files::project.eq(project))
+                        .filter(project_files::path.eq(path)),
+                )
+                .execute(&mut conn)
+                {
+                    error!("Unable to forget {}: {}", path, e);
+                }
+            }
+            Err(e) => {
+                error!("Unable to remove {}: {}", path, e);
+                failed.push(path.clone());
+            }
+        }
+    }
+    if failed.is_empty() {
+        Ok(files.len())
+    } else {
+        Err(format!("Unable to remove {}", failed.join(", ")))
+    }
+}
+
+/// Remove directories left empty by removed files, up to the project directory
+fn remove_empty_parents(file: &Path, project: &Path) {
+    let mut dir = file.parent();
+    while let Some(d) = dir {
+        if d == project || !d.starts_with(project) || std::fs::remove_dir(d).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+}
+
+pub trait ProjectFiles {
+    /// A downloaded file was copied into a target directory
+    fn file_copied(
+        &self,
+        _id: &str,
+        _project: &str,
+        _path: &Path,
+        _backup: Option<&Path>,
+        _replaced: bool,
+    ) {
+        unimplemented!()
+    }
+}
+
+impl ProjectFiles for crate::ui::widgets::download_manager::EpicDownloadManager {
+    fn file_copied(
+        &self,
+        id: &str,
+        project: &str,
+        path: &Path,
+        backup: Option<&Path>,
+        replaced: bool,
+    ) {
+        let label = self
+            .get_item(id)
+            .and_then(|item| item.property::<Option<String>>("label"))
+            .unwrap_or_default();
+        record_file(project, path, id, &label, backup, replaced);
+    }
+}
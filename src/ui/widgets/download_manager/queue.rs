@@ -0,0 +1,322 @@
+use crate::schema::{download_queue, download_queue_chunk, download_queue_file};
+use crate::ui::widgets::download_manager::asset::Asset;
+use crate::ui::widgets::download_manager::docker::Docker;
+use crate::ui::widgets::download_manager::download_item::ItemType;
+use crate::ui::widgets::download_manager::epic_file::EpicFile;
+use crate::ui::widgets::download_manager::history::History;
+use crate::ui::widgets::download_manager::PostDownloadAction;
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use gtk4::subclass::prelude::*;
+use log::{debug, error};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Download saved in the database so it can be resumed after the application restarts
+#[derive(Debug, Clone)]
+pub struct QueuedDownload {
+    pub id: String,
+    pub item_type: ItemType,
+    pub label: String,
+    pub target: Option<String>,
+    pub asset: Option<egs_api::api::types::asset_info::AssetInfo>,
+    pub actions: Vec<PostDownloadAction>,
+    pub total_size: u128,
+    pub total_files: u64,
+    pub finished_files: u64,
+}
+
+pub trait Queue {
+    /// Store a new download in the queue
+    fn queue_add(
+        &self,
+        _id: &str,
+        _item_type: ItemType,
+        _label: &str,
+        _target: &Option<String>,
+        _asset: Option<&egs_api::api::types::asset_info::AssetInfo>,
+        _actions: &[PostDownloadAction],
+    ) {
+        unimplemented!()
+    }
+
+    /// Update the download size and file count once they are known
+    fn queue_set_totals(&self, _id: &str, _size: u128, _files: u64) {
+        unimplemented!()
+    }
+
+    fn queue_chunk_finished(&self, _id: &str, _guid: &str) {
+        unimplemented!()
+    }
+
+    fn queue_file_finished(&self, _id: &str, _name: &str) {
+        unimplemented!()
+    }
+
+    /// Remove the download and all its progress from the queue
+    fn queue_remove(&self, _id: &str) {
+        unimplemented!()
+    }
+
+    /// Load downloads saved by a previous run and show them as paused
+    fn restore_queue(&self) {
+        unimplemented!()
+    }
+
+    /// Take the restored download information so it can be started
+    fn take_restored(&self, _id: &str) -> Option<QueuedDownload> {
+        unimplemented!()
+    }
+
+    /// Returns the size of a chunk already downloaded by a previous run if it is still on disk
+    fn stored_chunk_size(&self, _id: &str, _guid: &str, _path: &Path) -> Option<u64> {
+        unimplemented!()
+    }
+
+    /// Returns if a file was finished by a previous run and still has its size on disk
+    fn stored_file_finished(&self, _id: &str, _name: &str, _path: &Path, _size: u128) -> bool {
+        unimplemented!()
+    }
+}
+
+impl Queue for crate::ui::widgets::download_manager::EpicDownloadManager {
+    fn queue_add(
+        &self,
+        id: &str,
+        item_type: ItemType,
+        label: &str,
+        target: &Option<String>,
+        asset: Option<&egs_api::api::types::asset_info::AssetInfo>,
+        actions: &[PostDownloadAction],
+    ) {
+        let db = crate::models::database::connection();
+        if let Ok(mut conn) = db.get() {
+            let asset_info = asset
+                .and_then(|a| serde_json::to_string(a).ok())
+                .unwrap_or_default();
+            let actions = serde_json::to_string(actions).unwrap_or_else(|_| "[]".to_string());
+            if let Err(e) = diesel::replace_into(download_queue::table)
+                .values((
+                    download_queue::id.eq(id),
+                    download_queue::item_type.eq(item_type_name(item_type)),
+                    download_queue::label.eq(label),
+                    download_queue::target.eq(target.clone().unwrap_or_default()),
+                    download_queue::asset_info.eq(asset_info),
+                    download_queue::actions.eq(actions),
+                    download_queue::added_at.eq(chrono::Utc::now().to_rfc3339()),
+                ))
+                .execute(&mut conn)
+            {
+                error!("Unable to save download {} to the queue: {}", id, e);
+            }
+        }
+        self.history_start(QueuedDownload {
+            id: id.to_string(),
+            item_type,
+            label: label.to_string(),
+            target: target.clone(),
+            asset: asset.cloned(),
+            actions: actions.to_vec(),
+            total_size: 0,
+            total_files: 0,
+            finished_files: 0,
+        });
+    }
+
+    fn queue_set_totals(&self, id: &str, size: u128, files: u64) {
+        let db = crate::models::database::connection();
+        if let Ok(mut conn) = db.get() {
+            if let Err(e) = diesel::update(download_queue::table.filter(download_queue::id.eq(id)))
+                .set((
+                    download_queue::total_size.eq(i64::try_from(size).unwrap_or(i64::MAX)),
+                    download_queue::total_files.eq(i64::try_from(files).unwrap_or(i64::MAX)),
+                ))
+                .execute(&mut conn)
+            {
+                error!("Unable to update download {} in the queue: {}", id, e);
+            }
+        }
+    }
+
+    fn queue_chunk_finished(&self, id: &str, guid: &str) {
+        let db = crate::models::database::connection();
+        if let Ok(mut conn) = db.get() {
+            if let Err(e) = diesel::insert_or_ignore_into(download_queue_chunk::table)
+                .values((
+                    download_queue_chunk::download.eq(id),
+                    download_queue_chunk::guid.eq(guid),
+                ))
+                .execute(&mut conn)
+            {
+                error!("Unable to save finished chunk {}: {}", guid, e);
+            }
+        }
+    }
+
+    fn queue_file_finished(&self, id: &str, name: &str) {
+        let db = crate::models::database::connection();
+        if let Ok(mut conn) = db.get() {
+            if let Err(e) = diesel::insert_or_ignore_into(download_queue_file::table)
+                .values((
+                    download_queue_file::download.eq(id),
+                    download_queue_file::name.eq(name),
+                ))
+                .execute(&mut conn)
+            {
+                error!("Unable to save finished file {}: {}", name, e);
+            }
+        }
+    }
+
+    fn queue_remove(&self, id: &str) {
+        let self_ = self.imp();
+        self_.stored_chunks.borrow_mut().remove(id);
+        self_.stored_files.borrow_mut().remove(id);
+        let db = crate::models::database::connection();
+        if let Ok(mut conn) = db.get() {
+            if let Err(e) = diesel::delete(
+                download_queue_chunk::table.filter(download_queue_chunk::download.eq(id)),
+            )
+            .execute(&mut conn)
+            {
+                error!("Unable to remove chunks of {} from the queue: {}", id, e);
+            }
+            if let Err(e) = diesel::delete(
+                download_queue_file::table.filter(download_queue_file::download.eq(id)),
+            )
+            .execute(&mut conn)
+            {
+                error!("Unable to remove files of {} from the queue: {}", id, e);
+            }
+            if let Err(e) = diesel::delete(download_queue::table.filter(download_queue::id.eq(id)))
+                .execute(&mut conn)
+            {
+                error!("Unable to remove {} from the queue: {}", id, e);
+            }
+        }
+    }
+
+    fn restore_queue(&self) {
+        let self_ = self.imp();
+        let db = crate::models::database::connection();
+        let Ok(mut conn) = db.get() else { return };
+        let rows: Vec<(String, String, String, String, String, String, i64, i64)> =
+            match download_queue::table
+                .select((
+                    download_queue::id,
+                    download_queue::item_type,
+                    download_queue::label,
+                    download_queue::target,
+                    download_queue::asset_info,
+                    download_queue::actions,
+                    download_queue::total_size,
+                    download_queue::total_files,
+                ))
+                .order(download_queue::added_at)
+                .load(&mut conn)
+            {
+                Ok(r) => r,
+                Err(e) => {
+                    error!("Unable to load the download queue: {}", e);
+                    return;
+                }
+            };
+        for (id, item_type, label, target, asset_info, actions, total_size, total_files) in rows {
+            let item_type = item_type_from_name(&item_type);
+            let asset: Option<egs_api::api::types::asset_info::AssetInfo> =
+                serde_json::from_str(&asset_info).ok();
+            if item_type == ItemType::Unknown || (item_type == ItemType::Asset && asset.is_none()) {
+                debug!("Dropping unusable queued download {}", id);
+                self.queue_remove(&id);
+                continue;
+            }
+            let chunks: HashSet<String> = download_queue_chunk::table
+                .filter(download_queue_chunk::download.eq(&id))
+                .select(download_queue_chunk::guid)
+                .load::<String>(&mut conn)
+                .unwrap_or_default()
+                .into_iter()
+                .collect();
+            let files: HashSet<String> = download_queue_file::table
+                .filter(download_queue_file::download.eq(&id))
+                .select(download_queue_file::name)
+                .load::<String>(&mut conn)
+                .unwrap_or_default()
+                .into_iter()
+                .collect();
+            let finished_files = files.len();
+            self_.stored_chunks.borrow_mut().insert(id.clone(), chunks);
+            self_.stored_files.borrow_mut().insert(id.clone(), files);
+            let queued = QueuedDownload {
+                id: id.clone(),
+                item_type,
+                label,
+                target: if target.is_empty() {
+                    None
+                } else {
+                    Some(target)
+                },
+                asset,
+                actions: serde_json::from_str(&actions).unwrap_or_default(),
+                total_size: u128::try_from(total_size).unwrap_or_default(),
+                total_files: u64::try_from(total_files).unwrap_or_default(),
+                finished_files: finished_files as u64,
+            };
+            debug!("Restoring queued download {}", id);
+            self.history_start(queued.clone());
+            match item_type {
+                ItemType::Asset => self.restore_asset_download(&queued),
+                #[cfg(any(target_os = "linux", target_os = "macos"))]
+                ItemType::Docker => self.restore_docker_download(&queued),
+                ItemType::Epic => self.restore_epic_download(&queued),
+                _ => continue,
+            }
+            self_.restored_downloads.borrow_mut().insert(id, queued);
+        }
+    }
+
+    fn take_restored(&self, id: &str) -> Option<QueuedDownload> {
+        let self_ = self.imp();
+        self_.restored_downloads.borrow_mut().remove(id)
+    }
+
+    fn stored_chunk_size(&self, id: &str, guid: &str, path: &Path) -> Option<u64> {
+        let self_ = self.imp();
+        if !self_
+            .stored_chunks
+            .borrow()
+            .get(id)
+            .map_or(false, |chunks| chunks.contains(guid))
+        {
+            return None;
+        }
+        std::fs::metadata(path).ok().map(|m| m.len())
+    }
+
+    fn stored_file_finished(&self, id: &str, name: &str, path: &Path, size: u128) -> bool {
+        let self_ = self.imp();
+        self_
+            .stored_files
+            .borrow()
+            .get(id)
+            .map_or(false, |files| files.contains(name))
+            && std::fs::metadata(path).map_or(false, |m| u128::from(m.len()) == size)
+    }
+}
+
+pub fn item_type_name(item_type: ItemType) -> &'static str {
+    match item_type {
+        ItemType::Unknown => "unknown",
+        ItemType::Asset => "asset",
+        ItemType::Docker => "docker",
+        ItemType::Epic => "epic",
+    }
+}
+
+pub fn item_type_from_name(name: &str) -> ItemType {
+    match name {
+        "asset" => ItemType::Asset,
+        "docker" => ItemType::Docker,
+        "epic" => ItemType::Epic,
+        _ => ItemType::Unknown,
+    }
+}
@@ -0,0 +1,84 @@
+use chrono::NaiveTime;
+
+/// Time window in which downloads run at full speed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl Schedule {
+    /// Parse a window from two `HH:MM` strings
+    pub fn parse(start: &str, end: &str) -> Option<Self> {
+        Some(Self {
+            start: NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?,
+            end: NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?,
+        })
+    }
+
+    /// Check if the time is inside the window, windows can span midnight (22:00 - 07:00)
+    ///
+    /// A window starting when it ends (00:00 - 00:00) covers the whole day.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start == self.end {
+            true
+        } else if self.start < self.end {
+            time >= self.start && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    pub fn label(&self) -> String {
+        format!(
+            "{}\u{2013}{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(time, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn window_within_a_day() {
+        let schedule = Schedule::parse("09:00", "17:00").unwrap();
+        assert!(schedule.contains(at("09:00")));
+        assert!(schedule.contains(at("16:59")));
+        assert!(!schedule.contains(at("17:00")));
+        assert!(!schedule.contains(at("08:59")));
+    }
+
+    #[test]
+    fn window_crossing_midnight() {
+        let schedule = Schedule::parse("22:00", "07:00").unwrap();
+        assert!(schedule.contains(at("22:00")));
+        assert!(schedule.contains(at("23:59")));
+        assert!(schedule.contains(at("00:00")));
+        assert!(schedule.contains(at("06:59")));
+        assert!(!schedule.contains(at("07:00")));
+        assert!(!schedule.contains(at("12:00")));
+        assert!(!schedule.contains(at("21:59")));
+    }
+
+    #[test]
+    fn equal_start_and_end_is_all_day() {
+        let schedule = Schedule::parse("08:00", "08:00").unwrap();
+        assert!(schedule.contains(at("08:00")));
+        assert!(schedule.contains(at("07:59")));
+        assert!(schedule.contains(at("00:00")));
+        assert!(schedule.contains(at("23:59")));
+    }
+
+    #[test]
+    fn invalid_times_are_rejected() {
+        assert!(Schedule::parse("25:00", "07:00").is_none());
+        assert!(Schedule::parse("22:00", "").is_none());
+    }
+}
@@ -0,0 +1,144 @@
+use crate::download::pipeline::{self, SpaceNeed};
+use gtk4::subclass::prelude::*;
+use log::{debug, warn};
+
+/// Extracted engine archives are assumed to take this many times their download size
+pub const EXTRACTION_FACTOR: u64 = 2;
+
+/// Space reserved by a download
+#[derive(Debug, Clone)]
+pub struct Reservation {
+    pub needs: Vec<SpaceNeed>,
+    /// Size of the files the download still has to write
+    pub remaining: u64,
+}
+
+impl Reservation {
+    /// Files of `bytes` were written, every need shrinks by their share of the remaining data
+    pub fn written(&mut self, bytes: u64) {
+        if self.remaining == 0 {
+            return;
+        }
+        let bytes = bytes.min(self.remaining);
+        for need in &mut self.needs {
+            let share = u128::from(need.bytes) * u128::from(bytes) / u128::from(self.remaining);
+            need.bytes -= u64::try_from(share).unwrap_or(need.bytes);
+        }
+        self.remaining -= bytes;
+    }
+}
+
+pub trait DiskSpace {
+    /// Check that all needs fit on their filesystems and reserve the space for the download `id`.
+    /// `data` is the size of the files the download writes, 0 if it does not report written files.
+    /// Returns false and fails the item if there is not enough space even without other downloads.
+    fn reserve_space(&self, _id: &str, _needs: Vec<SpaceNeed>, _data: u64) -> bool {
+        unimplemented!()
+    }
+
+    /// Shrink the reservation of `id` after it wrote files of `bytes`
+    fn space_written(&self, _id: &str, _bytes: u64) {
+        unimplemented!()
+    }
+
+    /// Give back the space reserved by a finished or canceled download
+    fn release_space(&self, _id: &str) {
+        unimplemented!()
+    }
+}
+
+impl DiskSpace for crate::ui::widgets::download_manager::EpicDownloadManager {
+    fn reserve_space(&self, id: &str, needs: Vec<SpaceNeed>, data: u64) -> bool {
+        let self_ = self.imp();
+        self.release_space(id);
+        let checks = pipeline::check_space(
+            &needs,
+            self_
+                .space_reservations
+                .borrow()
+                .values()
+                .flat_map(|r| r.needs.iter()),
+        );
+        if let Some(full) = checks.iter().find(|c| !c.fits_alone()) {
+            let message = format!("Not enough disk space: {}", full.describe());
+            warn!("{} for {}", message, id);
+            if let Some(item) = self.get_item(id) {
+                item.fail(&message);
+            }
+            if let Some(w) = self_.window.get() {
+                w.add_notification(&format!("nospace{id}"), &message, gtk4::MessageType::Error);
+            }
+            return false;
+        }
+        if let Some(tight) = checks.iter().find(|c| !c.fits()) {
+            let message = format!(
+                "Disk space might run out while other downloads are running: {}",
+                tight.describe()
+            );
+            warn!("{} for {}", message, id);
+            if let Some(w) = self_.window.get() {
+                w.add_notification(
+                    &format!("lowspace{id}"),
+                    &message,
+                    gtk4::MessageType::Warning,
+                );
+            }
+        }
+        debug!("Reserved space for {}: {:?}", id, needs);
+        self_.space_reservations.borrow_mut().insert(
+            id.to_string(),
+            Reservation {
+                needs,
+                remaining: data,
+            },
+        );
+        true
+    }
+
+    fn space_written(&self, id: &str, bytes: u64) {
+        let self_ = self.imp();
+        if let Some(reservation) = self_.space_reservations.borrow_mut().get_mut(id) {
+            reservation.written(bytes);
+        }
+    }
+
+    fn release_space(&self, id: &str) {
+        let self_ = self.imp();
+        if self_.space_reservations.borrow_mut().remove(id).is_some() {
+            debug!("Released space reserved for {}", id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reservation_shrinks_with_written_files() {
+        let mut r = Reservation {
+            needs: vec![SpaceNeed::new("/temp", 50), SpaceNeed::new("/vault", 100)],
+            remaining: 100,
+        };
+        r.written(40);
+        assert_eq!(r.needs[0].bytes, 30);
+        assert_eq!(r.needs[1].bytes, 60);
+        assert_eq!(r.remaining, 60);
+        // Reports past the expected size only release what is left
+        r.written(100);
+        assert_eq!(r.needs[0].bytes, 0);
+        assert_eq!(r.needs[1].bytes, 0);
+        r.written(10);
+        assert_eq!(r.remaining, 0);
+    }
+
+    #[test]
+    fn reservation_without_written_data_is_kept() {
+        let mut r = Reservation {
+            needs: vec![SpaceNeed::new("/engines", 10)],
+            remaining: 0,
+        };
+        r.written(10);
+        assert_eq!(r.needs[0].bytes, 10);
+    }
+}
@@ -0,0 +1,224 @@
+use crate::ui::widgets::download_manager::download_item::ItemType;
+use crate::ui::widgets::download_manager::history::{DownloadResult, History, HistoryEntry};
+use crate::ui::widgets::logged_in::refresh::Refresh;
+use adw::prelude::*;
+use gtk4::glib::clone;
+use gtk4::subclass::prelude::*;
+use gtk4::{self, glib, CompositeTemplate};
+
+pub mod imp {
+    use super::*;
+    use once_cell::sync::OnceCell;
+    use std::cell::RefCell;
+
+    #[derive(Debug, CompositeTemplate)]
+    #[template(resource = "/io/github/achetagames/epic_asset_manager/history.ui")]
+    pub struct EpicDownloadHistory {
+        pub download_manager: OnceCell<crate::ui::widgets::download_manager::EpicDownloadManager>,
+        #[template_child]
+        pub search: TemplateChild<gtk4::SearchEntry>,
+        #[template_child]
+        pub result_filter: TemplateChild<gtk4::ComboBoxText>,
+        #[template_child]
+        pub entries: TemplateChild<gtk4::ListBox>,
+        pub history: RefCell<Vec<HistoryEntry>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for EpicDownloadHistory {
+        const NAME: &'static str = "EpicDownloadHistory";
+        type Type = super::EpicDownloadHistory;
+        type ParentType = gtk4::Box;
+
+        fn new() -> Self {
+            Self {
+                download_manager: OnceCell::new(),
+                search: TemplateChild::default(),
+                result_filter: TemplateChild::default(),
+                entries: TemplateChild::default(),
+                history: RefCell::new(Vec::new()),
+            }
+        }
+
+        fn class_init(klass: &mut Self::Class) {
+            Self::bind_template(klass);
+        }
+
+        // You must call `Widget`'s `init_template()` within `instance_init()`.
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for EpicDownloadHistory {
+        fn constructed(&self) {
+            self.parent_constructed();
+            self.obj().setup_filters();
+        }
+    }
+
+    impl WidgetImpl for EpicDownloadHistory {}
+    impl BoxImpl for EpicDownloadHistory {}
+}
+
+glib::wrapper! {
+    pub struct EpicDownloadHistory(ObjectSubclass<imp::EpicDownloadHistory>)
+        @extends gtk4::Widget, gtk4::Box;
+}
+
+impl Default for EpicDownloadHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EpicDownloadHistory {
+    pub fn new() -> Self {
+        glib::Object::new()
+    }
+
+    pub fn set_download_manager(
+        &self,
+        dm: &crate::ui::widgets::download_manager::EpicDownloadManager,
+    ) {
+        let self_ = self.imp();
+        // Do not run this twice
+        if self_.download_manager.get().is_some() {
+            return;
+        }
+        self_.download_manager.set(dm.clone()).unwrap();
+    }
+
+    fn setup_filters(&self) {
+        let self_ = self.imp();
+        let placeholder = gtk4::Label::builder()
+            .label("No downloads yet")
+            .margin_top(12)
+            .margin_bottom(12)
+            .build();
+        placeholder.add_css_class("dim-label");
+        self_.entries.set_placeholder(Some(&placeholder));
+        self_
+            .search
+            .connect_search_changed(clone!(@weak self as history => move |_| {
+                history.show_entries();
+            }));
+        self_
+            .result_filter
+            .connect_changed(clone!(@weak self as history => move |_| {
+                history.show_entries();
+            }));
+    }
+
+    /// Load the history from the database
+    pub fn load(&self) {
+        let self_ = self.imp();
+        self_
+            .history
+            .replace(crate::ui::widgets::download_manager::history::load());
+        self.show_entries();
+    }
+
+    fn show_entries(&self) {
+        let self_ = self.imp();
+        while let Some(row) = self_.entries.first_child() {
+            self_.entries.remove(&row);
+        }
+        let text = self_.search.text().to_string();
+        let result = self_
+            .result_filter
+            .active_id()
+            .filter(|id| !id.eq("all"))
+            .map(|id| DownloadResult::from_name(&id));
+        for entry in self_.history.borrow().iter() {
+            if result.map_or(false, |r| r != entry.result) {
+                continue;
+            }
+            if !text.is_empty() && !entry.matches(&text) {
+                continue;
+            }
+            self_.entries.append(&self.entry_row(entry));
+        }
+    }
+
+    fn entry_row(&self, entry: &HistoryEntry) -> adw::ActionRow {
+        let kind = match entry.item_type {
+            ItemType::Asset => "Asset",
+            ItemType::Docker => "Engine (Docker)",
+            ItemType::Epic => "Engine",
+            ItemType::Unknown => "Unknown",
+        };
+        let mut details = vec![
+            format!("{kind} {}", entry.release),
+            entry
+                .finished_at
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string(),
+            format_size(entry.size),
+            format!(
+                "{}:{:02}:{:02}",
+                entry.duration / 3600,
+                entry.duration / 60 % 60,
+                entry.duration % 60
+            ),
+            format!("{}/s", format_size(entry.average_speed)),
+        ];
+        if !entry.targets.is_empty() {
+            details.push(entry.targets.join(", "));
+        }
+        let row = adw::ActionRow::builder()
+            .title(glib::markup_escape_text(&entry.label).as_str())
+            .subtitle(glib::markup_escape_text(&details.join(" · ")).as_str())
+            .subtitle_lines(2)
+            .build();
+        let status = gtk4::Label::builder()
+            .label(match entry.result {
+                DownloadResult::Finished => "Finished",
+                DownloadResult::Failed => "Failed",
+                DownloadResult::Canceled => "Canceled",
+            })
+            .build();
+        if entry.result == DownloadResult::Failed {
+            status.add_css_class("error");
+        } else {
+            status.add_css_class("dim-label");
+        }
+        if let Some(error) = &entry.error {
+            status.set_tooltip_text(Some(error));
+        }
+        row.add_suffix(&status);
+
+        let rerun = gtk4::Button::builder()
+            .icon_name("folder-download-symbolic")
+            .tooltip_text("Download again")
+            .valign(gtk4::Align::Center)
+            .build();
+        rerun.add_css_class("flat");
+        rerun.set_sensitive(entry.item_type != ItemType::Asset || entry.asset.is_some());
+        let e = entry.clone();
+        rerun.connect_clicked(clone!(@weak self as history => move |_| {
+            if let Some(dm) = history.imp().download_manager.get() {
+                dm.history_rerun(&e);
+            }
+        }));
+        row.add_suffix(&rerun);
+        row
+    }
+}
+
+fn format_size(bytes: u128) -> String {
+    byte_unit::Byte::from_bytes(bytes)
+        .get_appropriate_unit(false)
+        .format(1)
+}
+
+impl Refresh for EpicDownloadHistory {
+    fn run_refresh(&self) {
+        self.load();
+    }
+
+    fn can_be_refreshed(&self) -> bool {
+        true
+    }
+}
@@ -1,4 +1,5 @@
 use crate::ui::widgets::download_manager::asset::Asset;
+use crate::ui::widgets::download_manager::conflict::ConflictPolicy;
 use gtk4::glib::clone;
 use gtk4::subclass::prelude::*;
 use gtk4::{self, gio, prelude::*};
@@ -28,7 +29,13 @@ pub mod imp {
         #[template_child]
         pub warning_row: TemplateChild<gtk::InfoBar>,
         #[template_child]
-        pub overwrite: TemplateChild<gtk4::CheckButton>,
+        pub report_label: TemplateChild<gtk4::Label>,
+        #[template_child]
+        pub report_files: TemplateChild<gtk4::Label>,
+        #[template_child]
+        pub conflict_policy: TemplateChild<gtk4::ComboBoxText>,
+        #[template_child]
+        pub post_download_command: TemplateChild<gtk4::Entry>,
     }
 
     #[glib::object_subclass]
@@ -47,7 +54,10 @@ pub mod imp {
                 window: OnceCell::new(),
                 select_target_directory: TemplateChild::default(),
                 warning_row: TemplateChild::default(),
-                overwrite: TemplateChild::default(),
+                report_label: TemplateChild::default(),
+                report_files: TemplateChild::default(),
+                conflict_policy: TemplateChild::default(),
+                post_download_command: TemplateChild::default(),
             }
         }
 
@@ -188,16 +198,20 @@ impl EpicAddToProject {
         if let Some(dm) = self_.download_manager.get() {
             if let Some(asset_info) = &*self_.asset.borrow() {
                 if let Some(id) = self_.select_target_directory.active_id() {
+                    let mut actions = vec![
+                        crate::ui::widgets::download_manager::PostDownloadAction::Copy(
+                            id.to_string(),
+                            ConflictPolicy::from_id(
+                                &self_.conflict_policy.active_id().unwrap_or_default(),
+                            ),
+                        ),
+                    ];
+                    actions.extend(super::post_download_command(&self_.post_download_command));
                     dm.add_asset_download(
                         self.selected_version(),
                         asset_info.clone(),
                         &None,
-                        Some(vec![
-                            crate::ui::widgets::download_manager::PostDownloadAction::Copy(
-                                id.to_string(),
-                                self_.overwrite.is_active(),
-                            ),
-                        ]),
+                        Some(actions),
                     );
                     self.emit_by_name::<()>("start-download", &[]);
                 }
@@ -211,6 +225,7 @@ impl EpicAddToProject {
             // Remove old manifest if we are setting a new asset
             if !asset_info.id.eq(&asset.id) {
                 self_.manifest.replace(None);
+                self_.post_download_command.set_text("");
             }
         };
         self_.warning_row.set_visible(false);
@@ -220,26 +235,17 @@ impl EpicAddToProject {
 
     fn validate_target_directory(&self) {
         let self_ = self.imp();
-        self_
-            .warning_row
-            .set_tooltip_text(Some("Files that would be overwritten: "));
+        self_.warning_row.set_visible(false);
         if let Some(manifest) = &*self_.manifest.borrow() {
             if let Some(id) = self_.select_target_directory.active_id() {
                 let path = std::path::PathBuf::from_str(id.as_str()).unwrap();
-                for (file, _) in manifest.files() {
-                    let mut p = path.clone();
-                    p.push(file);
-                    if p.exists() {
-                        self_.warning_row.set_visible(true);
-                        if let Some(old) = self_.warning_row.tooltip_text() {
-                            self_.warning_row.set_tooltip_text(Some(&format!(
-                                "{}\n{}",
-                                old,
-                                p.to_str().unwrap_or_default()
-                            )));
-                        };
-                    }
-                }
+                super::show_copy_report(
+                    manifest.clone(),
+                    path,
+                    &self_.warning_row,
+                    &self_.report_label,
+                    &self_.report_files,
+                );
             }
         }
     }
@@ -1,4 +1,5 @@
 use crate::ui::widgets::download_manager::asset::Asset;
+use crate::ui::widgets::download_manager::conflict::ConflictPolicy;
 use gtk4::glib::clone;
 use gtk4::subclass::prelude::*;
 use gtk4::{self, gio, prelude::*};
@@ -29,7 +30,13 @@ pub mod imp {
         #[template_child]
         pub warning_row: TemplateChild<gtk4::InfoBar>,
         #[template_child]
-        pub overwrite: TemplateChild<gtk4::CheckButton>,
+        pub report_label: TemplateChild<gtk4::Label>,
+        #[template_child]
+        pub report_files: TemplateChild<gtk4::Label>,
+        #[template_child]
+        pub conflict_policy: TemplateChild<gtk4::ComboBoxText>,
+        #[template_child]
+        pub post_download_command: TemplateChild<gtk4::Entry>,
     }
 
     #[glib::object_subclass]
@@ -49,7 +56,10 @@ pub mod imp {
                 settings: gio::Settings::new(crate::config::APP_ID),
                 select_target_directory: TemplateChild::default(),
                 warning_row: TemplateChild::default(),
-                overwrite: TemplateChild::default(),
+                report_label: TemplateChild::default(),
+                report_files: TemplateChild::default(),
+                conflict_policy: TemplateChild::default(),
+                post_download_command: TemplateChild::default(),
             }
         }
 
@@ -206,8 +216,15 @@ impl EpicCreateAssetProject {
             if let Some(id) = self_.select_target_directory.active_id() {
                 let mut path = PathBuf::from_str(id.as_str()).unwrap();
                 path.push(project);
-                if path.exists() {
-                    self_.warning_row.set_visible(true);
+                self_.warning_row.set_visible(false);
+                if let Some(manifest) = &*self_.manifest.borrow() {
+                    super::show_copy_report(
+                        manifest.clone(),
+                        path,
+                        &self_.warning_row,
+                        &self_.report_label,
+                        &self_.report_files,
+                    );
                 }
             }
         }
@@ -221,16 +238,20 @@ impl EpicCreateAssetProject {
                     if let Some(id) = self_.select_target_directory.active_id() {
                         let mut path = PathBuf::from_str(id.as_str()).unwrap();
                         path.push(project);
+                        let mut actions = vec![
+                            crate::ui::widgets::download_manager::PostDownloadAction::Copy(
+                                path.to_str().unwrap().to_string(),
+                                ConflictPolicy::from_id(
+                                    &self_.conflict_policy.active_id().unwrap_or_default(),
+                                ),
+                            ),
+                        ];
+                        actions.extend(super::post_download_command(&self_.post_download_command));
                         dm.add_asset_download(
                             self.selected_version(),
                             asset_info.clone(),
                             &None,
-                            Some(vec![
-                                crate::ui::widgets::download_manager::PostDownloadAction::Copy(
-                                    path.to_str().unwrap().to_string(),
-                                    self_.overwrite.is_active(),
-                                ),
-                            ]),
+                            Some(actions),
                         );
                         self.emit_by_name::<()>("start-download", &[]);
                     }
@@ -245,6 +266,7 @@ impl EpicCreateAssetProject {
             // Remove old manifest if we are setting a new asset
             if !asset_info.id.eq(&asset.id) {
                 self_.manifest.replace(None);
+                self_.post_download_command.set_text("");
             }
         };
         self_.warning_row.set_visible(false);
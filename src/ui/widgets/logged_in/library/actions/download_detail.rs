@@ -22,6 +22,8 @@ pub mod imp {
         pub settings: gio::Settings,
         #[template_child]
         pub select_target_directory: TemplateChild<gtk4::ComboBoxText>,
+        #[template_child]
+        pub post_download_command: TemplateChild<gtk4::Entry>,
     }
 
     #[glib::object_subclass]
@@ -39,6 +41,7 @@ pub mod imp {
                 download_manager: OnceCell::new(),
                 settings: gio::Settings::new(crate::config::APP_ID),
                 select_target_directory: TemplateChild::default(),
+                post_download_command: TemplateChild::default(),
             }
         }
 
@@ -178,7 +181,7 @@ impl EpicDownloadDetails {
                         .select_target_directory
                         .active_id()
                         .map(|v| v.to_string()),
-                    None,
+                    super::post_download_command(&self_.post_download_command).map(|a| vec![a]),
                 );
                 self.emit_by_name::<()>("start-download", &[]);
             }
@@ -191,6 +194,7 @@ impl EpicDownloadDetails {
             // Remove old manifest if we are setting a new asset
             if !asset_info.id.eq(&asset.id) {
                 self_.manifest.replace(None);
+                self_.post_download_command.set_text("");
             }
         };
         self_.asset.replace(Some(asset.clone()));
@@ -1,8 +1,9 @@
+use crate::ui::widgets::download_manager::asset::VerificationReport;
 use gtk4::glib::clone;
 use gtk4::subclass::prelude::*;
 use gtk4::{self, gio, prelude::*};
 use gtk4::{glib, CompositeTemplate};
-use gtk_macros::action;
+use gtk_macros::{action, get_action};
 use log::debug;
 
 pub mod imp {
@@ -14,6 +15,9 @@ pub mod imp {
     pub struct EpicLocalAsset {
         label: RefCell<Option<String>>,
         path: RefCell<Option<String>>,
+        release: RefCell<Option<String>>,
+        status: RefCell<Option<String>>,
+        pub report: RefCell<Option<VerificationReport>>,
         pub actions: gio::SimpleActionGroup,
     }
 
@@ -27,6 +31,9 @@ pub mod imp {
             Self {
                 label: RefCell::new(None),
                 path: RefCell::new(None),
+                release: RefCell::new(None),
+                status: RefCell::new(None),
+                report: RefCell::new(None),
                 actions: gio::SimpleActionGroup::new(),
             }
         }
@@ -50,9 +57,17 @@ pub mod imp {
         fn signals() -> &'static [glib::subclass::Signal] {
             static SIGNALS: once_cell::sync::Lazy<Vec<glib::subclass::Signal>> =
                 once_cell::sync::Lazy::new(|| {
-                    vec![glib::subclass::Signal::builder("delete")
-                        .flags(glib::SignalFlags::ACTION)
-                        .build()]
+                    vec![
+                        glib::subclass::Signal::builder("delete")
+                            .flags(glib::SignalFlags::ACTION)
+                            .build(),
+                        glib::subclass::Signal::builder("verify")
+                            .flags(glib::SignalFlags::ACTION)
+                            .build(),
+                        glib::subclass::Signal::builder("repair")
+                            .flags(glib::SignalFlags::ACTION)
+                            .build(),
+                    ]
                 });
             SIGNALS.as_ref()
         }
@@ -63,6 +78,8 @@ pub mod imp {
                 vec![
                     glib::ParamSpecString::builder("label").build(),
                     glib::ParamSpecString::builder("path").build(),
+                    glib::ParamSpecString::builder("release").build(),
+                    glib::ParamSpecString::builder("status").build(),
                 ]
             });
 
@@ -85,6 +102,18 @@ pub mod imp {
                         .expect("type conformity checked by `Object::set_property`");
                     self.path.replace(path);
                 }
+                "release" => {
+                    let release = value
+                        .get()
+                        .expect("type conformity checked by `Object::set_property`");
+                    self.release.replace(release);
+                }
+                "status" => {
+                    let status = value
+                        .get()
+                        .expect("type conformity checked by `Object::set_property`");
+                    self.status.replace(status);
+                }
                 _ => unimplemented!(),
             }
         }
@@ -93,6 +122,8 @@ pub mod imp {
             match pspec.name() {
                 "label" => self.label.borrow().to_value(),
                 "path" => self.path.borrow().to_value(),
+                "release" => self.release.borrow().to_value(),
+                "status" => self.status.borrow().to_value(),
                 _ => unimplemented!(),
             }
         }
@@ -136,6 +167,85 @@ impl EpicLocalAsset {
                 local_asset.delete();
             })
         );
+
+        action!(
+            self_.actions,
+            "verify",
+            clone!(@weak self as local_asset => move |_, _| {
+                local_asset.verify();
+            })
+        );
+
+        action!(
+            self_.actions,
+            "repair",
+            clone!(@weak self as local_asset => move |_, _| {
+                local_asset.repair();
+            })
+        );
+        get_action!(self_.actions, @repair).set_enabled(false);
+    }
+
+    pub fn verify(&self) {
+        let self_ = self.imp();
+        get_action!(self_.actions, @verify).set_enabled(false);
+        get_action!(self_.actions, @repair).set_enabled(false);
+        self.set_property("status", "<i>Verifying files...</i>");
+        self.emit_by_name::<()>("verify", &[]);
+    }
+
+    pub fn repair(&self) {
+        let self_ = self.imp();
+        get_action!(self_.actions, @repair).set_enabled(false);
+        self.set_property("status", "<i>Repair queued in the download manager</i>");
+        self.emit_by_name::<()>("repair", &[]);
+    }
+
+    /// Result of the last verification
+    pub fn report(&self) -> Option<VerificationReport> {
+        self.imp().report.borrow().clone()
+    }
+
+    /// Show the result of the verification and allow repair if needed
+    pub fn set_report(&self, report: Option<&VerificationReport>) {
+        let self_ = self.imp();
+        get_action!(self_.actions, @verify).set_enabled(true);
+        self_.report.replace(report.cloned());
+        let Some(report) = report else {
+            self.set_property("status", "<i>Unable to verify, missing manifest</i>");
+            return;
+        };
+        get_action!(self_.actions, @repair).set_enabled(report.needs_repair());
+        if !report.needs_repair() && report.extra.is_empty() {
+            self.set_property("status", "<i>All files are valid</i>");
+            self.set_tooltip_text(None);
+            return;
+        }
+        self.set_property(
+            "status",
+            format!(
+                "<i>{} missing, {} corrupted, {} extra files</i>",
+                report.missing.len(),
+                report.corrupt.len(),
+                report.extra.len()
+            ),
+        );
+        let mut details: Vec<String> = Vec::new();
+        for (title, files) in [
+            ("Missing", &report.missing),
+            ("Corrupted", &report.corrupt),
+            ("Extra", &report.extra),
+        ] {
+            if files.is_empty() {
+                continue;
+            }
+            let mut listed: Vec<String> = files.iter().take(20).cloned().collect();
+            if files.len() > listed.len() {
+                listed.push(format!("... and {} more", files.len() - listed.len()));
+            }
+            details.push(format!("{title}:\n{}", listed.join("\n")));
+        }
+        self.set_tooltip_text(Some(&details.join("\n\n")));
     }
 
     pub fn delete(&self) {
@@ -162,4 +272,8 @@ impl EpicLocalAsset {
     pub fn path(&self) -> Option<String> {
         self.property("path")
     }
+
+    pub fn release(&self) -> Option<String> {
+        self.property("release")
+    }
 }
@@ -1,3 +1,4 @@
+use crate::ui::widgets::download_manager::asset::Asset;
 use gtk4::glib::clone;
 use gtk4::subclass::prelude::*;
 use gtk4::{self, gio, prelude::*};
@@ -66,9 +67,14 @@ pub mod imp {
         fn signals() -> &'static [gtk4::glib::subclass::Signal] {
             static SIGNALS: once_cell::sync::Lazy<Vec<gtk4::glib::subclass::Signal>> =
                 once_cell::sync::Lazy::new(|| {
-                    vec![gtk4::glib::subclass::Signal::builder("removed")
-                        .flags(glib::SignalFlags::ACTION)
-                        .build()]
+                    vec![
+                        gtk4::glib::subclass::Signal::builder("removed")
+                            .flags(glib::SignalFlags::ACTION)
+                            .build(),
+                        gtk4::glib::subclass::Signal::builder("start-download")
+                            .flags(glib::SignalFlags::ACTION)
+                            .build(),
+                    ]
                 });
             SIGNALS.as_ref()
         }
@@ -94,6 +100,19 @@ impl EpicLocalAssets {
         glib::Object::new()
     }
 
+    pub fn set_download_manager(
+        &self,
+        dm: &crate::ui::widgets::download_manager::EpicDownloadManager,
+    ) {
+        let self_ = self.imp();
+        // Do not run this twice
+        if self_.download_manager.get().is_some() {
+            return;
+        }
+
+        self_.download_manager.set(dm.clone()).unwrap();
+    }
+
     pub fn set_asset(&self, asset: &egs_api::api::types::asset_info::AssetInfo) {
         let self_ = self.imp();
         self_.asset.replace(Some(asset.clone()));
@@ -128,6 +147,7 @@ impl EpicLocalAssets {
                                     .unwrap_or_default()
                                     .to_string(),
                             );
+                            row.set_property("release", rel.id.clone());
                             if app.eq(release) {
                                 &self_.local_list
                             } else {
@@ -143,6 +163,22 @@ impl EpicLocalAssets {
                                     None
                                 }),
                             );
+                            row.connect_local(
+                                "verify",
+                                false,
+                                clone!(@weak self as mla, @weak row => @default-return None, move |_| {
+                                    mla.verify(&row);
+                                    None
+                                }),
+                            );
+                            row.connect_local(
+                                "repair",
+                                false,
+                                clone!(@weak self as mla, @weak row => @default-return None, move |_| {
+                                    mla.repair(&row);
+                                    None
+                                }),
+                            );
                         }
                     }
                 }
@@ -168,6 +204,53 @@ impl EpicLocalAssets {
         self.emit_by_name::<()>("removed", &[]);
     }
 
+    pub fn verify(&self, widget: &super::local_asset::EpicLocalAsset) {
+        let Some(p) = widget.path() else { return };
+        let (sender, receiver) = gtk4::glib::MainContext::channel(gtk4::glib::PRIORITY_DEFAULT);
+        receiver.attach(
+            None,
+            clone!(@weak widget => @default-return glib::Continue(false), move |report: Option<crate::ui::widgets::download_manager::asset::VerificationReport>| {
+                widget.set_report(report.as_ref());
+                glib::Continue(false)
+            }),
+        );
+        std::thread::spawn(move || {
+            let report =
+                crate::ui::widgets::download_manager::asset::verify_asset(&PathBuf::from(p));
+            if sender.send(report).is_err() {
+                error!("Unable to report verification result");
+            }
+        });
+    }
+
+    /// Download the missing and corrupted files found by the verification into the same vault
+    pub fn repair(&self, widget: &super::local_asset::EpicLocalAsset) {
+        let self_ = self.imp();
+        let Some(dm) = self_.download_manager.get() else { return };
+        let Some(asset) = self_.asset.borrow().clone() else { return };
+        let (Some(release), Some(p), Some(report)) =
+            (widget.release(), widget.path(), widget.report())
+        else {
+            return;
+        };
+        let data = PathBuf::from(p);
+        let Some(manifest) = crate::download::pipeline::load_asset_manifest(&data) else {
+            error!("Unable to repair {:?} without its manifest", data);
+            return;
+        };
+        // Path points to <vault>/<app_id>/data
+        let Some(vault) = data
+            .parent()
+            .and_then(std::path::Path::parent)
+            .map(|v| v.to_string_lossy().to_string())
+        else {
+            return;
+        };
+        let files = report.missing.into_iter().chain(report.corrupt).collect();
+        dm.repair_asset_download(release, asset, &Some(vault), manifest, files);
+        self.emit_by_name::<()>("start-download", &[]);
+    }
+
     pub fn empty(&self) -> bool {
         let self_ = self.imp();
         self_.local_list.first_child().is_none() && self_.local_list_other.first_child().is_none()
@@ -7,6 +7,8 @@ mod manage_local_assets;
 use crate::models::asset_data::AssetType;
 use crate::tools::or::Or;
 use crate::ui::widgets::download_manager::asset::Asset;
+use crate::ui::widgets::download_manager::conflict::{copy_report, CopyReport};
+use crate::ui::widgets::download_manager::PostDownloadAction;
 use adw::prelude::ExpanderRowExt;
 use egs_api::api::types::asset_info::AssetInfo;
 use gtk4::glib::clone;
@@ -15,6 +17,39 @@ use gtk4::{self, gio, prelude::*, SizeGroupMode};
 use gtk4::{glib, CompositeTemplate};
 use gtk_macros::action;
 
+/// Compare the manifest with the target in a thread and show which files would change
+fn show_copy_report(
+    manifest: egs_api::api::types::download_manifest::DownloadManifest,
+    target: std::path::PathBuf,
+    warning_row: &gtk4::InfoBar,
+    report_label: &gtk4::Label,
+    report_files: &gtk4::Label,
+) {
+    let (sender, receiver) = glib::MainContext::channel::<CopyReport>(gtk4::glib::PRIORITY_DEFAULT);
+    receiver.attach(
+        None,
+        clone!(@weak warning_row, @weak report_label, @weak report_files => @default-return glib::Continue(false), move |report| {
+            warning_row.set_visible(!report.changed.is_empty() || !report.unchanged.is_empty());
+            report_label.set_label(&report.summary());
+            report_files.set_label(&report.changed.join("\n"));
+            glib::Continue(false)
+        }),
+    );
+    std::thread::spawn(move || {
+        sender.send(copy_report(&manifest, &target)).ok();
+    });
+}
+
+/// Post download command typed next to the other download actions, if any
+fn post_download_command(entry: &gtk4::Entry) -> Option<PostDownloadAction> {
+    let command = entry.text().trim().to_string();
+    if command.is_empty() {
+        None
+    } else {
+        Some(PostDownloadAction::RunCommand(command))
+    }
+}
+
 pub enum Action {
     Local,
     Download,
@@ -255,6 +290,7 @@ impl EpicAssetActions {
         self_.download_details.set_download_manager(&dm.clone());
         self_.create_asset_project.set_download_manager(&dm.clone());
         self_.add_to_project.set_download_manager(&dm.clone());
+        self_.local_assets.set_download_manager(&dm.clone());
         self_.download_manager.set(dm.clone()).unwrap();
     }
 
@@ -276,6 +312,15 @@ impl EpicAssetActions {
             }),
         );
 
+        self_.local_assets.connect_local(
+            "start-download",
+            false,
+            clone!(@weak self as ead => @default-return None, move |_| {
+                ead.emit_by_name::<()>("start-download", &[]);
+                None
+            }),
+        );
+
         self_.local_assets.connect_local(
             "removed",
             false,
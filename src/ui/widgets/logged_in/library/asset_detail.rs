@@ -1,4 +1,5 @@
 use crate::models::asset_data::AssetType;
+use crate::models::{asset_notes, collections};
 use diesel::dsl::exists;
 use diesel::{select, ExpressionMethods, QueryDsl, RunQueryDsl};
 use egs_api::api::types::asset_info::AssetInfo;
@@ -9,6 +10,9 @@ use gtk4::{glib, CompositeTemplate};
 use gtk_macros::{action, get_action};
 use log::{error, info};
 
+/// Seconds without typing before the notes are saved
+const NOTES_SAVE_DELAY: u32 = 2;
+
 pub mod imp {
     use super::*;
     use crate::ui::widgets::download_manager::EpicDownloadManager;
@@ -58,6 +62,12 @@ pub mod imp {
         pub download_manager: OnceCell<EpicDownloadManager>,
         pub details_group: gtk4::SizeGroup,
         pub settings: gtk4::gio::Settings,
+        pub tags_box: RefCell<Option<gtk4::Box>>,
+        pub collections_box: RefCell<Option<gtk4::Box>>,
+        pub rating_box: RefCell<Option<gtk4::Box>>,
+        /// Asset the notes editor shows, with its buffer
+        pub notes: RefCell<Option<(String, gtk4::TextBuffer)>>,
+        pub notes_save: RefCell<Option<glib::SourceId>>,
         position: RefCell<u32>,
     }
 
@@ -91,6 +101,11 @@ pub mod imp {
                 download_manager: OnceCell::new(),
                 details_group: gtk4::SizeGroup::new(gtk4::SizeGroupMode::Horizontal),
                 settings: gio::Settings::new(crate::config::APP_ID),
+                tags_box: RefCell::new(None),
+                collections_box: RefCell::new(None),
+                rating_box: RefCell::new(None),
+                notes: RefCell::new(None),
+                notes_save: RefCell::new(None),
                 position: RefCell::new(0),
             }
         }
@@ -482,6 +497,8 @@ impl EpicAssetDetails {
             }
         };
 
+        // Notes typed right before switching belong to the previous asset
+        self.save_notes();
         self_.images.set_property("asset", asset.id.clone());
         self_.asset.replace(Some(asset.clone()));
         self.set_actions();
@@ -554,6 +571,9 @@ impl EpicAssetDetails {
             );
         }
 
+        self.add_user_data_rows();
+        self.add_notes_rows(&asset.id);
+
         if let Some(desc) = &asset.long_description {
             let label = gtk4::Label::builder().wrap(true).xalign(0.0).build();
             label.set_markup(&html2pango::matrix_html_to_markup(desc).replace("\n\n", "\n"));
@@ -569,6 +589,230 @@ impl EpicAssetDetails {
         self.check_favorite();
     }
 
+    /// Rows to edit the tags and collections of the asset
+    fn add_user_data_rows(&self) {
+        let self_ = self.imp();
+        let tags = gtk4::Box::new(gtk4::Orientation::Horizontal, 5);
+        self.add_info_row("Tags", &tags);
+        self_.tags_box.replace(Some(tags));
+        let collections = gtk4::Box::new(gtk4::Orientation::Horizontal, 5);
+        self.add_info_row("Collections", &collections);
+        self_.collections_box.replace(Some(collections));
+        self.show_user_data();
+    }
+
+    fn show_user_data(&self) {
+        let self_ = self.imp();
+        let Some(asset) = self.asset() else {
+            return;
+        };
+        if let Some(b) = self_.tags_box.borrow().as_ref() {
+            while let Some(el) = b.first_child() {
+                b.remove(&el);
+            }
+            for tag in collections::asset_tags(&asset.id) {
+                let id = asset.id.clone();
+                let removed = tag.clone();
+                b.append(&self.removable_item(&tag, "Remove tag", move || {
+                    collections::remove_tag(&id, &removed);
+                }));
+            }
+            let entry = gtk4::Entry::builder()
+                .placeholder_text("Add tag")
+                .hexpand(true)
+                .build();
+            entry.connect_activate(clone!(@weak self as details => move |e| {
+                let tag = e.text().trim().to_string();
+                if let Some(asset) = details.asset() {
+                    if !tag.is_empty() {
+                        collections::add_tag(&asset.id, &tag);
+                        details.user_data_changed();
+                    }
+                }
+            }));
+            b.append(&entry);
+        }
+        if let Some(b) = self_.collections_box.borrow().as_ref() {
+            while let Some(el) = b.first_child() {
+                b.remove(&el);
+            }
+            let current = collections::asset_collections(&asset.id);
+            for name in &current {
+                let id = asset.id.clone();
+                let collection = name.clone();
+                b.append(
+                    &self.removable_item(name, "Remove from collection", move || {
+                        collections::remove_from_collection(&collection, &id);
+                    }),
+                );
+            }
+            let combo = gtk4::ComboBoxText::with_entry();
+            combo.set_hexpand(true);
+            combo.set_tooltip_text(Some("Pick a collection or type the name of a new one"));
+            for name in collections::collections() {
+                if !current.contains(&name) {
+                    combo.append_text(&name);
+                }
+            }
+            combo.connect_changed(clone!(@weak self as details => move |c| {
+                if c.active().is_some() {
+                    if let Some(name) = c.active_text() {
+                        details.add_to_collection(&name);
+                    }
+                }
+            }));
+            if let Some(entry) = combo.child().and_then(|c| c.downcast::<gtk4::Entry>().ok()) {
+                entry.set_placeholder_text(Some("Add to collection"));
+                entry.connect_activate(clone!(@weak self as details => move |e| {
+                    details.add_to_collection(&e.text());
+                }));
+            }
+            b.append(&combo);
+        }
+    }
+
+    fn removable_item(
+        &self,
+        label: &str,
+        tooltip: &str,
+        remove: impl Fn() + 'static,
+    ) -> gtk4::Button {
+        let button = gtk4::Button::builder()
+            .child(&Self::build_box_with_icon_label(
+                Some(label),
+                "window-close-symbolic",
+            ))
+            .tooltip_text(tooltip)
+            .build();
+        button.add_css_class("flat");
+        button.connect_clicked(clone!(@weak self as details => move |_| {
+            remove();
+            details.user_data_changed();
+        }));
+        button
+    }
+
+    /// Rating stars and the private notes of the asset
+    fn add_notes_rows(&self, id: &str) {
+        let self_ = self.imp();
+        let note = asset_notes::note(id);
+        let stars = gtk4::Box::new(gtk4::Orientation::Horizontal, 0);
+        for value in 1..=asset_notes::MAX_RATING {
+            let star = gtk4::Button::from_icon_name("non-starred-symbolic");
+            star.add_css_class("flat");
+            star.set_tooltip_text(Some(&format!(
+                "Rate {value} of {}",
+                asset_notes::MAX_RATING
+            )));
+            star.connect_clicked(clone!(@weak self as details => move |_| {
+                details.rate(value);
+            }));
+            stars.append(&star);
+        }
+        self.add_info_row("Rating", &stars);
+        self_.rating_box.replace(Some(stars));
+        self.show_rating(note.rating);
+
+        let buffer = gtk4::TextBuffer::new(None);
+        buffer.set_text(&note.notes);
+        buffer.set_modified(false);
+        let view = gtk4::TextView::builder()
+            .buffer(&buffer)
+            .wrap_mode(gtk4::WrapMode::WordChar)
+            .monospace(true)
+            .hexpand(true)
+            .height_request(80)
+            .tooltip_text("Private notes in Markdown")
+            .build();
+        buffer.connect_changed(clone!(@weak self as details => move |_| {
+            details.schedule_notes_save();
+        }));
+        self_.notes.replace(Some((id.to_string(), buffer)));
+        self.add_info_row("Notes", &view);
+    }
+
+    /// Save the notes once the user stopped typing for a moment
+    fn schedule_notes_save(&self) {
+        let self_ = self.imp();
+        if let Some(source) = self_.notes_save.take() {
+            source.remove();
+        }
+        self_
+            .notes_save
+            .replace(Some(glib::timeout_add_seconds_local(
+                NOTES_SAVE_DELAY,
+                clone!(@weak self as details => @default-return glib::Continue(false), move || {
+                    details.imp().notes_save.replace(None);
+                    details.save_notes();
+                    glib::Continue(false)
+                }),
+            )));
+    }
+
+    /// Save changed notes right away
+    fn save_notes(&self) {
+        let self_ = self.imp();
+        if let Some(source) = self_.notes_save.take() {
+            source.remove();
+        }
+        if let Some((id, buffer)) = self_.notes.borrow().as_ref() {
+            if buffer.is_modified() {
+                let (start, end) = buffer.bounds();
+                asset_notes::save_notes(id, &buffer.text(&start, &end, false));
+                buffer.set_modified(false);
+            }
+        }
+    }
+
+    /// Clicking the current rating again clears it
+    fn rate(&self, value: i32) {
+        let Some(asset) = self.asset() else {
+            return;
+        };
+        let rating = if asset_notes::note(&asset.id).rating == value {
+            0
+        } else {
+            value
+        };
+        asset_notes::save_rating(&asset.id, rating);
+        self.show_rating(rating);
+        self.refresh_asset();
+    }
+
+    fn show_rating(&self, rating: i32) {
+        let self_ = self.imp();
+        if let Some(b) = self_.rating_box.borrow().as_ref() {
+            let mut value = 1;
+            let mut child = b.first_child();
+            while let Some(star) = child {
+                if let Some(button) = star.downcast_ref::<gtk4::Button>() {
+                    button.set_icon_name(if value <= rating {
+                        "starred-symbolic"
+                    } else {
+                        "non-starred-symbolic"
+                    });
+                }
+                value += 1;
+                child = star.next_sibling();
+            }
+        }
+    }
+
+    fn add_to_collection(&self, name: &str) {
+        let name = name.trim();
+        if let Some(asset) = self.asset() {
+            if !name.is_empty() {
+                collections::add_to_collection(name, &asset.id);
+                self.user_data_changed();
+            }
+        }
+    }
+
+    fn user_data_changed(&self) {
+        self.show_user_data();
+        self.refresh_asset();
+    }
+
     fn add_info_row(&self, title: &str, widget: &impl IsA<gtk4::Widget>) {
         let self_ = self.imp();
         self_
@@ -662,6 +906,7 @@ impl EpicAssetDetails {
 
     pub fn collapse(&self) {
         let self_ = self.imp();
+        self.save_notes();
         self.set_property("expanded", false);
         if let Some(w) = self_.window.get() {
             let w_ = w.imp();
@@ -1,4 +1,6 @@
+use crate::models::asset_cache;
 use crate::tools::asset_info::Search;
+use crate::tools::query::{Field, ParseError, Query, QueryTarget};
 use crate::ui::widgets::logged_in::refresh::Refresh;
 use asset::EpicAsset;
 use glib::clone;
@@ -6,10 +8,8 @@ use gtk4::{self, prelude::*, CustomSorter};
 use gtk4::{gio, glib, subclass::prelude::*, CompositeTemplate};
 use gtk_macros::action;
 use log::{debug, error, trace};
+use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
-use std::fs;
-use std::fs::File;
-use std::io::Write;
 use std::path::{Path, PathBuf};
 
 mod actions;
@@ -18,6 +18,42 @@ pub mod asset_detail;
 pub mod image_stack;
 mod sidebar;
 
+/// Asset checked against the search query, with the search index results of the free text
+struct SearchTarget<'a> {
+    asset: &'a crate::models::asset_data::AssetData,
+    hits: &'a HashMap<String, Option<HashSet<String>>>,
+}
+
+impl QueryTarget for SearchTarget<'_> {
+    fn text_matches(&self, text: &str) -> bool {
+        match self.hits.get(text) {
+            Some(Some(ids)) => ids.contains(&self.asset.id()),
+            // The title check is only used if the index is not available
+            _ => self
+                .asset
+                .name()
+                .to_lowercase()
+                .contains(&text.to_lowercase()),
+        }
+    }
+
+    fn field_matches(&self, field: &Field) -> bool {
+        self.asset.matches_field(field)
+    }
+}
+
+/// Look up the free text of the query in the search index once, not for every asset
+fn search_hits(query: &Query) -> HashMap<String, Option<HashSet<String>>> {
+    query
+        .texts()
+        .into_iter()
+        .map(|t| {
+            let ids = asset_cache::search(&t);
+            (t, ids)
+        })
+        .collect()
+}
+
 pub mod imp {
     use super::*;
     use crate::config;
@@ -45,6 +81,8 @@ pub mod imp {
         #[template_child]
         pub asset_search: TemplateChild<gtk4::SearchEntry>,
         #[template_child]
+        pub search_error: TemplateChild<gtk4::Label>,
+        #[template_child]
         pub search_toggle: TemplateChild<gtk4::ToggleButton>,
         #[template_child]
         pub select_order_by: TemplateChild<gtk4::ComboBoxText>,
@@ -65,6 +103,8 @@ pub mod imp {
         pub grid_model: ListStore,
         pub loaded_assets: RefCell<HashMap<String, egs_api::api::types::asset_info::AssetInfo>>,
         pub loaded_data: RefCell<HashMap<String, crate::models::asset_data::AssetData>>,
+        /// Tags, collections and ratings of all assets, loaded once for the assets being added
+        pub user_data: RefCell<Option<HashMap<String, crate::models::collections::UserData>>>,
         pub asset_product_names: RefCell<HashMap<String, String>>,
         pub asset_load_pool: ThreadPool,
         pub image_load_pool: ThreadPool,
@@ -89,6 +129,7 @@ pub mod imp {
                 sidebar: TemplateChild::default(),
                 asset_grid: TemplateChild::default(),
                 asset_search: TemplateChild::default(),
+                search_error: TemplateChild::default(),
                 search_toggle: TemplateChild::default(),
                 select_order_by: TemplateChild::default(),
                 order: TemplateChild::default(),
@@ -111,6 +152,7 @@ pub mod imp {
                 grid_model: gio::ListStore::new(crate::models::asset_data::AssetData::static_type()),
                 loaded_assets: RefCell::new(HashMap::new()),
                 loaded_data: RefCell::new(HashMap::new()),
+                user_data: RefCell::new(None),
                 asset_product_names: RefCell::new(HashMap::new()),
                 asset_load_pool: ThreadPool::with_name("Asset Load Pool".to_string(), 15),
                 image_load_pool: ThreadPool::with_name("Image Load Pool".to_string(), 15),
@@ -356,6 +398,28 @@ impl EpicLibraryBox {
                     info2.unwrap().cmp(&info1.unwrap()).into()
                 }
             }),
+            "rating" => gtk4::CustomSorter::new(move |obj1, obj2| {
+                let info1 = obj1
+                    .downcast_ref::<crate::models::asset_data::AssetData>()
+                    .unwrap();
+                let info2 = obj2
+                    .downcast_ref::<crate::models::asset_data::AssetData>()
+                    .unwrap();
+                let rating = if asc {
+                    info1.rating().cmp(&info2.rating())
+                } else {
+                    info2.rating().cmp(&info1.rating())
+                };
+                // Assets with the same rating stay in name order
+                rating
+                    .then_with(|| {
+                        info1
+                            .name()
+                            .to_lowercase()
+                            .cmp(&info2.name().to_lowercase())
+                    })
+                    .into()
+            }),
             "updated" => gtk4::CustomSorter::new(move |obj1, obj2| {
                 let info1 = obj1
                     .downcast_ref::<crate::models::asset_data::AssetData>()
@@ -448,6 +512,7 @@ impl EpicLibraryBox {
             vec.clear();
         }
         self.update_count();
+        self_.sidebar.update_saved_search_counts();
         // Scroll to top if nothing is selected
         if !self_.details.has_asset() {
             if let Some(adj) = self_.asset_grid.vadjustment() {
@@ -584,26 +649,71 @@ impl EpicLibraryBox {
         let search = self.search();
         let filter_p = self.filter();
         if filter_p.is_none() && search.is_none() {
+            self.show_search_error(None);
             self_.filter_model.set_filter(None::<&gtk4::CustomFilter>);
             self.update_count();
             return;
         }
 
+        let query = match search.as_deref().map(Query::parse).transpose() {
+            Ok(q) => {
+                self.show_search_error(None);
+                q.flatten()
+            }
+            Err(e) => {
+                // Keep the last working filter while the query is being typed
+                self.show_search_error(Some(&e));
+                return;
+            }
+        };
+        let hits = query.as_ref().map(search_hits).unwrap_or_default();
         let filter = gtk4::CustomFilter::new(move |object| {
             let asset = object
                 .downcast_ref::<crate::models::asset_data::AssetData>()
                 .unwrap();
-            search.as_ref().map_or(true, |se| {
-                asset
-                    .name()
-                    .to_ascii_lowercase()
-                    .contains(&se.to_ascii_lowercase())
-            }) && filter_p.as_ref().map_or(true, |f| asset.check_category(f))
+            query
+                .as_ref()
+                .map_or(true, |q| q.matches(&SearchTarget { asset, hits: &hits }))
+                && filter_p.as_ref().map_or(true, |f| asset.check_category(f))
         });
         self_.filter_model.set_filter(Some(&filter));
         self.update_count();
     }
 
+    /// Number of loaded assets matching the query, shown next to saved searches
+    pub fn count_matches(&self, query: &Query) -> usize {
+        let self_ = self.imp();
+        let hits = search_hits(query);
+        self_
+            .loaded_data
+            .borrow()
+            .values()
+            .filter(|asset| query.matches(&SearchTarget { asset, hits: &hits }))
+            .count()
+    }
+
+    pub fn set_search(&self, text: &str) {
+        let self_ = self.imp();
+        self_.asset_search.set_text(text);
+    }
+
+    fn show_search_error(&self, error: Option<&ParseError>) {
+        let self_ = self.imp();
+        match error {
+            None => {
+                self_.asset_search.remove_css_class("error");
+                self_.asset_search.set_tooltip_text(None);
+                self_.search_error.set_visible(false);
+            }
+            Some(e) => {
+                self_.asset_search.add_css_class("error");
+                self_.asset_search.set_tooltip_text(Some(&e.to_string()));
+                self_.search_error.set_label(&e.to_string());
+                self_.search_error.set_visible(true);
+            }
+        }
+    }
+
     pub fn add_asset(
         &self,
         asset: &egs_api::api::types::asset_info::AssetInfo,
@@ -659,7 +769,14 @@ impl EpicLibraryBox {
                     }
                 }
             } {
-                let data = crate::models::asset_data::AssetData::new(asset, image);
+                let user_data = self_
+                    .user_data
+                    .borrow_mut()
+                    .get_or_insert_with(crate::models::collections::user_data)
+                    .get(&asset.id)
+                    .cloned()
+                    .unwrap_or_default();
+                let data = crate::models::asset_data::AssetData::new(asset, image, user_data);
                 let mut data_hash = self_.loaded_data.borrow_mut();
                 data_hash.insert(data.id(), data.clone());
                 if let Ok(mut vec) = self_.assets_pending.write() {
@@ -761,66 +878,34 @@ impl EpicLibraryBox {
             let cache_dir = self_.settings.string("cache-directory").to_string();
             let cache_path = PathBuf::from(cache_dir);
             debug!("Fetching assets");
-            let mut cached: Vec<String> = vec![];
-            if cache_path.is_dir() {
-                debug!("Checking cache");
-                let entries = std::fs::read_dir(cache_path).unwrap();
-                for entry in entries {
-                    let sender = win_.model.borrow().sender.clone();
-                    if let Ok(entr) = entry {
-                        let mut asset_file = entr.path();
-                        cached.push(entr.file_name().into_string().unwrap_or_default());
-                        asset_file.push("asset_info.json");
-                        self_.asset_load_pool.execute(move || {
-                            // Load assets from cache
-
-                            if let Ok(w) = crate::RUNNING.read() {
-                                if !*w {
-                                    return;
-                                }
-                            }
-
-                            if asset_file.exists() {
-                                sender
-                                    .send(crate::ui::messages::Msg::StartAssetProcessing)
-                                    .unwrap();
-                                if let Ok(f) = std::fs::File::open(asset_file.as_path()) {
-                                    if let Ok(asset) = serde_json::from_reader(f) {
-                                        sender
-                                            .send(crate::ui::messages::Msg::ProcessAssetInfo(asset))
-                                            .unwrap();
-                                    }
-                                };
-                            }
-                        });
-                    }
-                }
-            };
-            self.set_property("to-load", 0u32);
-            self.set_property("loaded", 0u32);
-            self_
-                .refresh_progress
-                .set_tooltip_text(Some("Loading from Epic Store"));
             let mut eg = win_.model.borrow().epic_games.borrow().clone();
             let sender = win_.model.borrow().sender.clone();
-            // Start loading assets from the API
             self_.asset_load_pool.execute(move || {
-                let mut assets = tokio::runtime::Runtime::new()
+                if let Ok(w) = crate::RUNNING.read() {
+                    if !*w {
+                        return;
+                    }
+                }
+                asset_cache::import_json_cache(&cache_path);
+                // Load assets from cache
+                let cached = asset_cache::cached_assets();
+                debug!("Loaded {} assets from cache", cached.len());
+                for asset in cached {
+                    sender
+                        .send(crate::ui::messages::Msg::StartAssetProcessing)
+                        .unwrap();
+                    sender
+                        .send(crate::ui::messages::Msg::ProcessAssetInfo(asset))
+                        .unwrap();
+                }
+                // Start loading assets from the API
+                let listing = tokio::runtime::Runtime::new()
                     .unwrap()
                     .block_on(eg.list_assets(None, None));
-                assets.sort_by(|a, b| {
-                    let contains_a = cached.contains(&a.catalog_item_id);
-                    let contains_b = cached.contains(&b.catalog_item_id);
-                    if contains_a && contains_b {
-                        std::cmp::Ordering::Equal
-                    } else if contains_a {
-                        std::cmp::Ordering::Greater
-                    } else if contains_b {
-                        std::cmp::Ordering::Less
-                    } else {
-                        std::cmp::Ordering::Equal
-                    }
-                });
+                // Assets missing from the cache go first
+                let cached_ids = asset_cache::cached_ids();
+                let mut assets = listing;
+                assets.sort_by_key(|a| cached_ids.contains(&a.catalog_item_id));
                 for asset in assets {
                     sender
                         .send(crate::ui::messages::Msg::StartAssetProcessing)
@@ -848,25 +933,8 @@ impl EpicLibraryBox {
         let self_ = self.imp();
         if let Some(window) = self.main_window() {
             let win_ = window.imp();
-            let mut cache_dir = PathBuf::from(self_.settings.string("cache-directory").to_string());
-            cache_dir.push(&epic_asset.catalog_item_id);
-            let mut cache_dir_c = cache_dir.clone();
-            let ea = epic_asset.clone();
-
-            // Write the Epic Asset file
-            self_.asset_load_pool.execute(move || {
-                cache_dir_c.push("epic_asset.json");
-                fs::create_dir_all(cache_dir_c.parent().unwrap()).unwrap();
-                if let Ok(mut asset_file) = File::create(cache_dir_c.as_path()) {
-                    asset_file
-                        .write_all(serde_json::to_string(&ea).unwrap().as_bytes().as_ref())
-                        .unwrap();
-                }
-            });
-
             let mut eg = win_.model.borrow().epic_games.borrow().clone();
             let sender = win_.model.borrow().sender.clone();
-            let mut cache_dir_c = cache_dir;
             let epic_asset = epic_asset.clone();
             self_.asset_load_pool.execute(move || {
                 if let Ok(w) = crate::RUNNING.read() {
@@ -878,13 +946,11 @@ impl EpicLibraryBox {
                     .unwrap()
                     .block_on(eg.asset_info(epic_asset.clone()))
                 {
-                    // TODO: Check with already added assets to see if it needs updating
-                    cache_dir_c.push("asset_info.json");
-                    fs::create_dir_all(cache_dir_c.parent().unwrap()).unwrap();
-                    if let Ok(mut asset_file) = File::create(cache_dir_c.as_path()) {
-                        asset_file
-                            .write_all(serde_json::to_string(&asset).unwrap().as_bytes().as_ref())
-                            .unwrap();
+                    // The listing is only cached with its info, so a failed request is retried
+                    if let Err(e) = asset_cache::store_epic_asset(&epic_asset).and_then(|_| {
+                        asset_cache::store_asset_info(&epic_asset.catalog_item_id, &asset)
+                    }) {
+                        error!("Unable to cache {}: {}", epic_asset.catalog_item_id, e);
                     }
                     sender
                         .send(crate::ui::messages::Msg::ProcessAssetInfo(asset))
@@ -899,7 +965,18 @@ impl EpicLibraryBox {
         if let Some(data) = self_.loaded_data.borrow().get(id) {
             data.refresh();
         }
+        // Assets added later load the changed data again
+        self_.user_data.replace(None);
         self.apply_filter();
+        // Sorting by rating has to pick up a changed rating
+        if self_
+            .select_order_by
+            .active_id()
+            .map_or(false, |by| by.eq("rating"))
+        {
+            self.order_changed();
+        }
+        self_.sidebar.update_saved_search_counts();
     }
 }
 
@@ -1,9 +1,11 @@
+use crate::models::saved_search;
+use crate::tools::query::Query;
 use crate::ui::widgets::logged_in::library::sidebar::categories::EpicSidebarCategories;
 use gtk4::glib::clone;
 use gtk4::subclass::prelude::*;
 use gtk4::{self, gio, prelude::*};
 use gtk4::{glib, CompositeTemplate};
-use gtk_macros::action;
+use gtk_macros::{action, get_action};
 use log::{error, warn};
 use std::thread;
 
@@ -48,6 +50,10 @@ pub mod imp {
         pub downloaded_switch: TemplateChild<gtk4::Switch>,
         #[template_child]
         pub favorites_switch: TemplateChild<gtk4::Switch>,
+        #[template_child]
+        pub saved_search_list: TemplateChild<gtk4::ListBox>,
+        pub saved_searches: RefCell<Vec<(String, String)>>,
+        pub saved_search_counts: RefCell<Vec<(Option<Query>, gtk4::Label)>>,
     }
 
     #[glib::object_subclass]
@@ -73,6 +79,9 @@ pub mod imp {
                 games_category: TemplateChild::default(),
                 downloaded_switch: TemplateChild::default(),
                 favorites_switch: TemplateChild::default(),
+                saved_search_list: TemplateChild::default(),
+                saved_searches: RefCell::new(Vec::new()),
+                saved_search_counts: RefCell::new(Vec::new()),
                 settings: gio::Settings::new(crate::config::APP_ID),
             }
         }
@@ -162,6 +171,14 @@ impl EpicSidebar {
         }
 
         self_.loggedin.set(loggedin.clone()).unwrap();
+        self.search_changed(loggedin.search());
+        loggedin.connect_notify_local(
+            Some("search"),
+            clone!(@weak self as sidebar => move |library, _| {
+                sidebar.search_changed(library.search());
+            }),
+        );
+        self.update_saved_search_counts();
         match self_.settings.string("default-category").as_str() {
             "all" => &self_.all_category,
             "games" => &self_.games_category,
@@ -189,6 +206,13 @@ impl EpicSidebar {
                 sidebar.open_marketplace();
             })
         );
+        action!(
+            self_.actions,
+            "save_search",
+            clone!(@weak self as sidebar => move |_, _| {
+                sidebar.save_search();
+            })
+        );
     }
 
     fn open_marketplace(&self) {
@@ -253,6 +277,13 @@ impl EpicSidebar {
                 sidebar.filter_changed();
             }));
 
+        self_.saved_search_list.connect_row_activated(
+            clone!(@weak self as sidebar => move |_, row| {
+                sidebar.open_saved_search(row.index());
+            }),
+        );
+        self.load_saved_searches();
+
         if self_.settings.boolean("sidebar-expanded") {
             self.expand();
         };
@@ -374,6 +405,124 @@ impl EpicSidebar {
         }
     }
 
+    /// Only searches that parse can be saved
+    fn search_changed(&self, search: Option<String>) {
+        let self_ = self.imp();
+        get_action!(self_.actions, @save_search).set_enabled(
+            search
+                .as_deref()
+                .map_or(false, |s| matches!(Query::parse(s), Ok(Some(_)))),
+        );
+    }
+
+    fn save_search(&self) {
+        let self_ = self.imp();
+        let Some(query) = self_.loggedin.get().and_then(|l| l.search()) else {
+            return;
+        };
+        let dialog = gtk4::MessageDialog::builder()
+            .modal(true)
+            .message_type(gtk4::MessageType::Question)
+            .text("Save Search")
+            .secondary_text(format!("Name to show {query} under in the sidebar"))
+            .build();
+        if let Some(w) = self_.window.get() {
+            dialog.set_transient_for(Some(w));
+        }
+        let entry = gtk4::Entry::builder()
+            .placeholder_text("Name")
+            .activates_default(true)
+            .build();
+        dialog.message_area().append(&entry);
+        dialog.add_button("Cancel", gtk4::ResponseType::Cancel);
+        dialog.add_button("Save", gtk4::ResponseType::Accept);
+        dialog.set_default_response(Some(gtk4::ResponseType::Accept));
+        dialog.connect_response(
+            clone!(@weak self as sidebar, @weak entry => move |dialog, response| {
+                dialog.close();
+                let name = entry.text().trim().to_string();
+                if response != gtk4::ResponseType::Accept || name.is_empty() {
+                    return;
+                }
+                saved_search::save_search(&name, &query);
+                sidebar.load_saved_searches();
+            }),
+        );
+        dialog.show();
+    }
+
+    /// Rebuild the saved searches list from the database
+    pub fn load_saved_searches(&self) {
+        let self_ = self.imp();
+        while let Some(row) = self_.saved_search_list.first_child() {
+            self_.saved_search_list.remove(&row);
+        }
+        let searches = saved_search::saved_searches();
+        let mut counts = Vec::new();
+        for (name, query) in &searches {
+            let count = gtk4::Label::new(None);
+            self_
+                .saved_search_list
+                .append(&self.saved_search_row(name, query, &count));
+            counts.push((Query::parse(query).ok().flatten(), count));
+        }
+        self_.saved_searches.replace(searches);
+        self_.saved_search_counts.replace(counts);
+        self.update_saved_search_counts();
+    }
+
+    fn saved_search_row(&self, name: &str, query: &str, count: &gtk4::Label) -> gtk4::Box {
+        let row = gtk4::Box::new(gtk4::Orientation::Horizontal, 12);
+        row.set_tooltip_text(Some(query));
+        let label = gtk4::Label::new(Some(name));
+        label.set_xalign(0.0);
+        label.set_hexpand(true);
+        label.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+        row.append(&label);
+        count.add_css_class("dim-label");
+        row.append(count);
+        let remove = gtk4::Button::from_icon_name("list-remove-symbolic");
+        remove.add_css_class("flat");
+        remove.set_tooltip_text(Some("Remove Saved Search"));
+        let name = name.to_string();
+        remove.connect_clicked(clone!(@weak self as sidebar => move |_| {
+            saved_search::remove_search(&name);
+            sidebar.load_saved_searches();
+        }));
+        row.append(&remove);
+        row
+    }
+
+    /// Count the library assets matching each saved search
+    pub fn update_saved_search_counts(&self) {
+        let self_ = self.imp();
+        let Some(library) = self_.loggedin.get() else {
+            return;
+        };
+        for (query, label) in self_.saved_search_counts.borrow().iter() {
+            label.set_label(
+                &query
+                    .as_ref()
+                    .map_or_else(String::new, |q| library.count_matches(q).to_string()),
+            );
+        }
+    }
+
+    /// Show everything matching the saved search
+    fn open_saved_search(&self, index: i32) {
+        let self_ = self.imp();
+        let Some((_, query)) = usize::try_from(index)
+            .ok()
+            .and_then(|i| self_.saved_searches.borrow().get(i).cloned())
+        else {
+            return;
+        };
+        if let Some(l) = self_.loggedin.get() {
+            self_.all_category.clicked();
+            l.set_search(&query);
+        }
+    }
+
     fn add_category_by_name(&self, part: &str, p: &str) -> EpicSidebarCategories {
         let self_ = self.imp();
         self.category_by_name(p).map_or_else(
@@ -6,6 +6,7 @@ use gtk4::{self, prelude::*};
 use gtk4::{glib, CompositeTemplate};
 
 pub mod engines;
+pub mod history;
 pub mod library;
 mod log_line;
 pub mod logs;
@@ -33,6 +34,8 @@ pub mod imp {
         #[template_child]
         pub projects: TemplateChild<crate::ui::widgets::logged_in::projects::EpicProjectsBox>,
         #[template_child]
+        pub history: TemplateChild<crate::ui::widgets::logged_in::history::EpicDownloadHistory>,
+        #[template_child]
         pub adwstack: TemplateChild<adw::ViewStack>,
         pub settings: gtk4::gio::Settings,
         stack: RefCell<Option<adw::ViewStack>>,
@@ -51,6 +54,7 @@ pub mod imp {
                 library: TemplateChild::default(),
                 engines: TemplateChild::default(),
                 projects: TemplateChild::default(),
+                history: TemplateChild::default(),
                 adwstack: TemplateChild::default(),
                 settings: gtk4::gio::Settings::new(crate::config::APP_ID),
                 stack: RefCell::new(None),
@@ -171,6 +175,7 @@ impl EpicLoggedInBox {
         self_.download_manager.set(dm.clone()).unwrap();
         self_.library.set_download_manager(dm);
         self_.engines.set_download_manager(dm);
+        self_.history.set_download_manager(dm);
     }
 
     pub fn update_docker(&self) {
@@ -233,6 +238,10 @@ impl EpicLoggedInBox {
                 "library" => self_.library.can_be_refreshed(),
                 "projects" => self_.projects.can_be_refreshed(),
                 "engines" => self_.engines.can_be_refreshed(),
+                "history" => {
+                    self_.history.load();
+                    self_.history.can_be_refreshed()
+                }
                 _ => return,
             }
         } else {
@@ -258,6 +267,7 @@ impl Refresh for EpicLoggedInBox {
                 "library" => self_.library.run_refresh(),
                 "projects" => self_.projects.run_refresh(),
                 "engines" => self_.engines.run_refresh(),
+                "history" => self_.history.run_refresh(),
                 _ => {}
             }
         }
@@ -1,6 +1,7 @@
 use crate::models::project_data::Uproject;
 use crate::schema::unreal_project_latest_engine;
 use crate::ui::widgets::button_cust::ButtonEpic;
+use crate::ui::widgets::download_manager::project_files::{self, AddedAsset};
 use crate::ui::widgets::logged_in::engines::UnrealEngine;
 use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
 use gtk4::glib::clone;
@@ -8,7 +9,7 @@ use gtk4::subclass::prelude::*;
 use gtk4::{self, gio, prelude::*, ComboBoxText};
 use gtk4::{glib, CompositeTemplate};
 use gtk_macros::{action, get_action};
-use log::debug;
+use log::{debug, error};
 use std::path::PathBuf;
 
 pub mod imp {
@@ -350,6 +351,104 @@ impl UnrealProjectDetails {
                 &gtk4::Label::new(Some(&project.engine_association)),
                 &self_.details_group,
             ));
+
+        // Assets copied into the project
+        if let Some(dir) = parent.to_str() {
+            let added = project_files::added_assets(dir);
+            if !added.is_empty() {
+                let assets_box = gtk4::Box::new(gtk4::Orientation::Vertical, 5);
+                for asset in added {
+                    assets_box.append(&self.added_asset_row(dir, &asset));
+                }
+                self_
+                    .details
+                    .append(&crate::window::EpicAssetManagerWindow::create_details_row(
+                        "Added Assets",
+                        &assets_box,
+                        &self_.details_group,
+                    ));
+            }
+        }
+    }
+
+    fn added_asset_row(&self, project: &str, asset: &AddedAsset) -> gtk4::Box {
+        let row = gtk4::Box::new(gtk4::Orientation::Horizontal, 5);
+        let label = gtk4::Label::new(Some(&format!(
+            "{} ({} files{})",
+            asset.label,
+            asset.files,
+            if asset.backups > 0 {
+                format!(", {} backups", asset.backups)
+            } else {
+                String::new()
+            }
+        )));
+        label.set_xalign(0.0);
+        label.set_hexpand(true);
+        label.set_wrap(true);
+        row.append(&label);
+        let button = gtk4::Button::with_icon_and_label("edit-undo-symbolic", "Remove from project");
+        button.set_tooltip_text(Some(
+            "Delete the files added by this asset and restore the files it replaced",
+        ));
+        let project = project.to_string();
+        let release = asset.release.clone();
+        let name = asset.label.clone();
+        button.connect_clicked(clone!(@weak self as details, @weak row => move |_| {
+            details.confirm_remove_from_project(&project, &release, &name, &row);
+        }));
+        row.append(&button);
+        row
+    }
+
+    fn confirm_remove_from_project(
+        &self,
+        project: &str,
+        release: &str,
+        name: &str,
+        row: &gtk4::Box,
+    ) {
+        let self_ = self.imp();
+        let dialog = gtk4::MessageDialog::builder()
+            .modal(true)
+            .message_type(gtk4::MessageType::Warning)
+            .text(format!("Remove {name} from the project?"))
+            .secondary_text(
+                "Files added by the asset are deleted and the replaced files are restored",
+            )
+            .build();
+        if let Some(w) = self_.window.get() {
+            dialog.set_transient_for(Some(w));
+        }
+        dialog.add_button("Cancel", gtk4::ResponseType::Cancel);
+        dialog.add_button("Remove", gtk4::ResponseType::Accept);
+        let project = project.to_string();
+        let release = release.to_string();
+        dialog.connect_response(
+            clone!(@weak self as details, @weak row => move |dialog, response| {
+                dialog.close();
+                if response != gtk4::ResponseType::Accept {
+                    return;
+                }
+                match project_files::remove_from_project(&project, &release) {
+                    Ok(count) => {
+                        debug!("Removed {} files of {} from {}", count, release, project);
+                        row.set_visible(false);
+                    }
+                    Err(e) => {
+                        error!("Unable to remove {} from {}: {}", release, project, e);
+                        if let Some(w) = details.imp().window.get() {
+                            w.add_notification(
+                                "removefromproject",
+                                &format!("Unable to remove the asset from the project: {e}"),
+                                gtk4::MessageType::Error,
+                            );
+                        }
+                    }
+                }
+            }),
+        );
+        dialog.show();
     }
 
     fn populate_engines(
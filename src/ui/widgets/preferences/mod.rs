@@ -1,5 +1,6 @@
 pub mod dir_row;
 
+use crate::ui::widgets::download_manager::layer_cache;
 use adw::prelude::PreferencesWindowExt;
 use gtk4::gio::{File, FileQueryInfoFlags, FileType, SettingsBindFlags};
 use gtk4::glib::clone;
@@ -7,7 +8,7 @@ use gtk4::{gio, glib, prelude::*, subclass::prelude::*, CompositeTemplate};
 use gtk_macros::action;
 use log::{debug, error};
 use once_cell::sync::OnceCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 pub mod imp {
     use super::*;
@@ -58,6 +59,28 @@ pub mod imp {
         pub log_level_selection: TemplateChild<gtk4::ComboBoxText>,
         #[template_child]
         pub default_category_selection: TemplateChild<gtk4::ComboBoxText>,
+        #[template_child]
+        pub download_speed_limit: TemplateChild<gtk4::SpinButton>,
+        #[template_child]
+        pub download_threads: TemplateChild<gtk4::SpinButton>,
+        #[template_child]
+        pub file_extraction_threads: TemplateChild<gtk4::SpinButton>,
+        #[template_child]
+        pub chunk_cache_size: TemplateChild<gtk4::SpinButton>,
+        #[template_child]
+        pub docker_layer_cache_size: TemplateChild<gtk4::SpinButton>,
+        #[template_child]
+        pub docker_layer_cache_row: TemplateChild<adw::ActionRow>,
+        #[template_child]
+        pub schedule_switch: TemplateChild<gtk4::Switch>,
+        #[template_child]
+        pub schedule_start: TemplateChild<gtk4::Entry>,
+        #[template_child]
+        pub schedule_end: TemplateChild<gtk4::Entry>,
+        #[template_child]
+        pub daytime_speed_limit: TemplateChild<gtk4::SpinButton>,
+        #[template_child]
+        pub post_download_hook: TemplateChild<gtk4::Entry>,
     }
 
     #[glib::object_subclass]
@@ -88,6 +111,17 @@ pub mod imp {
                 default_view_selection: TemplateChild::default(),
                 log_level_selection: TemplateChild::default(),
                 default_category_selection: TemplateChild::default(),
+                download_speed_limit: TemplateChild::default(),
+                download_threads: TemplateChild::default(),
+                file_extraction_threads: TemplateChild::default(),
+                chunk_cache_size: TemplateChild::default(),
+                docker_layer_cache_size: TemplateChild::default(),
+                docker_layer_cache_row: TemplateChild::default(),
+                schedule_switch: TemplateChild::default(),
+                schedule_start: TemplateChild::default(),
+                schedule_end: TemplateChild::default(),
+                daytime_speed_limit: TemplateChild::default(),
+                post_download_hook: TemplateChild::default(),
             }
         }
 
@@ -187,6 +221,76 @@ impl PreferencesWindow {
             .flags(SettingsBindFlags::DEFAULT)
             .build();
 
+        self_
+            .settings
+            .bind(
+                "download-speed-limit",
+                &*self_.download_speed_limit,
+                "value",
+            )
+            .flags(SettingsBindFlags::DEFAULT)
+            .build();
+        self_
+            .settings
+            .bind("download-threads", &*self_.download_threads, "value")
+            .flags(SettingsBindFlags::DEFAULT)
+            .build();
+        self_
+            .settings
+            .bind(
+                "file-extraction-threads",
+                &*self_.file_extraction_threads,
+                "value",
+            )
+            .flags(SettingsBindFlags::DEFAULT)
+            .build();
+        self_
+            .settings
+            .bind("chunk-cache-size", &*self_.chunk_cache_size, "value")
+            .flags(SettingsBindFlags::DEFAULT)
+            .build();
+        self_
+            .settings
+            .bind(
+                "docker-layer-cache-size",
+                &*self_.docker_layer_cache_size,
+                "value",
+            )
+            .flags(SettingsBindFlags::DEFAULT)
+            .build();
+        self_
+            .settings
+            .bind(
+                "download-schedule-enabled",
+                &*self_.schedule_switch,
+                "active",
+            )
+            .build();
+        self_
+            .settings
+            .bind("download-schedule-start", &*self_.schedule_start, "text")
+            .flags(SettingsBindFlags::DEFAULT)
+            .build();
+        self_
+            .settings
+            .bind("download-schedule-end", &*self_.schedule_end, "text")
+            .flags(SettingsBindFlags::DEFAULT)
+            .build();
+        self_
+            .settings
+            .bind(
+                "download-daytime-speed-limit",
+                &*self_.daytime_speed_limit,
+                "value",
+            )
+            .flags(SettingsBindFlags::DEFAULT)
+            .build();
+        self_
+            .settings
+            .bind("post-download-hook", &*self_.post_download_hook, "text")
+            .flags(SettingsBindFlags::DEFAULT)
+            .build();
+
         self_
             .settings
             .bind("github-user", &*self_.github_user, "text")
@@ -308,6 +412,8 @@ impl PreferencesWindow {
             );
         }
 
+        self.show_docker_layer_cache_usage();
+
         let view = self_.settings.string("default-view");
         self_.default_view_selection.set_active_id(Some(&view));
         let level = self_.settings.int("log-level");
@@ -481,6 +587,13 @@ impl PreferencesWindow {
 
             })
         );
+        action!(
+            actions,
+            "clear_docker_layers",
+            clone!(@weak self as preferences => move |_, _| {
+                preferences.clear_docker_layers();
+            })
+        );
         action!(
             actions,
             "add_vault",
@@ -504,6 +617,43 @@ impl PreferencesWindow {
         );
     }
 
+    fn show_docker_layer_cache_usage(&self) {
+        let self_ = self.imp();
+        let (layers, size) = layer_cache::cache_usage();
+        self_.docker_layer_cache_row.set_subtitle(&format!(
+            "{} layers using {}",
+            layers,
+            byte_unit::Byte::from_bytes(u128::from(size))
+                .get_appropriate_unit(false)
+                .format(1)
+        ));
+    }
+
+    fn clear_docker_layers(&self) {
+        let self_ = self.imp();
+        let Some(dir) = layer_cache::cache_directory(&self_.settings) else {
+            return;
+        };
+        // Layers of running downloads are still needed
+        let keep: HashSet<String> = self_
+            .window
+            .get()
+            .map(|w| {
+                w.imp()
+                    .download_manager
+                    .imp()
+                    .docker_digests
+                    .borrow()
+                    .values()
+                    .flat_map(|digests| digests.iter().map(|(d, _)| d.clone()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let (removed, freed) = layer_cache::evict(&dir, 0, &keep);
+        debug!("Cleared {} docker layers, freed {} bytes", removed, freed);
+        self.show_docker_layer_cache_usage();
+    }
+
     fn set_directory(&self, dir: &File, kind: DirectoryConfigType) {
         let self_ = self.imp();
         match dir.query_file_type(FileQueryInfoFlags::NONE, gtk4::gio::Cancellable::NONE) {
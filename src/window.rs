@@ -1,10 +1,10 @@
 use crate::application::EpicAssetManager;
 use crate::config::{APP_ID, PROFILE};
 use crate::ui::update::Update;
+use crate::ui::widgets::download_manager::notifications::Notifications;
 use crate::ui::widgets::logged_in::refresh::Refresh;
 use crate::ui::widgets::progress_icon::ProgressIconExt;
 use crate::ui::PreferencesWindow;
-use chrono::TimeZone;
 use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
 use glib::clone;
 use glib::signal::Inhibit;
@@ -12,8 +12,7 @@ use gtk4::subclass::prelude::*;
 use gtk4::{self, prelude::*, ListBoxRow};
 use gtk4::{gio, glib, CompositeTemplate};
 use gtk_macros::{action, get_action};
-use log::{debug, error, warn};
-use std::collections::HashMap;
+use log::warn;
 use std::ops::Deref;
 
 pub mod imp {
@@ -290,6 +289,7 @@ impl EpicAssetManagerWindow {
             clone!(@weak self as window => @default-return None, move |_| {
                 let self_ = window.imp();
                 self_.progress_icon.set_fraction(self_.download_manager.progress());
+                self_.download_manager.update_launcher_entry();
                 None}),
         );
     }
@@ -487,106 +487,21 @@ impl EpicAssetManagerWindow {
         expiration: Option<chrono::DateTime<chrono::Utc>>,
     ) {
         let self_ = self.imp();
-        let mut attributes = HashMap::new();
-        attributes.insert("application", crate::config::APP_ID);
-        attributes.insert("type", secret_type);
-        let d = expiration.map_or_else(
-            || {
-                chrono::Utc
-                    .timestamp_opt(0, 0)
-                    .unwrap()
-                    .to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
-            },
-            |e| e.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
-        );
-        self_
-            .model
-            .borrow()
-            .settings
-            .set_string(expiration_name, d.as_str())
-            .unwrap();
-
-        #[cfg(any(target_os = "linux", target_os = "macos"))]
-        {
-            debug!("Saving {} secret", secret_name);
-            match &self_.model.borrow().secret_service {
-                None => {
-                    self.add_notification("ss_none_auth", "org.freedesktop.Secret.Service not available for use, secrets stored insecurely!", gtk4::MessageType::Warning);
-                    self.save_insecure(secret_name, secret);
-                }
-                Some(ss) => {
-                    // Clear the insecure storage if any
-                    self_
-                        .model
-                        .borrow()
-                        .settings
-                        .set_string(
-                            match secret_name {
-                                "eam_epic_games_token" => "token",
-                                "eam_epic_games_refresh_token" => "refresh-token",
-                                _ => {
-                                    return;
-                                }
-                            },
-                            "",
-                        )
-                        .unwrap();
-                    match secret {
-                        None => {
-                            if let Err(e) = ss.get_any_collection().unwrap().create_item(
-                                secret_name,
-                                attributes,
-                                b"",
-                                true,
-                                "text/plain",
-                            ) {
-                                error!("Failed to save secret {}", e);
-                                self.add_notification("ss_none_auth", "org.freedesktop.Secret.Service not available for use, secrets stored insecurely!", gtk4::MessageType::Warning);
-                                self.save_insecure(secret_name, secret);
-                            }
-                        }
-                        Some(rt) => {
-                            if let Err(e) = ss.get_any_collection().unwrap().create_item(
-                                secret_name,
-                                attributes,
-                                rt.as_bytes(),
-                                true,
-                                "text/plain",
-                            ) {
-                                error!("Failed to save secret {}", e);
-                                self.add_notification("ss_none_auth", "org.freedesktop.Secret.Service not available for use, secrets stored insecurely!", gtk4::MessageType::Warning);
-                                self.save_insecure(secret_name, Some(rt));
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        #[cfg(target_os = "windows")]
-        {
-            self.save_insecure(secret_name, secret);
+        if !self_.model.borrow().save_secret(
+            secret_type,
+            secret_name,
+            secret,
+            expiration_name,
+            expiration,
+        ) {
+            self.add_notification(
+                "ss_none_auth",
+                "org.freedesktop.Secret.Service not available for use, secrets stored insecurely!",
+                gtk4::MessageType::Warning,
+            );
         }
     }
 
-    fn save_insecure(&self, secret_name: &str, secret: Option<String>) {
-        let self_ = self.imp();
-        self_
-            .model
-            .borrow()
-            .settings
-            .set_string(
-                match secret_name {
-                    "eam_epic_games_token" => "token",
-                    "eam_epic_games_refresh_token" => "refresh-token",
-                    _ => {
-                        return;
-                    }
-                },
-                &secret.unwrap_or_default(),
-            )
-            .unwrap();
-    }
-
     pub fn close_download_manager(&self) {
         let self_ = self.imp();
         self_.progress_button.popdown();